@@ -1,20 +1,34 @@
 use std::path::Path;
 
-use anyhow::{Error, Result};
+use anyhow::{bail, Error, Result};
 use console::Style;
 use dialoguer::Confirm;
 use log::{error, info};
 
+use crate::core::consts::LOCK_FILE;
+use crate::core::lockfile::{LockedPackage, Lockfile};
 use crate::core::package::Package;
 use crate::core::packages_manager::PackagesManager;
 use crate::core::utils::packages::extract_package_name_and_version;
 use crate::core::utils::repository::find_cocmd_files;
+use crate::core::utils::version::version_matches;
 use crate::package_provider::get_provider;
 
 pub fn install_package(
     packages_manager: &mut PackagesManager,
     package: &str,
     dont_ask: bool,
+) -> Result<(), Error> {
+    install_package_and_requirements(packages_manager, package, dont_ask, &mut vec![])
+}
+
+// `installing` holds the (name, uri) of the packages whose requirements are being installed,
+// a requirement that is already there is a cycle
+fn install_package_and_requirements(
+    packages_manager: &mut PackagesManager,
+    package: &str,
+    dont_ask: bool,
+    installing: &mut Vec<(String, String)>,
 ) -> Result<(), Error> {
     info!("Installing package {:?}", package);
 
@@ -22,7 +36,10 @@ pub fn install_package(
 
     let (package_uri, version) = extract_package_name_and_version(package);
 
-    let provider = get_provider(&package_uri.to_string(), &settings.runtime_dir, version).unwrap();
+    let provider = get_provider(&package_uri, settings, version).map_err(|e| {
+        error!("Error: unable to install package '{}': {:#}", package, e);
+        e
+    })?;
 
     if !provider.is_exists_locally() {
         info!("Package not found locally. Downloading...");
@@ -85,8 +102,20 @@ pub fn install_package(
                 &packages_manager.settings,
             );
 
+            // Package::new already logged why (e.g. the package needs a newer cocmd)
+            if package.cocmd_config.is_none() {
+                bail!("unable to load the cocmd package in {}", loc);
+            }
+
+            // requirements first, so the package is never installed without them
+            install_requirements(packages_manager, &package, installing)?;
+
             let uri = package.uri.clone();
             packages_manager.add_package(package.clone());
+            if let Err(e) = packages_manager.lock_package(&package, provider.as_ref()) {
+                error!("unable to update {}: {:#}", LOCK_FILE, e);
+                return Err(e);
+            }
             info!("Package '{}' was installed:", uri);
             info!("- ✅ {} aliases available now", package.get_aliases_count());
             info!(
@@ -108,3 +137,231 @@ pub fn install_package(
 
     Ok(())
 }
+
+// install exactly the packages in the lock file, every requirement is locked too
+pub fn install_locked(packages_manager: &mut PackagesManager, lock_file: &Path) -> Result<()> {
+    let lockfile = Lockfile::load(lock_file)?;
+    if lockfile.packages.is_empty() {
+        bail!("no packages are locked in {}", lock_file.display());
+    }
+
+    let mut failed = vec![];
+    for locked in &lockfile.packages {
+        info!("Installing locked package {:?}", locked.name);
+        if let Err(e) = install_locked_package(packages_manager, locked) {
+            error!(
+                "unable to install locked package '{}': {:#}",
+                locked.name, e
+            );
+            failed.push(locked.name.clone());
+        }
+    }
+    if !failed.is_empty() {
+        bail!("unable to install locked packages: {}", failed.join(", "));
+    }
+
+    // another lock file becomes the lock of this home
+    if lock_file != packages_manager.settings.lock_file {
+        lockfile.save(&packages_manager.settings.lock_file)?;
+    }
+    Ok(())
+}
+
+fn install_locked_package(
+    packages_manager: &mut PackagesManager,
+    locked: &LockedPackage,
+) -> Result<()> {
+    let provider = get_provider(
+        &locked.uri,
+        &packages_manager.settings,
+        locked.version.clone(),
+    )?;
+    if provider.name() != locked.provider {
+        bail!(
+            "{} is locked from {}, but it resolves to {} now",
+            locked.uri,
+            locked.provider,
+            provider.name()
+        );
+    }
+
+    let path = provider.download_locked(locked)?;
+    let package = Package::new(locked.uri.clone(), &path, &packages_manager.settings);
+    if package.cocmd_config.is_none() {
+        bail!("unable to load the cocmd package in {}", path.display());
+    }
+    if package.name() != locked.name {
+        bail!(
+            "{} is the package '{}', not '{}'",
+            locked.uri,
+            package.name(),
+            locked.name
+        );
+    }
+
+    packages_manager.add_package(package);
+    info!("- ✅ {} {}", locked.name, locked_revision(locked));
+    Ok(())
+}
+
+fn locked_revision(locked: &LockedPackage) -> String {
+    match (&locked.version, &locked.commit) {
+        (Some(version), _) => version.clone(),
+        (None, Some(commit)) => commit.chars().take(12).collect(),
+        (None, None) => locked.uri.clone(),
+    }
+}
+
+fn install_requirements(
+    packages_manager: &mut PackagesManager,
+    package: &Package,
+    installing: &mut Vec<(String, String)>,
+) -> Result<(), Error> {
+    let requirements = package.requirements();
+    if requirements.is_empty() {
+        return Ok(());
+    }
+
+    installing.push((package.name().to_string(), package.uri.clone()));
+    for (uri, version) in requirements {
+        let spec = match &version {
+            Some(version) => format!("{}@{}", uri, version),
+            None => uri.clone(),
+        };
+
+        if let Some(pos) = installing
+            .iter()
+            .position(|(name, installing_uri)| name == &uri || installing_uri == &uri)
+        {
+            let mut cycle: Vec<String> = installing[pos..]
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect();
+            cycle.push(installing[pos].0.clone());
+            error!("dependency cycle detected: {}", cycle.join(" -> "));
+            bail!("dependency cycle detected: {}", cycle.join(" -> "));
+        }
+
+        if let Some(installed) = packages_manager.get_package(uri.clone()) {
+            if version
+                .as_ref()
+                .is_none_or(|version| version_matches(version, &installed.version()))
+            {
+                info!(
+                    "- ✅ {} (required by {}) is already installed",
+                    spec,
+                    package.name()
+                );
+                continue;
+            }
+        }
+
+        info!("Installing {} (required by {})", spec, package.name());
+        if let Err(e) = install_package_and_requirements(packages_manager, &spec, true, installing)
+        {
+            error!("unable to install {}, required by {}", spec, package.name());
+            return Err(e);
+        }
+    }
+    installing.pop();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::Settings;
+
+    fn write_package(root: &Path, name: &str, requires: &[&str]) {
+        let dir = root.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        let requires: Vec<String> = requires
+            .iter()
+            .map(|r| root.join(r).to_string_lossy().to_string())
+            .collect();
+        fs::write(
+            dir.join("cocmd.yaml"),
+            format!("name: {}\nrequires: {:?}\n", name, requires),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_install_requirements() {
+        let root = TempDir::new("cocmd").unwrap();
+        let home = root.path().join("home");
+        write_package(root.path(), "a", &["b", "c"]);
+        write_package(root.path(), "b", &["c"]);
+        write_package(root.path(), "c", &[]);
+
+        let mut packages_manager = PackagesManager::new(Settings::new(home.to_str(), None));
+        let a = root.path().join("a").to_string_lossy().to_string();
+        assert!(install_package(&mut packages_manager, &a, true).is_ok());
+        for name in ["a", "b", "c"] {
+            assert!(packages_manager.get_package(name.to_string()).is_some());
+        }
+    }
+
+    #[test]
+    fn test_install_requirements_cycle() {
+        let root = TempDir::new("cocmd").unwrap();
+        let home = root.path().join("home");
+        write_package(root.path(), "a", &["b"]);
+        write_package(root.path(), "b", &["a"]);
+
+        let mut packages_manager = PackagesManager::new(Settings::new(home.to_str(), None));
+        let a = root.path().join("a").to_string_lossy().to_string();
+        let err = install_package(&mut packages_manager, &a, true).unwrap_err();
+        assert_eq!(err.to_string(), "dependency cycle detected: a -> b -> a");
+        assert!(packages_manager.packages.is_empty());
+    }
+
+    #[test]
+    fn test_install_locked() {
+        let root = TempDir::new("cocmd").unwrap();
+        let home = root.path().join("home");
+        write_package(root.path(), "a", &["b"]);
+        write_package(root.path(), "b", &[]);
+
+        let mut packages_manager = PackagesManager::new(Settings::new(home.to_str(), None));
+        let a = root.path().join("a").to_string_lossy().to_string();
+        install_package(&mut packages_manager, &a, true).unwrap();
+        let lock_file = packages_manager.settings.lock_file.clone();
+        let lockfile = Lockfile::load(&lock_file).unwrap();
+        let names: Vec<&str> = lockfile.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "b"]);
+        assert_eq!(lockfile.packages[0].uri, a);
+        assert_eq!(lockfile.packages[0].provider, "local");
+
+        // the same set on another machine
+        let other_home = root.path().join("other");
+        let mut other = PackagesManager::new(Settings::new(other_home.to_str(), None));
+        install_locked(&mut other, &lock_file).unwrap();
+        assert!(other.get_package("a".to_string()).is_some());
+        assert!(other.get_package("b".to_string()).is_some());
+        assert_eq!(Lockfile::load(&other.settings.lock_file).unwrap(), lockfile);
+
+        packages_manager.remove_package("b").unwrap();
+        assert_eq!(Lockfile::load(&lock_file).unwrap().packages.len(), 1);
+
+        fs::remove_dir_all(root.path().join("a")).unwrap();
+        let err = install_locked(&mut other, &lock_file).unwrap_err();
+        assert_eq!(err.to_string(), "unable to install locked packages: a");
+    }
+
+    #[test]
+    fn test_install_from_unknown_registry() {
+        let root = TempDir::new("cocmd").unwrap();
+        let home = root.path().join("home");
+        let mut packages_manager = PackagesManager::new(Settings::new(home.to_str(), None));
+
+        let err = install_package(&mut packages_manager, "nope:docker", true).unwrap_err();
+        assert_eq!(err.to_string(), "registry 'nope' is not configured");
+        assert!(packages_manager.packages.is_empty());
+    }
+}
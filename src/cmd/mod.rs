@@ -1,7 +1,12 @@
 pub mod add;
 pub mod docs;
+pub mod outdated;
+pub mod params;
 pub mod profile_loader;
+pub mod refresh;
 pub mod run;
+pub mod schema;
 pub mod setup;
 pub mod show;
 pub mod uninstall;
+pub mod validate;
@@ -0,0 +1,188 @@
+use anyhow::{bail, Result};
+use log::error;
+use serde_derive::Serialize;
+
+use crate::core::packages_manager::PackagesManager;
+use crate::output::print_md;
+use crate::package_provider::{get_provider, UpdateCheck};
+
+#[derive(Serialize, Debug)]
+struct OutdatedPackage {
+    name: String,
+    provider: String,
+    #[serde(flatten)]
+    check: UpdateCheck,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+// the installed and the latest revision of every installed package, as a table or as json.
+// latest stays within the version a package was installed with
+pub fn run_outdated(packages_manager: &PackagesManager, json: bool) -> Result<()> {
+    let mut packages: Vec<_> = packages_manager.packages.values().collect();
+    packages.sort_by(|a, b| a.name().cmp(b.name()));
+
+    // packages.txt lines that don't resolve to a package at all
+    let mut report: Vec<OutdatedPackage> = packages_manager
+        .unresolved
+        .iter()
+        .map(|(uri, e)| OutdatedPackage {
+            name: uri.clone(),
+            provider: "-".to_string(),
+            check: UpdateCheck::default(),
+            error: Some(e.clone()),
+        })
+        .collect();
+    for package in packages {
+        let constraint = packages_manager.locked_constraint(package.name());
+        let provider = get_provider(&package.uri, &packages_manager.settings, constraint);
+        let provider_name = provider
+            .as_ref()
+            .map_or_else(|_| "-".to_string(), |provider| provider.name());
+        let (check, error) = match provider.and_then(|provider| provider.check_update()) {
+            Ok(check) => (check, None),
+            Err(e) => {
+                error!("unable to check {} for updates: {:#}", package.name(), e);
+                (UpdateCheck::default(), Some(format!("{:#}", e)))
+            }
+        };
+        report.push(OutdatedPackage {
+            name: package.name().to_string(),
+            provider: provider_name,
+            check: UpdateCheck {
+                installed: check.installed.or_else(|| Some(package.version())),
+                ..check
+            },
+            error,
+        });
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else if report.is_empty() {
+        print_md("No packages yet");
+    } else {
+        let mut table = String::from("| Package | Installed | Latest | Provider | Status |\n");
+        table.push_str("|---------|-----------|--------|----------|--------|\n");
+        for package in &report {
+            let status = match (&package.error, &package.check) {
+                (Some(_), _) => "check failed",
+                (None, UpdateCheck { latest: None, .. }) => "-",
+                (None, UpdateCheck { outdated: true, .. }) => "outdated",
+                (None, _) => "up to date",
+            };
+            let status = match &package.check.newest {
+                Some(newest) if package.error.is_none() => {
+                    format!("{} ({} is out of range)", status, newest)
+                }
+                _ => status.to_string(),
+            };
+            table.push_str(&format!(
+                "| {} | {} | {} | {} | {} |\n",
+                package.name,
+                package.check.installed.as_deref().unwrap_or("n/a"),
+                package.check.latest.as_deref().unwrap_or("n/a"),
+                package.provider,
+                status
+            ));
+        }
+        print_md(&table);
+    }
+
+    let failed: Vec<&str> = report
+        .iter()
+        .filter(|package| package.error.is_some())
+        .map(|package| package.name.as_str())
+        .collect();
+    if !failed.is_empty() {
+        bail!("unable to check for updates: {}", failed.join(", "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::cmd::add::install_package;
+    use crate::core::consts;
+    use crate::package_provider::hub::tests::write_registry;
+    use crate::Settings;
+
+    #[test]
+    fn test_outdated_local_packages() {
+        let root = TempDir::new("cocmd").unwrap();
+        let home = root.path().join("home");
+        let package_dir = root.path().join("demo");
+        fs::create_dir_all(&package_dir).unwrap();
+        fs::write(
+            package_dir.join("cocmd.yaml"),
+            "name: demo\nversion: 1.0.0\n",
+        )
+        .unwrap();
+
+        let mut packages_manager = PackagesManager::new(Settings::new(home.to_str(), None));
+        install_package(&mut packages_manager, package_dir.to_str().unwrap(), true).unwrap();
+
+        let provider = get_provider(
+            &package_dir.to_string_lossy().to_string(),
+            &packages_manager.settings,
+            None,
+        )
+        .unwrap();
+        let check = provider.check_update().unwrap();
+        assert_eq!(check.latest, None);
+        assert!(!check.outdated);
+        assert!(run_outdated(&packages_manager, true).is_ok());
+        assert!(run_outdated(&packages_manager, false).is_ok());
+    }
+
+    #[test]
+    fn test_outdated_with_a_broken_package() {
+        let root = TempDir::new("cocmd").unwrap();
+        let acme = root.path().join("acme");
+        write_registry(&acme, &[("docker", "1.0.0")]);
+        let home = root.path().join("home");
+        fs::create_dir_all(&home).unwrap();
+        let config = home.join(consts::CONFIG_FILE);
+        fs::write(
+            &config,
+            format!(
+                "registries:\n  - name: cocmd\n    url: file://{}\n  - name: acme\n    url: file://{}\n",
+                acme.display(),
+                acme.display()
+            ),
+        )
+        .unwrap();
+        let package_dir = root.path().join("demo");
+        fs::create_dir_all(&package_dir).unwrap();
+        fs::write(
+            package_dir.join("cocmd.yaml"),
+            "name: demo\nversion: 1.0.0\n",
+        )
+        .unwrap();
+
+        let mut packages_manager = PackagesManager::new(Settings::new(home.to_str(), None));
+        install_package(&mut packages_manager, "acme:docker", true).unwrap();
+        install_package(&mut packages_manager, package_dir.to_str().unwrap(), true).unwrap();
+        drop(packages_manager);
+
+        // acme is gone, only its package fails the check
+        fs::write(
+            &config,
+            format!(
+                "registries:\n  - name: cocmd\n    url: file://{}\n",
+                acme.display()
+            ),
+        )
+        .unwrap();
+        let packages_manager = PackagesManager::new(Settings::new(home.to_str(), None));
+        for json in [true, false] {
+            let err = run_outdated(&packages_manager, json).unwrap_err();
+            assert_eq!(err.to_string(), "unable to check for updates: acme:docker");
+        }
+    }
+}
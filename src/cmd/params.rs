@@ -0,0 +1,214 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+use dialoguer::{Input, Password};
+use log::{error, info};
+
+use crate::core::packages_manager::PackagesManager;
+use crate::output::print_md;
+
+fn scope_name(package: Option<&str>) -> String {
+    match package {
+        Some(package) => format!("package {}", package),
+        None => "global".to_string(),
+    }
+}
+
+// the saved params, with the scope they come from. with a package, only what that package
+// sees (its own params, and the global ones it doesn't override)
+pub fn list_params(packages_manager: &mut PackagesManager, package: Option<String>) -> Result<()> {
+    let settings = &mut packages_manager.settings;
+    let package = package.as_deref();
+
+    // (scope, name, value)
+    let mut rows: Vec<(Option<String>, String, String)> = vec![];
+    for (name, value) in &settings.params {
+        rows.push((None, name.clone(), value.clone()));
+    }
+    for (scope, params) in &settings.package_params {
+        for (name, value) in params {
+            rows.push((Some(scope.clone()), name.clone(), value.clone()));
+        }
+    }
+    // secret values are never shown, listing them needs the vault unlocked
+    match settings.vault.names() {
+        Ok(names) => {
+            for (scope, name) in names {
+                rows.push((scope, name, "••••••".to_string()));
+            }
+        }
+        Err(e) => error!("secret params are not listed: {}", e),
+    }
+
+    if let Some(package) = package {
+        let overridden: HashSet<String> = rows
+            .iter()
+            .filter(|(scope, _, _)| scope.as_deref() == Some(package))
+            .map(|(_, name, _)| name.clone())
+            .collect();
+        rows.retain(|(scope, name, _)| match scope {
+            Some(scope) => scope == package,
+            None => !overridden.contains(name),
+        });
+    }
+    rows.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+
+    if rows.is_empty() {
+        print_md("No saved params yet");
+        return Ok(());
+    }
+    let mut table = String::new();
+    table.push_str("| Scope | Param | Value |\n");
+    table.push_str("|-------|-------|-------|\n");
+    for (scope, name, value) in rows {
+        table.push_str(&format!(
+            "| {} | {} | {} |\n",
+            scope_name(scope.as_deref()),
+            name,
+            value
+        ));
+    }
+    print_md(&table);
+    Ok(())
+}
+
+// prints the value a run of the package would use, for scripts
+pub fn get_param(
+    packages_manager: &mut PackagesManager,
+    name: &str,
+    package: Option<String>,
+) -> Result<()> {
+    let settings = &mut packages_manager.settings;
+    let package = package.as_deref();
+    let value = match settings.get_param(name, package) {
+        Some(value) => Some(value),
+        None => settings.vault.get(name, package)?,
+    };
+    match value {
+        Some(value) => {
+            println!("{}", value);
+            Ok(())
+        }
+        None => {
+            error!("'{}' is not saved for {}", name, scope_name(package));
+            bail!("param '{}' not found", name)
+        }
+    }
+}
+
+// without a value, it's asked for (masked for secrets, so it doesn't end up in the shell history)
+pub fn set_param(
+    packages_manager: &mut PackagesManager,
+    name: &str,
+    value: Option<String>,
+    package: Option<String>,
+    secret: bool,
+) -> Result<()> {
+    let settings = &mut packages_manager.settings;
+    let package = package.as_deref();
+    let value = match value {
+        Some(value) => value,
+        None if secret => Password::new()
+            .with_prompt(format!("Value for secret param '{}'", name))
+            .interact()?,
+        None => Input::<String>::new()
+            .with_prompt(format!("Value for param '{}'", name))
+            .interact_text()?,
+    };
+
+    if secret {
+        settings.vault.set(name, &value, package).map_err(|e| {
+            error!("{}", e);
+            e
+        })?;
+        // a secret never stays in the plain file
+        settings.unset_param(name, package);
+    } else {
+        settings.save_param(name, &value, package);
+    }
+    info!("'{}' saved for {}", name, scope_name(package));
+    Ok(())
+}
+
+pub fn unset_param(
+    packages_manager: &mut PackagesManager,
+    name: &str,
+    package: Option<String>,
+) -> Result<()> {
+    let settings = &mut packages_manager.settings;
+    let package = package.as_deref();
+    let removed =
+        settings.unset_param(name, package).is_some() || settings.vault.remove(name, package)?;
+    if !removed {
+        error!("'{}' is not saved for {}", name, scope_name(package));
+        bail!("param '{}' not found", name);
+    }
+    info!("'{}' removed from {}", name, scope_name(package));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::core::consts;
+    use crate::Settings;
+
+    #[test]
+    fn test_set_and_unset_param() {
+        let home = TempDir::new("cocmd").unwrap();
+        std::fs::write(home.path().join(consts::VAULT_KEY_FILE), "key").unwrap();
+        let mut packages_manager = PackagesManager::new(Settings::new(home.path().to_str(), None));
+
+        set_param(
+            &mut packages_manager,
+            "region",
+            Some("eu".into()),
+            None,
+            false,
+        )
+        .unwrap();
+        set_param(
+            &mut packages_manager,
+            "region",
+            Some("us".into()),
+            Some("demo".into()),
+            false,
+        )
+        .unwrap();
+        set_param(
+            &mut packages_manager,
+            "token",
+            Some("s3cr3t".into()),
+            Some("demo".into()),
+            true,
+        )
+        .unwrap();
+
+        let settings = &mut packages_manager.settings;
+        assert_eq!(
+            settings.get_param("region", Some("demo")),
+            Some("us".into())
+        );
+        assert_eq!(settings.get_param("region", None), Some("eu".into()));
+        assert_eq!(settings.get_param("token", Some("demo")), None);
+        assert_eq!(
+            settings.vault.get("token", Some("demo")).unwrap(),
+            Some("s3cr3t".into())
+        );
+
+        unset_param(&mut packages_manager, "region", Some("demo".into())).unwrap();
+        unset_param(&mut packages_manager, "token", Some("demo".into())).unwrap();
+        assert!(unset_param(&mut packages_manager, "token", Some("demo".into())).is_err());
+        assert!(get_param(&mut packages_manager, "missing", None).is_err());
+
+        let settings = &mut packages_manager.settings;
+        assert_eq!(
+            settings.get_param("region", Some("demo")),
+            Some("eu".into())
+        );
+        assert!(settings.package_params.is_empty());
+        assert_eq!(settings.vault.get("token", Some("demo")).unwrap(), None);
+    }
+}
@@ -0,0 +1,288 @@
+use anyhow::{bail, Result};
+use log::error;
+
+use crate::core::package::Package;
+use crate::core::packages_manager::PackagesManager;
+use crate::output::print_md;
+use crate::package_provider::get_provider;
+use crate::package_provider::hub::CocmdHubPackageProvider;
+
+// update the installed packages, all of them or the named ones, and print what changed
+pub fn run_refresh(
+    packages_manager: &mut PackagesManager,
+    names: &[String],
+    dry_run: bool,
+) -> Result<()> {
+    let mut packages: Vec<Package> = packages_manager.packages.values().cloned().collect();
+    packages.sort_by(|a, b| a.name().cmp(b.name()));
+    let mut unresolved = packages_manager.unresolved.clone();
+    for name in names {
+        if !packages.iter().any(|package| package.name() == name)
+            && !unresolved.iter().any(|(uri, _)| uri == name)
+        {
+            bail!("package '{}' is not installed", name);
+        }
+    }
+    packages.retain(|package| names.is_empty() || names.iter().any(|n| n == package.name()));
+    unresolved.retain(|(uri, _)| names.is_empty() || names.contains(uri));
+
+    let settings = &packages_manager.settings;
+    // hub packages are resolved against the index of today, not the cached one
+    let has_hub_packages = packages.iter().any(|package| {
+        get_provider(&package.uri, settings, None).is_ok_and(|p| p.is_provider_hub())
+    });
+    if has_hub_packages {
+        // without it every hub package reports its own failure below
+        if let Err(e) = CocmdHubPackageProvider::get_index(settings, true) {
+            error!("unable to fetch the package index: {:#}", e);
+        }
+    }
+
+    let mut table = String::from("| Package | Provider | Old | New | Status |\n");
+    table.push_str("|---------|----------|-----|-----|--------|\n");
+    let mut failed = vec![];
+    // packages.txt lines that don't resolve to a package at all
+    for (uri, e) in &unresolved {
+        table.push_str(&format!("| {} | - | - | - | failed: {} |\n", uri, e));
+        failed.push(uri.clone());
+    }
+    for package in &packages {
+        let constraint = packages_manager.locked_constraint(package.name());
+        // a package whose provider can't be built (a registry that's gone) is a failed row
+        let provider = get_provider(&package.uri, &packages_manager.settings, constraint);
+        let provider_name = provider
+            .as_ref()
+            .map_or_else(|_| "-".to_string(), |provider| provider.name());
+        let old = provider
+            .as_ref()
+            .ok()
+            .and_then(|provider| provider.installed_revision())
+            .unwrap_or_else(|| package.version());
+
+        let refreshed = provider.and_then(|_| refresh_package(packages_manager, package, dry_run));
+        let (new, status) = match refreshed {
+            Ok(new) => {
+                let status = if new == old {
+                    "up to date"
+                } else if dry_run {
+                    "would update"
+                } else {
+                    "updated"
+                };
+                (new, status.to_string())
+            }
+            Err(e) => {
+                error!("unable to refresh {}: {:#}", package.name(), e);
+                failed.push(package.name().to_string());
+                ("-".to_string(), format!("failed: {}", e))
+            }
+        };
+        table.push_str(&format!(
+            "| {} | {} | {} | {} | {} |\n",
+            package.name(),
+            provider_name,
+            old,
+            new,
+            status
+        ));
+    }
+
+    if packages.is_empty() && unresolved.is_empty() {
+        print_md("No packages yet");
+    } else {
+        print_md(&table);
+    }
+    if !failed.is_empty() {
+        bail!("unable to refresh: {}", failed.join(", "));
+    }
+    Ok(())
+}
+
+// the revision the package is at now, within the version it was installed with. a refreshed
+// package has to load and is locked again
+fn refresh_package(
+    packages_manager: &mut PackagesManager,
+    package: &Package,
+    dry_run: bool,
+) -> Result<String> {
+    let constraint = packages_manager.locked_constraint(package.name());
+    let provider = get_provider(&package.uri, &packages_manager.settings, constraint)?;
+    let revision = provider.refresh(dry_run)?;
+
+    let refreshed = Package::new(
+        package.uri.clone(),
+        &provider.local_path(),
+        &packages_manager.settings,
+    );
+    if refreshed.cocmd_config.is_none() {
+        bail!(
+            "unable to load the cocmd package in {}",
+            package.location().display()
+        );
+    }
+    let revision = revision.unwrap_or_else(|| refreshed.version());
+    if !dry_run {
+        packages_manager.add_package(refreshed.clone());
+        packages_manager.lock_package(&refreshed, provider.as_ref())?;
+    }
+    Ok(revision)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::cmd::add::install_package;
+    use crate::core::consts;
+    use crate::package_provider::hub::tests::write_registry;
+    use crate::Settings;
+
+    #[test]
+    fn test_refresh_local_packages() {
+        let root = TempDir::new("cocmd").unwrap();
+        let home = root.path().join("home");
+        let package_dir = root.path().join("demo");
+        fs::create_dir_all(&package_dir).unwrap();
+        fs::write(
+            package_dir.join("cocmd.yaml"),
+            "name: demo\nversion: 1.0.0\n",
+        )
+        .unwrap();
+
+        let mut packages_manager = PackagesManager::new(Settings::new(home.to_str(), None));
+        install_package(&mut packages_manager, package_dir.to_str().unwrap(), true).unwrap();
+
+        fs::write(
+            package_dir.join("cocmd.yaml"),
+            "name: demo\nversion: 1.1.0\n",
+        )
+        .unwrap();
+        assert!(run_refresh(&mut packages_manager, &[], true).is_ok());
+        assert!(run_refresh(&mut packages_manager, &["demo".to_string()], false).is_ok());
+        assert_eq!(
+            packages_manager
+                .get_package("demo".to_string())
+                .unwrap()
+                .version(),
+            "1.1.0"
+        );
+        assert!(run_refresh(&mut packages_manager, &["nope".to_string()], false).is_err());
+
+        fs::write(package_dir.join("cocmd.yaml"), "name: [\n").unwrap();
+        let err = run_refresh(&mut packages_manager, &[], false).unwrap_err();
+        assert_eq!(err.to_string(), "unable to refresh: demo");
+
+        fs::remove_dir_all(&package_dir).unwrap();
+        assert!(run_refresh(&mut packages_manager, &[], false).is_err());
+    }
+
+    #[test]
+    fn test_refresh_within_constraint() {
+        let root = TempDir::new("cocmd").unwrap();
+        let registry = root.path().join("registry");
+        write_registry(&registry, &[("docker", "1.0.0"), ("k8s", "1.0.0")]);
+        let home = root.path().join("home");
+        fs::create_dir_all(&home).unwrap();
+        fs::write(
+            home.join(consts::CONFIG_FILE),
+            format!(
+                "registries:\n  - name: cocmd\n    url: file://{}\n",
+                registry.display()
+            ),
+        )
+        .unwrap();
+
+        let mut packages_manager = PackagesManager::new(Settings::new(home.to_str(), None));
+        install_package(&mut packages_manager, "docker@1.0.0", true).unwrap();
+        install_package(&mut packages_manager, "k8s@^1", true).unwrap();
+
+        write_registry(
+            &registry,
+            &[
+                ("docker", "1.0.0"),
+                ("docker", "1.1.0"),
+                ("docker", "2.0.0"),
+                ("k8s", "1.0.0"),
+                ("k8s", "1.1.0"),
+                ("k8s", "2.0.0"),
+            ],
+        );
+        run_refresh(&mut packages_manager, &[], false).unwrap();
+
+        let version = |name: &str| {
+            packages_manager
+                .get_package(name.to_string())
+                .unwrap()
+                .version()
+        };
+        // a pinned package stays put, a range doesn't cross a major version
+        assert_eq!(version("docker"), "1.0.0");
+        assert_eq!(version("k8s"), "1.1.0");
+        assert_eq!(
+            packages_manager.locked_constraint("k8s").as_deref(),
+            Some("^1")
+        );
+    }
+
+    #[test]
+    fn test_refresh_with_a_broken_package() {
+        let root = TempDir::new("cocmd").unwrap();
+        let acme = root.path().join("acme");
+        write_registry(&acme, &[("docker", "1.0.0")]);
+        let home = root.path().join("home");
+        fs::create_dir_all(&home).unwrap();
+        let config = home.join(consts::CONFIG_FILE);
+        fs::write(
+            &config,
+            format!(
+                "registries:\n  - name: cocmd\n    url: file://{}\n  - name: acme\n    url: file://{}\n",
+                acme.display(),
+                acme.display()
+            ),
+        )
+        .unwrap();
+        let package_dir = root.path().join("demo");
+        fs::create_dir_all(&package_dir).unwrap();
+        fs::write(
+            package_dir.join("cocmd.yaml"),
+            "name: demo\nversion: 1.0.0\n",
+        )
+        .unwrap();
+
+        let mut packages_manager = PackagesManager::new(Settings::new(home.to_str(), None));
+        install_package(&mut packages_manager, "acme:docker", true).unwrap();
+        install_package(&mut packages_manager, package_dir.to_str().unwrap(), true).unwrap();
+        drop(packages_manager);
+
+        // acme is gone, the other packages are refreshed anyway
+        fs::write(
+            &config,
+            format!(
+                "registries:\n  - name: cocmd\n    url: file://{}\n",
+                acme.display()
+            ),
+        )
+        .unwrap();
+        fs::write(
+            package_dir.join("cocmd.yaml"),
+            "name: demo\nversion: 1.1.0\n",
+        )
+        .unwrap();
+        let mut packages_manager = PackagesManager::new(Settings::new(home.to_str(), None));
+        let err = run_refresh(&mut packages_manager, &[], false).unwrap_err();
+        assert_eq!(err.to_string(), "unable to refresh: acme:docker");
+        assert_eq!(
+            packages_manager
+                .get_package("demo".to_string())
+                .unwrap()
+                .version(),
+            "1.1.0"
+        );
+        // and it's still installed, for when acme is back
+        let packages_file = fs::read_to_string(&packages_manager.packages_file).unwrap();
+        assert!(packages_file.lines().any(|line| line == "acme:docker"));
+    }
+}
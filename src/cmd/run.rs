@@ -1,13 +1,18 @@
+use std::collections::HashMap;
 use std::process;
 
-use anyhow::{Error, Result};
+use anyhow::{bail, Context, Error, Result};
 use dialoguer::{theme::ColorfulTheme, Select};
+use itertools::Itertools;
 use log::error;
 
-use crate::core::packages_manager::PackagesManager;
+use crate::core::models::package_config_model::Automation;
+use crate::core::packages_manager::{PackagesManager, PROJECT_PREFIX};
 use crate::core::utils::cmd::parse_params;
+use crate::core::utils::graph::topological_order;
 use crate::core::utils::packages::get_package_name_from_uri;
-use crate::runner::{run_script, shell::interactive_shell};
+use crate::output::print_md_debug;
+use crate::runner::{check_params, run_script, shell::interactive_shell};
 
 pub fn run_automation(
     packages_manager: &mut PackagesManager,
@@ -35,8 +40,27 @@ pub fn run_automation(
 
     let selected_name = match specific_name {
         Some(name) => name,
+        None if packages_manager.settings.config.non_interactive => {
+            error!(
+                "name the automation to run, nothing can be selected when non_interactive is on"
+            );
+            return Err(Error::msg("No automation selected"));
+        }
         None => {
-            let script_choices: Vec<&String> = available_automations.keys().collect();
+            // the project's automations once, as `./name`
+            let project_prefix = packages_manager
+                .project
+                .as_ref()
+                .map(|project| format!("{}.", project.name()));
+            let script_choices: Vec<&String> = available_automations
+                .keys()
+                .filter(|key| {
+                    !project_prefix
+                        .as_ref()
+                        .is_some_and(|prefix| key.starts_with(prefix.as_str()))
+                })
+                .sorted()
+                .collect();
             let selected_script = Select::with_theme(&ColorfulTheme::default())
                 .with_prompt("What script to run?")
                 .items(&script_choices)
@@ -50,15 +74,74 @@ pub fn run_automation(
             script_choices[selected_script.unwrap()].to_string()
         }
     };
+    let requested_name = selected_name;
+    let selected_name = packages_manager.automation_key(&requested_name);
 
-    if let Some(automation) = available_automations.get(&selected_name) {
-        return run_script(
-            &selected_name,
-            automation.content.as_ref().unwrap(),
-            packages_manager.settings.os,
-            packages_manager,
-            parse_params(params),
-        );
+    if available_automations.contains_key(&selected_name) {
+        let plan = execution_plan(&selected_name, &available_automations).map_err(|e| {
+            error!("{}", e);
+            e
+        })?;
+        if plan.len() > 1 {
+            let steps: Vec<String> = plan
+                .iter()
+                .enumerate()
+                .map(|(i, name)| format!("{}. {}", i + 1, name))
+                .collect();
+            print_md_debug(&format!("## execution plan\n{}", steps.join("\n")));
+        }
+
+        // nothing runs when a param of any automation in the plan is wrong
+        let params = parse_params(params);
+        for name in &plan {
+            let automation = &available_automations[name];
+            check_params(
+                automation.content.as_ref().unwrap(),
+                packages_manager,
+                &params,
+                Some(package_name(name, automation)),
+            )
+            .with_context(|| format!("unable to run {}", name))?;
+        }
+
+        for name in &plan {
+            let automation = &available_automations[name];
+            let package = package_name(name, automation).to_string();
+            let package_dir = packages_manager
+                .get_package(package.clone())
+                .map(|package| package.location.clone());
+            let result = run_script(
+                name,
+                automation.content.as_ref().unwrap(),
+                packages_manager.settings.os,
+                packages_manager,
+                params.clone(),
+                Some(package),
+                package_dir,
+            );
+            if name != &selected_name {
+                result.with_context(|| format!("dependency {} failed", name))?;
+            } else {
+                result?;
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(name) = requested_name.strip_prefix(PROJECT_PREFIX) {
+        match &packages_manager.project {
+            Some(project) => error!(
+                "😲 {} ({}) has no automation {} for this os",
+                project.name(),
+                project.location.display(),
+                name
+            ),
+            None => error!(
+                "😲 {} needs a cocmd.yaml in this directory or above it",
+                requested_name
+            ),
+        }
+        return Err(Error::msg("I don't know this script"));
     }
 
     // return Err(Error("I don't know this script"));
@@ -69,3 +152,150 @@ pub fn run_automation(
     );
     Err(Error::msg("I don't know this script"))
 }
+
+// the package of a "package.automation" key
+fn package_name<'a>(key: &'a str, automation: &Automation) -> &'a str {
+    key.strip_suffix(&format!(".{}", automation.name))
+        .unwrap_or(key)
+}
+
+// the automations to run, dependencies first and each one once, ending with `name`
+fn execution_plan(name: &str, automations: &HashMap<String, Automation>) -> Result<Vec<String>> {
+    topological_order(&[name.to_string()], |key| {
+        let automation = &automations[key];
+        let dependencies = automation.dependencies(package_name(key, automation));
+        for dependency in &dependencies {
+            if !automations.contains_key(dependency) {
+                bail!(
+                    "{} depends on {}, which wasn't found. is its package installed and does it support this os?",
+                    key,
+                    dependency
+                );
+            }
+        }
+        Ok(dependencies)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn automations(yaml: &str) -> HashMap<String, Automation> {
+        let automations: Vec<Automation> = serde_yaml::from_str(yaml).unwrap();
+        automations
+            .into_iter()
+            .map(|a| (format!("onboarding.{}", a.name), a))
+            .collect()
+    }
+
+    #[test]
+    fn test_execution_plan() {
+        let automations = automations(
+            r#"
+            - name: env
+              depends_on: [clone]
+            - name: clone
+              depends_on: [git, node, onboarding.docker]
+            - name: node
+              depends_on: [git]
+            - name: git
+            - name: docker
+            "#,
+        );
+        assert_eq!(
+            execution_plan("onboarding.env", &automations).unwrap(),
+            vec![
+                "onboarding.git",
+                "onboarding.node",
+                "onboarding.docker",
+                "onboarding.clone",
+                "onboarding.env"
+            ]
+        );
+        assert_eq!(
+            execution_plan("onboarding.git", &automations).unwrap(),
+            vec!["onboarding.git"]
+        );
+    }
+
+    #[test]
+    fn test_execution_plan_errors() {
+        let automations = automations(
+            r#"
+            - name: a
+              depends_on: [b]
+            - name: b
+              depends_on: [a]
+            - name: c
+              depends_on: [other.missing]
+            "#,
+        );
+        assert!(execution_plan("onboarding.a", &automations)
+            .unwrap_err()
+            .to_string()
+            .contains("onboarding.a -> onboarding.b -> onboarding.a"));
+        assert!(execution_plan("onboarding.c", &automations)
+            .unwrap_err()
+            .to_string()
+            .contains("other.missing"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_params_checked_before_dependencies() {
+        use crate::cmd::add::install_package;
+        use crate::Settings;
+
+        let root = tempdir::TempDir::new("cocmd").unwrap();
+        let package_dir = root.path().join("demo");
+        let marker = root.path().join("setup-ran");
+        std::fs::create_dir_all(&package_dir).unwrap();
+        std::fs::write(
+            package_dir.join("cocmd.yaml"),
+            format!(
+                r#"
+name: demo
+automations:
+  - name: setup
+    content:
+      steps:
+        - title: setup
+          content: touch {}
+  - name: deploy
+    depends_on: [setup]
+    content:
+      params:
+        - name: region
+          type: enum
+          choices: [eu, us]
+      steps:
+        - title: deploy
+          content: echo {{{{ region }}}}
+"#,
+                marker.display()
+            ),
+        )
+        .unwrap();
+        let mut settings = Settings::new(root.path().join("home").to_str(), None);
+        settings.config.non_interactive = true;
+        let mut packages_manager = PackagesManager::new(settings);
+        install_package(&mut packages_manager, package_dir.to_str().unwrap(), true).unwrap();
+
+        let run = |packages_manager: &mut PackagesManager, params: &[&str]| {
+            run_automation(
+                packages_manager,
+                Some("demo.deploy".to_string()),
+                Some(params.iter().map(|p| p.to_string()).collect()),
+                None,
+            )
+        };
+        // a wrong value and a missing one are both refused before setup runs
+        assert!(run(&mut packages_manager, &["region=asia"]).is_err());
+        assert!(run(&mut packages_manager, &[]).is_err());
+        assert!(!marker.exists());
+
+        assert!(run(&mut packages_manager, &["region=eu"]).is_ok());
+        assert!(marker.exists());
+    }
+}
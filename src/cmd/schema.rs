@@ -0,0 +1,98 @@
+use anyhow::Result;
+use schemars::{schema_for, Schema};
+
+use crate::core::models::package_config_model::PackageConfigModel;
+
+// the json schema of cocmd.yaml, derived from the models so it accepts exactly what cocmd does
+pub fn package_schema() -> Schema {
+    let mut schema = schema_for!(PackageConfigModel);
+    schema.insert(
+        "title".to_string(),
+        "cocmd package (cocmd.yaml)".to_string().into(),
+    );
+    schema.insert(
+        "description".to_string(),
+        "automations, aliases and paths to share with your team. generated by `cocmd schema`"
+            .to_string()
+            .into(),
+    );
+    schema
+}
+
+pub fn print_schema() -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(&package_schema())?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::models::script_model::{ParamType, StepRunnerType};
+    use crate::core::utils::sys::OS;
+
+    #[test]
+    fn test_schema_is_up_to_date() {
+        // schemas/json/cocmd.json is what editors use, regenerate it with `cocmd schema`
+        let generated = serde_json::to_string_pretty(&package_schema()).unwrap();
+        let checked_in = include_str!("../../schemas/json/cocmd.json");
+        assert_eq!(checked_in.trim_end(), generated);
+    }
+
+    #[test]
+    fn test_schema_enums_are_accepted() {
+        // every name the schema allows has to deserialize
+        let schema = package_schema();
+        let defs = &schema.as_value()["$defs"];
+        let names = |def: &str| -> Vec<String> {
+            defs[def]["enum"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|name| name.as_str().unwrap().to_string())
+                .collect()
+        };
+
+        for name in names("StepRunnerType") {
+            assert!(
+                serde_yaml::from_str::<StepRunnerType>(&name).is_ok(),
+                "{}",
+                name
+            );
+        }
+        for name in names("ParamType") {
+            assert!(serde_yaml::from_str::<ParamType>(&name).is_ok(), "{}", name);
+        }
+        for name in names("OS") {
+            assert!(serde_yaml::from_str::<OS>(&name).is_ok(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_schema_enums_list_every_spelling() {
+        // every spelling that deserializes is in the schema, and every variant has one
+        let schema = package_schema();
+        let defs = &schema.as_value()["$defs"];
+        let listed = |def: &str, name: &str| {
+            defs[def]["enum"]
+                .as_array()
+                .unwrap()
+                .contains(&serde_json::json!(name))
+        };
+
+        for (name, runner) in StepRunnerType::NAMES {
+            assert!(listed("StepRunnerType", name), "{}", name);
+            let serialized = serde_yaml::to_string(runner).unwrap();
+            assert!(listed("StepRunnerType", serialized.trim()), "{:?}", runner);
+        }
+        for (name, param_type) in ParamType::NAMES {
+            assert!(listed("ParamType", name), "{}", name);
+            let serialized = serde_yaml::to_string(param_type).unwrap();
+            assert!(listed("ParamType", serialized.trim()), "{:?}", param_type);
+        }
+        for (name, os) in OS::NAMES {
+            assert!(listed("OS", name), "{}", name);
+            let serialized = serde_yaml::to_string(os).unwrap();
+            assert!(listed("OS", serialized.trim()), "{:?}", os);
+        }
+    }
+}
@@ -5,27 +5,41 @@ use anyhow::{bail, Result};
 #[cfg(feature = "howto")]
 use levenshtein::levenshtein;
 
+use crate::core::package::Package;
 use crate::core::packages_manager::PackagesManager;
+use crate::core::utils::version::version_matches;
 use crate::output::print_md;
 
 pub fn show_packages(packages_manager: &mut PackagesManager) -> Result<()> {
     let mut table = String::new();
 
-    let packages = packages_manager.packages.values();
-
-    if packages.len() > 0 {
+    // the project we stand in comes first, it hides an installed package with its name
+    let project = packages_manager.project.as_ref();
+    let packages: Vec<&Package> = project
+        .into_iter()
+        .chain(
+            packages_manager
+                .packages
+                .values()
+                .filter(|package| project.is_none_or(|p| p.name() != package.name())),
+        )
+        .collect();
+
+    if !packages.is_empty() {
         // Append the markdown table header
         table.push_str("| Package Name | #Aliases | #Automations | #Paths | Path |\n");
         table.push_str("|------------|----------|--------------|--------|-------|\n");
 
         // Iterate through packages and append rows to the table
-        for package in packages {
+        for (i, package) in packages.into_iter().enumerate() {
             if !package.is_legit_cocmd_package() {
                 continue;
             }
+            let is_project = i == 0 && project.is_some();
             table.push_str(&format!(
-                "| {} | {} | {} | {} | {} |\n",
+                "| {}{} | {} | {} | {} | {} |\n",
                 package.name(),
+                if is_project { " (project)" } else { "" },
                 package.get_aliases_count(),
                 package.get_automations_count(&packages_manager.settings),
                 package.get_paths_count(),
@@ -52,9 +66,60 @@ pub fn show_package(packages_manager: &mut PackagesManager, uri: String) -> Resu
 
     package.print_doc(&packages_manager.settings, true, true);
 
+    if !package.requirements().is_empty() {
+        let mut tree = String::from("## requires\n");
+        dependency_tree(
+            packages_manager,
+            package,
+            &mut vec![package.name().to_string()],
+            &mut tree,
+        );
+        print_md(&tree);
+    }
+
     Ok(())
 }
 
+// a nested list of the requirements of the package, and theirs, with what's installed
+fn dependency_tree(
+    packages_manager: &PackagesManager,
+    package: &Package,
+    path: &mut Vec<String>,
+    output: &mut String,
+) {
+    let indent = "  ".repeat(path.len() - 1);
+    for (uri, version) in package.requirements() {
+        let spec = match &version {
+            Some(version) => format!("{}@{}", uri, version),
+            None => uri.clone(),
+        };
+        let installed = packages_manager.get_package(uri.clone());
+        let status = match installed {
+            Some(dep)
+                if version
+                    .as_ref()
+                    .is_none_or(|v| version_matches(v, &dep.version())) =>
+            {
+                format!("✅ {}", dep.version())
+            }
+            Some(dep) => format!("⚠️ {} installed", dep.version()),
+            None => "❌ not installed".to_string(),
+        };
+
+        if path.contains(&uri) {
+            *output += &format!("{}- {} {} (cycle)\n", indent, spec, status);
+            continue;
+        }
+        *output += &format!("{}- {} {}\n", indent, spec, status);
+
+        if let Some(dep) = installed {
+            path.push(uri);
+            dependency_tree(packages_manager, dep, path, output);
+            path.pop();
+        }
+    }
+}
+
 #[cfg(feature = "howto")]
 pub fn howto(packages_manager: &mut PackagesManager, query: String) -> Result<()> {
     // lookup in all descriptions of automation for what matches best to query variable
@@ -77,8 +142,7 @@ pub fn howto(packages_manager: &mut PackagesManager, query: String) -> Result<()
         }
     }
 
-    let skin = termimad::MadSkin::default();
-    skin.print_text(&best_match);
+    print_md(&best_match);
 
     Ok(())
 }
@@ -0,0 +1,753 @@
+// `cocmd validate` checks a package, its cocmd.yaml and the files it points to, without running
+// anything, and reports the problems the way a compiler does
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Error, Result};
+use console::style;
+use log::error;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+
+use crate::core::consts;
+use crate::core::models::migrations::upgrade;
+use crate::core::models::package_config_model::PackageConfigModel;
+use crate::core::models::script_model::ScriptModel;
+use crate::core::packages_manager::PackagesManager;
+use crate::core::utils::io::normalize_path;
+use crate::core::utils::spanned_yaml::{self, Position, Spanned};
+use crate::output::print_md;
+use crate::runner::{template, OUTPUT_FILE_ENV};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub file: PathBuf,
+    pub position: Position,
+    pub help: Option<String>,
+    // the source line of the problem and how many characters of it to underline
+    source_line: String,
+    len: usize,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (label, underline) = match self.severity {
+            Severity::Error => (
+                style("error").red().bold(),
+                style("^".repeat(self.len)).red().bold(),
+            ),
+            Severity::Warning => (
+                style("warning").yellow().bold(),
+                style("^".repeat(self.len)).yellow().bold(),
+            ),
+        };
+        let number = self.position.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let bar = style("|").blue().bold();
+
+        writeln!(f, "{}: {}", label, style(&self.message).bold())?;
+        writeln!(
+            f,
+            "{}{} {}:{}:{}",
+            gutter,
+            style("-->").blue().bold(),
+            self.file.display(),
+            self.position.line,
+            self.position.column
+        )?;
+        writeln!(f, "{} {}", gutter, bar)?;
+        writeln!(
+            f,
+            "{} {} {}",
+            style(&number).blue().bold(),
+            bar,
+            self.source_line
+        )?;
+        write!(
+            f,
+            "{} {} {}{}",
+            gutter,
+            bar,
+            " ".repeat(self.position.column - 1),
+            underline
+        )?;
+        if let Some(help) = &self.help {
+            write!(
+                f,
+                "\n{} {} help: {}",
+                gutter,
+                style("=").blue().bold(),
+                help
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct SourceFile {
+    path: PathBuf,
+    source: String,
+}
+
+impl SourceFile {
+    fn read(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("unable to read {}", path.display()))?;
+        Ok(SourceFile {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    // where `needle` first shows up at or after `from`.
+    // the text of a block scalar (`content: |`) starts on a later line than the scalar itself
+    fn find(&self, from: Position, needle: &str) -> Option<Position> {
+        for (index, line) in self.source.lines().enumerate().skip(from.line - 1) {
+            let skip = if index + 1 == from.line {
+                from.column - 1
+            } else {
+                0
+            };
+            let rest: String = line.chars().skip(skip).collect();
+            if let Some(offset) = rest.find(needle) {
+                return Some(Position {
+                    line: index + 1,
+                    column: skip + rest[..offset].chars().count() + 1,
+                });
+            }
+        }
+        None
+    }
+}
+
+struct Validator {
+    location: PathBuf,
+    package_name: String,
+    // "package.automation" names that can be run, of this package and of the installed ones
+    automations: HashSet<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+// what the steps of a script can refer to, as they're checked in order
+struct ScriptScope {
+    params: HashSet<String>,
+    outputs: HashSet<String>,
+    // a step writes to $COCMD_OUTPUT, so any name can be an output of it
+    writes_output: bool,
+    titles: HashSet<String>,
+}
+
+impl Validator {
+    fn report(
+        &mut self,
+        file: &SourceFile,
+        severity: Severity,
+        position: Position,
+        len: usize,
+        message: String,
+        help: Option<&str>,
+    ) {
+        let source_line = file
+            .source
+            .lines()
+            .nth(position.line - 1)
+            .unwrap_or_default()
+            .to_string();
+        let rest = source_line
+            .chars()
+            .count()
+            .saturating_sub(position.column - 1);
+        self.diagnostics.push(Diagnostic {
+            severity,
+            message,
+            file: file.path.clone(),
+            position,
+            help: help.map(str::to_string),
+            source_line,
+            len: len.clamp(1, rest.max(1)),
+        });
+    }
+
+    fn error(&mut self, file: &SourceFile, node: &Spanned, message: String, help: Option<&str>) {
+        self.report(file, Severity::Error, node.start, node.len, message, help);
+    }
+
+    // the yaml tree of a file, or None (with the problem reported) when it can't be loaded as T
+    fn parse<T: DeserializeOwned>(
+        &mut self,
+        file: &SourceFile,
+        check: impl FnOnce(&mut Self, &SourceFile, &Spanned) -> bool,
+    ) -> Option<Spanned> {
+        let root = match spanned_yaml::load(&file.source) {
+            Ok(Some(root)) => root,
+            Ok(None) => {
+                let message = format!("{} is empty", file.path.display());
+                self.report(file, Severity::Error, Position::start(), 1, message, None);
+                return None;
+            }
+            Err(e) => {
+                let message = format!("invalid yaml: {}", e.message);
+                self.report(file, Severity::Error, e.position, 1, message, None);
+                return None;
+            }
+        };
+        if !check(self, file, &root) {
+            return None;
+        }
+
+        if let Err(e) = serde_yaml::from_str::<T>(&file.source) {
+            let position = e.location().map_or(Position::start(), |location| Position {
+                line: location.line(),
+                column: location.column(),
+            });
+            // the location is shown in the diagnostic
+            let message = Regex::new(r" at line \d+ column \d+$")
+                .unwrap()
+                .replace(&e.to_string(), "")
+                .to_string();
+            self.report(file, Severity::Error, position, 1, message, None);
+            return None;
+        }
+        Some(root)
+    }
+
+    // refused packages (too new for this cocmd) aren't checked any further
+    fn check_compatibility(&mut self, file: &SourceFile, root: &Spanned) -> bool {
+        let result = serde_yaml::from_str(&file.source)
+            .map_err(Error::from)
+            .and_then(upgrade);
+        let Err(e) = result else {
+            return true;
+        };
+
+        let message = format!("{:#}", e);
+        let key = if message.contains("schema") {
+            "schema_version"
+        } else {
+            "min_cocmd_version"
+        };
+        let node = root.key(key).unwrap_or(root).clone();
+        self.error(file, &node, message, None);
+        false
+    }
+
+    fn check_package(&mut self, file: &SourceFile, root: &Spanned) {
+        self.package_name = root
+            .get("name")
+            .and_then(Spanned::as_str)
+            .unwrap_or_default()
+            .to_string();
+
+        for path in root.get("paths").map(Spanned::items).unwrap_or_default() {
+            let value = path.as_str().unwrap_or_default();
+            if !Path::new(&normalize_path(value, &self.location)).exists() {
+                self.error(file, path, format!("path `{}` doesn't exist", value), None);
+            }
+        }
+
+        let automations = root
+            .get("automations")
+            .map(Spanned::items)
+            .unwrap_or_default();
+        let mut names = HashSet::new();
+        for automation in automations {
+            let Some(name) = automation.get("name") else {
+                continue;
+            };
+            let value = name.as_str().unwrap_or_default();
+            if !names.insert(value) {
+                self.error(
+                    file,
+                    name,
+                    format!("there's more than one automation named `{}`", value),
+                    Some("automation names have to be unique in a package"),
+                );
+            }
+            self.automations
+                .insert(format!("{}.{}", self.package_name, value));
+        }
+
+        for automation in automations {
+            let name = automation
+                .get("name")
+                .and_then(Spanned::as_str)
+                .unwrap_or_default();
+            self.check_depends_on(file, automation.get("depends_on"), name);
+
+            if let Some(script) = automation.get("content") {
+                self.check_script(file, script, name);
+            }
+            if let Some(file_node) = automation.get("file") {
+                let value = file_node.as_str().unwrap_or_default();
+                let path = PathBuf::from(normalize_path(value, &self.location));
+                match SourceFile::read(&path) {
+                    Ok(script_file) => {
+                        if let Some(script) =
+                            self.parse::<ScriptModel>(&script_file, |_, _, _| true)
+                        {
+                            self.check_script(&script_file, &script, name);
+                        }
+                    }
+                    Err(_) => self.error(
+                        file,
+                        file_node,
+                        format!(
+                            "automation `{}` reads `{}`, which doesn't exist",
+                            name, value
+                        ),
+                        None,
+                    ),
+                }
+            }
+        }
+    }
+
+    fn check_depends_on(&mut self, file: &SourceFile, depends_on: Option<&Spanned>, name: &str) {
+        for dependency in depends_on.map(Spanned::items).unwrap_or_default() {
+            let value = dependency.as_str().unwrap_or_default().trim();
+            let key = if value.contains('.') {
+                value.to_string()
+            } else {
+                format!("{}.{}", self.package_name, value)
+            };
+            if !self.automations.contains(&key) {
+                self.error(
+                    file,
+                    dependency,
+                    format!(
+                        "automation `{}` depends on `{}`, which wasn't found",
+                        name, key
+                    ),
+                    None,
+                );
+            }
+        }
+    }
+
+    // the names of the params in a `params` list
+    fn check_params(&mut self, file: &SourceFile, params: Option<&Spanned>) -> HashSet<String> {
+        let mut names = HashSet::new();
+        for param in params.map(Spanned::items).unwrap_or_default() {
+            let Some(name) = param.get("name") else {
+                continue;
+            };
+            let value = name.as_str().unwrap_or_default().to_string();
+            if !names.insert(value.clone()) {
+                self.error(
+                    file,
+                    name,
+                    format!("param `{}` is declared twice", value),
+                    None,
+                );
+            }
+        }
+        names
+    }
+
+    fn check_script(&mut self, file: &SourceFile, script: &Spanned, name: &str) {
+        self.check_depends_on(file, script.get("depends_on"), name);
+
+        let mut scope = ScriptScope {
+            params: self.check_params(file, script.get("params")),
+            outputs: HashSet::new(),
+            writes_output: false,
+            titles: HashSet::new(),
+        };
+        for section in ["steps", "on_failure", "always"] {
+            for step in script.get(section).map(Spanned::items).unwrap_or_default() {
+                self.check_step(file, step, &mut scope);
+            }
+        }
+    }
+
+    fn check_step(&mut self, file: &SourceFile, step: &Spanned, scope: &mut ScriptScope) {
+        let title = step
+            .get("title")
+            .and_then(Spanned::as_str)
+            .unwrap_or_default()
+            .to_string();
+        if !scope.titles.insert(title.clone()) {
+            let node = step.get("title").unwrap_or(step).clone();
+            self.report(
+                file,
+                Severity::Warning,
+                node.start,
+                node.len,
+                format!("there's more than one step titled `{}`", title),
+                Some("succeeded(), failed() and skipped() in `when` only see the last one"),
+            );
+        }
+
+        let mut declared: HashSet<String> = &scope.params | &scope.outputs;
+        declared.extend(self.check_params(file, step.get("params")));
+        if step.get("for_each").is_some() {
+            declared.extend(["item".to_string(), "index".to_string()]);
+        }
+
+        // everything that goes through the templating
+        let mut templated: Vec<&Spanned> = vec![];
+        templated.extend(step.get("content"));
+        templated.extend(step.get("cwd"));
+        templated.extend(step.get("env").map(Spanned::values).unwrap_or_default());
+        if let Some(for_each) = step.get("for_each") {
+            templated.extend(for_each.items());
+            templated.extend(for_each.as_str().map(|_| for_each));
+        }
+        for node in templated {
+            let text = node.as_str().unwrap_or_default();
+            self.check_references(file, node.start, text, &declared, scope, &title);
+        }
+
+        if let Some(file_node) = step.get("file") {
+            let value = file_node.as_str().unwrap_or_default();
+            let path = PathBuf::from(normalize_path(value, &self.location));
+            match SourceFile::read(&path) {
+                Ok(step_file) => {
+                    let text = step_file.source.clone();
+                    self.check_references(
+                        &step_file,
+                        Position::start(),
+                        &text,
+                        &declared,
+                        scope,
+                        &title,
+                    );
+                }
+                Err(_) => self.error(
+                    file,
+                    file_node,
+                    format!("step `{}` reads `{}`, which doesn't exist", title, value),
+                    None,
+                ),
+            }
+        }
+
+        let is_cocmd = step
+            .get("runner")
+            .and_then(Spanned::as_str)
+            .is_some_and(|runner| runner.eq_ignore_ascii_case("cocmd"));
+        if let (true, Some(content)) = (is_cocmd, step.get("content")) {
+            self.check_cocmd_target(file, content, &title);
+        }
+
+        for child in step.get("parallel").map(Spanned::items).unwrap_or_default() {
+            self.check_step(file, child, scope);
+        }
+
+        if let Some(name) = step.get("register").and_then(Spanned::as_str) {
+            scope.outputs.extend([
+                name.to_string(),
+                format!("{}.stdout", name),
+                format!("{}.exit_code", name),
+            ]);
+        }
+        if step
+            .get("content")
+            .and_then(Spanned::as_str)
+            .is_some_and(|content| content.contains(OUTPUT_FILE_ENV))
+        {
+            scope.writes_output = true;
+        }
+    }
+
+    // the automation a `runner: cocmd` step runs
+    fn check_cocmd_target(&mut self, file: &SourceFile, content: &Spanned, title: &str) {
+        let target = content.as_str().unwrap_or_default().trim();
+        if self.automations.contains(target) {
+            return;
+        }
+
+        let Some((package, _)) = target.split_once('.') else {
+            let message = format!(
+                "step `{}` runs `{}`, which isn't an automation name",
+                title, target
+            );
+            self.error(
+                file,
+                content,
+                message,
+                Some("automations are named `package.automation`"),
+            );
+            return;
+        };
+        let prefix = format!("{}.", package);
+        if package == self.package_name || self.automations.iter().any(|a| a.starts_with(&prefix)) {
+            let message = format!(
+                "step `{}` runs `{}`, which wasn't found in package `{}`",
+                title, target, package
+            );
+            self.error(file, content, message, None);
+        } else {
+            // the package is offered for install when the step runs
+            let message = format!(
+                "step `{}` runs `{}`, but package `{}` isn't installed",
+                title, target, package
+            );
+            let help = format!("`cocmd install {}` to check it now", package);
+            self.report(
+                file,
+                Severity::Warning,
+                content.start,
+                content.len,
+                message,
+                Some(&help),
+            );
+        }
+    }
+
+    fn check_references(
+        &mut self,
+        file: &SourceFile,
+        from: Position,
+        text: &str,
+        declared: &HashSet<String>,
+        scope: &ScriptScope,
+        title: &str,
+    ) {
+        let references = match template::references(text) {
+            Ok(references) => references,
+            Err(e) => {
+                let position = ["{%", "{{"]
+                    .iter()
+                    .find_map(|open| file.find(from, open))
+                    .unwrap_or(from);
+                let message = format!("invalid template in step `{}`: {}", title, e);
+                self.report(file, Severity::Error, position, 2, message, None);
+                return;
+            }
+        };
+        for (name, reference) in references {
+            if declared.contains(&name) {
+                continue;
+            }
+            let position = file.find(from, &reference).unwrap_or(from);
+            let (severity, help) = if scope.writes_output {
+                (
+                    Severity::Warning,
+                    "fine if an earlier step writes it to $COCMD_OUTPUT, otherwise declare it in `params`",
+                )
+            } else {
+                (
+                    Severity::Error,
+                    "declare it in `params` of the step or the script, or `register` it in an earlier step",
+                )
+            };
+            self.report(
+                file,
+                severity,
+                position,
+                reference.chars().count(),
+                format!("`{}` in step `{}` isn't a declared param", reference, title),
+                Some(help),
+            );
+        }
+    }
+}
+
+// the problems of the package config at `config_path`, with `installed_automations` as the
+// automations that `runner: cocmd` steps and `depends_on` can use from other packages
+pub fn validate_package(
+    config_path: &Path,
+    installed_automations: &HashSet<String>,
+) -> Result<Vec<Diagnostic>> {
+    let file = SourceFile::read(config_path)?;
+    let mut validator = Validator {
+        location: config_path
+            .parent()
+            .map_or(PathBuf::from("."), Path::to_path_buf),
+        package_name: String::new(),
+        automations: installed_automations.clone(),
+        diagnostics: vec![],
+    };
+
+    if let Some(root) = validator.parse::<PackageConfigModel>(&file, Validator::check_compatibility)
+    {
+        validator.check_package(&file, &root);
+    }
+    Ok(validator.diagnostics)
+}
+
+fn plural(count: usize, word: &str) -> String {
+    match count {
+        1 => format!("1 {}", word),
+        _ => format!("{} {}s", count, word),
+    }
+}
+
+pub fn run_validate(packages_manager: &PackagesManager, path: Option<String>) -> Result<()> {
+    let path = PathBuf::from(path.unwrap_or_else(|| ".".to_string()));
+    let config_path = if path.is_dir() {
+        path.join(consts::SOURCE_CONFIG_FILE)
+    } else {
+        path
+    };
+
+    let installed = packages_manager.automations().into_keys().collect();
+    let diagnostics = validate_package(&config_path, &installed).inspect_err(|e| {
+        error!("{:#}", e);
+    })?;
+    for diagnostic in &diagnostics {
+        eprintln!("{}\n", diagnostic);
+    }
+
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    let warnings = diagnostics.len() - errors;
+    if errors > 0 {
+        let summary = format!(
+            "{} has {} and {}",
+            config_path.display(),
+            plural(errors, "error"),
+            plural(warnings, "warning")
+        );
+        eprintln!("{}: {}", style("error").red().bold(), summary);
+        bail!(summary);
+    }
+
+    match warnings {
+        0 => print_md(&format!("✅ {} is valid", config_path.display())),
+        _ => print_md(&format!(
+            "✅ {} is valid, with {}",
+            config_path.display(),
+            plural(warnings, "warning")
+        )),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn validate(config: &str, files: &[(&str, &str)]) -> Vec<(Severity, String, usize, usize)> {
+        let dir = TempDir::new("cocmd").unwrap();
+        let config_path = dir.path().join("cocmd.yaml");
+        fs::write(&config_path, config).unwrap();
+        for (name, content) in files {
+            fs::write(dir.path().join(name), content).unwrap();
+        }
+
+        let installed = HashSet::from(["tools.build".to_string()]);
+        validate_package(&config_path, &installed)
+            .unwrap()
+            .into_iter()
+            .map(|d| (d.severity, d.message, d.position.line, d.position.column))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_package() {
+        let config = r#"name: demo
+paths:
+  - bin
+automations:
+  - name: build
+    content:
+      params:
+        - name: target
+      steps:
+        - title: compile
+          content: make {{ target }} {{ jobs }}
+          register: out
+        - title: report
+          content: |
+            echo {{ out }}
+            echo {{ missing }}
+        - title: tools
+          runner: cocmd
+          content: tools.build
+        - title: tools
+          runner: cocmd
+          content: other.build
+  - name: deploy
+    file: deploy.yaml
+    depends_on: [build, tools.lint]
+  - name: build
+    content:
+      steps:
+        - title: nested
+          runner: cocmd
+          content: demo.nope
+"#;
+        let deploy = "steps:\n  - title: up\n    file: up.sh\n";
+        let diagnostics = validate(config, &[("deploy.yaml", deploy)]);
+
+        let error =
+            |message: &str, line, column| (Severity::Error, message.to_string(), line, column);
+        let warning =
+            |message: &str, line, column| (Severity::Warning, message.to_string(), line, column);
+        assert_eq!(
+            diagnostics,
+            vec![
+                error("path `bin` doesn't exist", 3, 5),
+                error("there's more than one automation named `build`", 26, 11),
+                error(
+                    "`{{ jobs }}` in step `compile` isn't a declared param",
+                    11,
+                    38
+                ),
+                error(
+                    "`{{ missing }}` in step `report` isn't a declared param",
+                    16,
+                    18
+                ),
+                warning("there's more than one step titled `tools`", 20, 18),
+                warning(
+                    "step `tools` runs `other.build`, but package `other` isn't installed",
+                    22,
+                    20
+                ),
+                error(
+                    "automation `deploy` depends on `tools.lint`, which wasn't found",
+                    25,
+                    25
+                ),
+                error("step `up` reads `up.sh`, which doesn't exist", 3, 11),
+                error(
+                    "step `nested` runs `demo.nope`, which wasn't found in package `demo`",
+                    31,
+                    20
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_package_invalid() {
+        // yaml errors and model errors stop the validation, with their location
+        let diagnostics = validate("name: demo\nautomations: [\n", &[]);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].1.starts_with("invalid yaml"));
+
+        let config = "name: demo\nautomations:\n  - name: x\n    content:\n      steps:\n        - title: a\n          runner: bash\n";
+        let diagnostics = validate(config, &[]);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].1.contains("unknown variant `bash`"));
+        assert_eq!(diagnostics[0].2, 7);
+
+        let diagnostics = validate("schema_version: 99\nname: demo\n", &[]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].2, diagnostics[0].3), (1, 1));
+
+        // outputs written to $COCMD_OUTPUT can't be known, so they're only warned about
+        let config = "name: demo\nautomations:\n  - name: x\n    content:\n      steps:\n        - title: a\n          content: echo v=1 >> $COCMD_OUTPUT\n        - title: b\n          content: echo {{ v }}\n";
+        let diagnostics = validate(config, &[]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].0, Severity::Warning);
+    }
+}
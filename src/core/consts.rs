@@ -14,3 +14,15 @@ pub const SOURCES_FILE: &str = "packages.txt";
 pub const RUNTIME_DIR: &str = "runtime";
 pub const TMP_EXEC_FILE_NAME: &str = "cocmd-exec.sh";
 pub const PARAMS_FILE: &str = "saved_params.yaml";
+// the user's settings, see models::user_config
+pub const CONFIG_FILE: &str = "config.yaml";
+// the --config of the cocmd that runs us, so nested runs use the same config
+pub const CONFIG_ENV: &str = "COCMD_CONFIG";
+// values of `secret: true` params, see core::vault
+pub const VAULT_FILE: &str = "secrets.vault";
+pub const VAULT_KEY_FILE: &str = "vault.key";
+// what exactly is installed, see core::lockfile
+pub const LOCK_FILE: &str = "cocmd.lock";
+pub const COCMD_VERSION: &str = env!("CARGO_PKG_VERSION");
+// the layout version of cocmd.yaml this cocmd writes and understands, see models::migrations
+pub const SCHEMA_VERSION: u64 = 2;
@@ -0,0 +1,153 @@
+// cocmd.lock in the cocmd home, what exactly was installed. `cocmd install` keeps it up to date
+// and `cocmd install --locked` installs the same packages again, on another machine too
+//
+//   version: 1
+//   packages:
+//     - name: docker
+//       uri: docker
+//       provider: cocmd-hub
+//       registry: cocmd
+//       version: 1.2.0
+//       constraint: ^1.2
+//       sha256: 5e8f...
+//     - name: tools
+//       uri: https://github.com/acme/tools
+//       provider: git
+//       commit: 3f1c...
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde_derive::{Deserialize, Serialize};
+
+const LOCKFILE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct LockedPackage {
+    // the name in its cocmd.yaml
+    pub name: String,
+    // as in packages.txt
+    pub uri: String,
+    pub provider: String,
+    // the hub registry it came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    // the version it was installed with (`docker@^1.2`), a refresh stays within it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constraint: Option<String>,
+    // of the hub archive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct Lockfile {
+    pub version: u32,
+    #[serde(default)]
+    pub packages: Vec<LockedPackage>,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Lockfile {
+            version: LOCKFILE_VERSION,
+            packages: vec![],
+        }
+    }
+}
+
+impl Lockfile {
+    // an empty lock when there's no file yet
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Lockfile::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("unable to read {}", path.display()))?;
+        let lockfile: Lockfile = serde_yaml::from_str(&content)
+            .with_context(|| format!("{} is not a valid cocmd lock file", path.display()))?;
+        if lockfile.version > LOCKFILE_VERSION {
+            bail!(
+                "{} was written by a newer cocmd, please upgrade cocmd",
+                path.display()
+            );
+        }
+        Ok(lockfile)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_yaml::to_string(self)?)
+            .with_context(|| format!("unable to write {}", path.display()))
+    }
+
+    // replaces the entry of a package with the same name
+    pub fn insert(&mut self, package: LockedPackage) {
+        match self.packages.iter_mut().find(|p| p.name == package.name) {
+            Some(existing) => *existing = package,
+            None => self.packages.push(package),
+        }
+        self.packages.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let count = self.packages.len();
+        self.packages.retain(|p| p.name != name);
+        self.packages.len() != count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_lockfile() {
+        let dir = TempDir::new("cocmd").unwrap();
+        let path = dir.path().join("cocmd.lock");
+        assert_eq!(Lockfile::load(&path).unwrap(), Lockfile::default());
+
+        let mut lockfile = Lockfile::default();
+        lockfile.insert(LockedPackage {
+            name: "tools".to_string(),
+            uri: "https://github.com/acme/tools".to_string(),
+            provider: "git".to_string(),
+            commit: Some("3f1c".to_string()),
+            ..Default::default()
+        });
+        let docker = LockedPackage {
+            name: "docker".to_string(),
+            uri: "docker".to_string(),
+            provider: "cocmd-hub".to_string(),
+            version: Some("1.2.0".to_string()),
+            sha256: Some("5e8f".to_string()),
+            ..Default::default()
+        };
+        lockfile.insert(docker.clone());
+        lockfile.insert(LockedPackage {
+            version: Some("1.3.0".to_string()),
+            ..docker
+        });
+        lockfile.save(&path).unwrap();
+
+        let loaded = Lockfile::load(&path).unwrap();
+        assert_eq!(loaded, lockfile);
+        let names: Vec<&str> = loaded.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["docker", "tools"]);
+        assert_eq!(loaded.packages[0].version.as_deref(), Some("1.3.0"));
+        assert!(!fs::read_to_string(&path).unwrap().contains("commit: null"));
+
+        lockfile.remove("docker");
+        assert_eq!(lockfile.packages.len(), 1);
+        assert!(!lockfile.remove("docker"));
+
+        fs::write(&path, "version: 9\n").unwrap();
+        assert!(Lockfile::load(&path).is_err());
+    }
+}
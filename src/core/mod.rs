@@ -1,5 +1,7 @@
 pub mod consts;
+pub mod lockfile;
 pub mod models;
 pub mod package;
 pub mod packages_manager;
 pub mod utils;
+pub mod vault;
@@ -0,0 +1,261 @@
+// compatibility checks and upgrades of package configs (cocmd.yaml).
+//
+// `schema_version` is the layout of the file, `min_cocmd_version` the oldest cocmd that can run
+// the package. a config without `schema_version` is version 1.
+// to change the layout: bump consts::SCHEMA_VERSION and add a migration from the previous version
+// to MIGRATIONS, so packages written for older versions keep working. the scripts of `file:`
+// automations are in the layout of their package, they're read and migrated along with it.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde_yaml::{Mapping, Value};
+
+use super::package_config_model::PackageConfigModel;
+use crate::core::consts::{COCMD_VERSION, SCHEMA_VERSION};
+use crate::core::utils::io::normalize_path;
+use crate::core::utils::version::{compare_versions, parse_version};
+
+// MIGRATIONS[i] upgrades a script (the content of an automation) from schema version i + 1 to i + 2
+const MIGRATIONS: &[fn(&mut Mapping)] = &[migrate_v1_to_v2];
+
+pub fn load_package_config(path: &Path) -> Result<PackageConfigModel> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("unable to read {}", path.display()))?;
+    let value: Value = serde_yaml::from_str(&content)
+        .with_context(|| format!("{} is not valid yaml", path.display()))?;
+    let location = path.parent().unwrap_or(Path::new("."));
+    let value = upgrade_script_files(value, location)?;
+    let value = upgrade(value)?;
+    serde_yaml::from_value(value).with_context(|| format!("{} is invalid", path.display()))
+}
+
+// the `file:` scripts of an older package become the migrated `content` of their automations.
+// a file that can't be read is left to Automation::load_content to report
+fn upgrade_script_files(value: Value, location: &Path) -> Result<Value> {
+    let Value::Mapping(mut config) = value else {
+        return Ok(value);
+    };
+    // an invalid version is left to `upgrade` to refuse
+    let schema_version = config
+        .get("schema_version")
+        .map_or(Some(1), Value::as_u64)
+        .unwrap_or(SCHEMA_VERSION);
+    if schema_version == 0 || schema_version >= SCHEMA_VERSION {
+        return Ok(Value::Mapping(config));
+    }
+
+    let automations = config
+        .get_mut("automations")
+        .and_then(Value::as_sequence_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_mapping_mut);
+    for automation in automations {
+        let Some(file) = automation.get("file").and_then(Value::as_str) else {
+            continue;
+        };
+        let path = normalize_path(file, location);
+        let script = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_yaml::from_str::<Value>(&content).ok());
+        let Some(Value::Mapping(mut script)) = script else {
+            continue;
+        };
+        migrate_script(&mut script, schema_version);
+        automation.remove("file");
+        automation.insert("content".into(), Value::Mapping(script));
+    }
+    Ok(Value::Mapping(config))
+}
+
+// check that this cocmd can handle the config and migrate it to the current schema version
+pub fn upgrade(value: Value) -> Result<Value> {
+    let Value::Mapping(mut config) = value else {
+        bail!("a package config must be a mapping");
+    };
+
+    let name = config
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or("the package")
+        .to_string();
+    let schema_version = match config.get("schema_version") {
+        None => 1,
+        Some(version) => match version.as_u64() {
+            Some(version) if version >= 1 => version,
+            _ => bail!(
+                "{} has an invalid schema_version {:?}, expected a positive number",
+                name,
+                version
+            ),
+        },
+    };
+
+    if let Some(min_version) = config.get("min_cocmd_version") {
+        let min_version = match min_version {
+            Value::String(version) => version.clone(),
+            Value::Number(version) => version.to_string(),
+            _ => bail!("{} has an invalid min_cocmd_version", name),
+        };
+        if parse_version(&min_version).is_none() {
+            bail!(
+                "{} has an invalid min_cocmd_version '{}'",
+                name,
+                min_version
+            );
+        }
+        if compare_versions(COCMD_VERSION, &min_version).is_lt() {
+            bail!(
+                "{} requires cocmd {} or newer, but this is cocmd {}. please upgrade cocmd to use it",
+                name,
+                min_version,
+                COCMD_VERSION
+            );
+        }
+    }
+
+    if schema_version > SCHEMA_VERSION {
+        bail!(
+            "{} uses package schema version {}, but cocmd {} supports up to version {}. please upgrade cocmd to use it",
+            name,
+            schema_version,
+            COCMD_VERSION,
+            SCHEMA_VERSION
+        );
+    }
+
+    for content in automation_contents(&mut config) {
+        migrate_script(content, schema_version);
+    }
+    config.insert("schema_version".into(), SCHEMA_VERSION.into());
+
+    Ok(Value::Mapping(config))
+}
+
+fn automation_contents(config: &mut Mapping) -> impl Iterator<Item = &mut Mapping> {
+    config
+        .get_mut("automations")
+        .and_then(Value::as_sequence_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(|automation| automation.get_mut("content"))
+        .filter_map(Value::as_mapping_mut)
+}
+
+fn migrate_script(script: &mut Mapping, schema_version: u64) {
+    for migration in &MIGRATIONS[(schema_version - 1) as usize..] {
+        migration(script);
+    }
+}
+
+// version 1 kept the os of a script in `env:`, version 2 has `os:` for that and `env:` for
+// environment variables
+fn migrate_v1_to_v2(script: &mut Mapping) {
+    if let Some(Value::String(os)) = script.get("env").cloned() {
+        script.remove("env");
+        if !script.contains_key("os") {
+            script.insert("os".into(), Value::String(os));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::models::script_model::ScriptEnv;
+    use crate::core::utils::sys::OS;
+
+    fn config(yaml: &str) -> Result<PackageConfigModel> {
+        upgrade(serde_yaml::from_str(yaml).unwrap())
+            .and_then(|value| Ok(serde_yaml::from_value(value)?))
+    }
+
+    #[test]
+    fn test_upgrade_v1() {
+        let config = config(
+            r#"
+            name: old
+            automations:
+              - name: setup
+                content:
+                  env: osx
+                  steps: []
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.schema_version, Some(SCHEMA_VERSION));
+
+        let content = config.automations.unwrap()[0].content.clone().unwrap();
+        assert_eq!(content.os, Some(OS::MacOS));
+        assert_eq!(content.env, None);
+    }
+
+    #[test]
+    fn test_upgrade_current() {
+        let config = config(
+            r#"
+            name: new
+            schema_version: 2
+            min_cocmd_version: "1.0"
+            automations:
+              - name: setup
+                content:
+                  env: {STAGE: prod}
+                  steps: []
+            "#,
+        )
+        .unwrap();
+
+        let content = config.automations.unwrap()[0].content.clone().unwrap();
+        assert!(matches!(content.env, Some(ScriptEnv::Vars(_))));
+    }
+
+    #[test]
+    fn test_upgrade_incompatible() {
+        let err = config("{name: future, schema_version: 99}").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("future uses package schema version 99"));
+
+        let err = config("{name: future, min_cocmd_version: 999.0.0}").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("future requires cocmd 999.0.0 or newer"));
+
+        assert!(config("{name: x, schema_version: zero}").is_err());
+        assert!(config("{name: x, min_cocmd_version: soon}").is_err());
+    }
+
+    #[test]
+    fn test_upgrade_v1_script_files() {
+        let dir = tempdir::TempDir::new("cocmd").unwrap();
+        fs::write(
+            dir.path().join("cocmd.yaml"),
+            "name: old\nautomations:\n  - name: setup\n    file: scripts/setup.yaml\n  - name: gone\n    file: gone.yaml\n",
+        )
+        .unwrap();
+        fs::create_dir_all(dir.path().join("scripts")).unwrap();
+        fs::write(
+            dir.path().join("scripts/setup.yaml"),
+            "env: osx\nsteps:\n  - title: install\n    file: install.sh\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("install.sh"), "brew install jq").unwrap();
+
+        let config = load_package_config(&dir.path().join("cocmd.yaml")).unwrap();
+        let automations = config.automations.unwrap();
+        assert_eq!(automations[0].file, None);
+        let content = automations[0].content.clone().unwrap();
+        assert_eq!(content.os, Some(OS::MacOS));
+        assert_eq!(content.env, None);
+        // the steps' files are still relative to the package
+        let loaded = automations[0].load_content(dir.path()).content.unwrap();
+        assert_eq!(loaded.steps[0].content.as_deref(), Some("brew install jq"));
+
+        // a missing file is reported when the automation is loaded, like before
+        assert_eq!(automations[1].file.as_deref(), Some("gone.yaml"));
+        assert_eq!(automations[1].content, None);
+    }
+}
@@ -1,3 +1,5 @@
+pub mod migrations;
 pub mod package_config_model;
 pub mod script_model;
 pub mod settings;
+pub mod user_config;
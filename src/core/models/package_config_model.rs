@@ -1,69 +1,114 @@
 use std::path::{Path, PathBuf};
 
 use log::error;
+use schemars::JsonSchema;
 use serde_derive::{Deserialize as De, Serialize as Se};
 
 use super::script_model::{ScriptModel, StepModel};
 use crate::core::utils::io::{from_file, from_yaml_file, normalize_path};
+use crate::core::utils::packages::extract_package_name_and_version;
 use crate::core::utils::sys::OS;
 
-#[derive(Debug, Se, De, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, Se, De, JsonSchema, PartialEq, Eq, Hash, Clone)]
 pub struct Automation {
     pub name: String,
+    /// read the content from this yaml file, relative to the package
     pub file: Option<String>,
     pub content: Option<ScriptModel>,
+    /// automations to run before this one, merged with `depends_on` of the content
+    pub depends_on: Option<Vec<String>>,
 }
 
 impl Automation {
+    // the content from `file:` when there's one, with the steps' `file:` read too
     pub fn load_content(&self, location: &Path) -> Automation {
         let mut automation_clone = self.clone();
         if let Some(file) = &self.file {
             let normalized_path = normalize_path(file, location);
 
             match from_yaml_file::<ScriptModel>(&PathBuf::from(normalized_path)) {
-                Ok(script_model) => {
-                    let updated_steps: Vec<StepModel> = script_model
-                        .steps
-                        .iter()
-                        .map(|step| {
-                            if let Some(file) = &step.file {
-                                let normalized_path = normalize_path(file, location);
-
-                                match from_file(&normalized_path) {
-                                    Ok(file_content) => {
-                                        let mut step_clone = step.clone(); // Clone the existing StepModel
-                                        step_clone.content = Some(file_content);
-                                        step_clone
-                                    }
-                                    Err(err) => {
-                                        // Handle the error if needed
-                                        error!("{}", err);
-                                        step.clone() // Return the original step on error
-                                    }
-                                }
-                            } else {
-                                step.clone() // No file specified, return the original step
-                            }
-                        })
-                        .collect();
-
-                    // Update the content's steps with the updated_steps
-                    let mut content_clone = script_model.clone();
-                    content_clone.steps = updated_steps;
-                    automation_clone.content = Some(content_clone);
-                }
+                Ok(script_model) => automation_clone.content = Some(script_model),
                 Err(err) => {
                     // Handle the error if needed
                     error!("{}", err);
                 }
             }
         }
+        if let Some(content) = &mut automation_clone.content {
+            // Update the content's steps with the loaded steps
+            content.steps = Self::load_steps_content(&content.steps, location);
+            content.on_failure = content
+                .on_failure
+                .as_deref()
+                .map(|steps| Self::load_steps_content(steps, location));
+            content.always = content
+                .always
+                .as_deref()
+                .map(|steps| Self::load_steps_content(steps, location));
+        }
         automation_clone
     }
 
+    fn load_steps_content(steps: &[StepModel], location: &Path) -> Vec<StepModel> {
+        steps
+            .iter()
+            .map(|step| Self::load_step_content(step, location))
+            .collect()
+    }
+
+    fn load_step_content(step: &StepModel, location: &Path) -> StepModel {
+        if let Some(parallel) = &step.parallel {
+            let mut step_clone = step.clone();
+            step_clone.parallel = Some(
+                parallel
+                    .iter()
+                    .map(|step| Self::load_step_content(step, location))
+                    .collect(),
+            );
+            return step_clone;
+        }
+        if let Some(file) = &step.file {
+            let normalized_path = normalize_path(file, location);
+
+            match from_file(&normalized_path) {
+                Ok(file_content) => {
+                    let mut step_clone = step.clone(); // Clone the existing StepModel
+                    step_clone.content = Some(file_content);
+                    step_clone
+                }
+                Err(err) => {
+                    // Handle the error if needed
+                    error!("{}", err);
+                    step.clone() // Return the original step on error
+                }
+            }
+        } else {
+            step.clone() // No file specified, return the original step
+        }
+    }
+
+    // the automations this one depends on, as "pkg.automation" keys.
+    // names without a package are looked up in the same package
+    pub fn dependencies(&self, package_name: &str) -> Vec<String> {
+        let content_depends_on = self.content.as_ref().and_then(|c| c.depends_on.as_ref());
+        let mut dependencies: Vec<String> = vec![];
+        for name in self.depends_on.iter().chain(content_depends_on).flatten() {
+            let name = name.trim();
+            let key = if name.contains('.') {
+                name.to_string()
+            } else {
+                format!("{}.{}", package_name, name)
+            };
+            if !dependencies.contains(&key) {
+                dependencies.push(key);
+            }
+        }
+        dependencies
+    }
+
     pub fn supports_os(&self, os: &OS) -> bool {
         if let Some(content) = &self.content {
-            if let Some(content_env) = &content.env {
+            if let Some(content_env) = &content.supported_os() {
                 return *content_env == *os || *content_env == OS::Any;
             }
         }
@@ -93,11 +138,72 @@ impl Automation {
     }
 }
 
-#[derive(Debug, Se, De, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, Se, De, JsonSchema, PartialEq, Eq, Hash, Clone)]
 pub struct PackageConfigModel {
+    /// the layout version of this file (default: 1)
+    pub schema_version: Option<u64>,
+    /// the oldest cocmd that can use this package
+    pub min_cocmd_version: Option<String>,
     pub name: String,
     pub version: Option<String>,
+    /// shell aliases, loaded into the shell by `cocmd setup`
     pub aliases: Option<String>,
+    /// directories, relative to the package, to add to PATH
     pub paths: Option<Vec<String>>,
     pub automations: Option<Vec<Automation>>,
+    /// packages this package needs, installed with it. `name` or `name@version`
+    pub requires: Option<Vec<String>>,
+}
+
+impl PackageConfigModel {
+    // the required packages as (package uri, version)
+    pub fn requirements(&self) -> Vec<(String, Option<String>)> {
+        self.requires
+            .iter()
+            .flatten()
+            .map(|spec| extract_package_name_and_version(spec.trim()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requirements() {
+        let config: PackageConfigModel = serde_yaml::from_str(
+            r#"
+            name: fullstack
+            requires: [git, node@18.2.0, " docker "]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.requirements(),
+            vec![
+                ("git".to_string(), None),
+                ("node".to_string(), Some("18.2.0".to_string())),
+                ("docker".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dependencies() {
+        let automation: Automation = serde_yaml::from_str(
+            r#"
+            name: env
+            depends_on: [clone, tools.node]
+            content:
+              depends_on: [tools.node, tools.docker]
+              steps: []
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            automation.dependencies("onboarding"),
+            vec!["onboarding.clone", "tools.node", "tools.docker"]
+        );
+    }
 }
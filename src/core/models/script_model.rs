@@ -1,36 +1,359 @@
 #![allow(clippy::upper_case_acronyms)]
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use regex::Regex;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::de::Error as _;
+use serde::{Deserialize as _, Deserializer};
 use serde_derive::{Deserialize, Serialize};
 
+use crate::core::utils::names::{deserialize_named, named_schema};
 use crate::core::utils::sys::OS;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, Default, Serialize, PartialEq, Eq, Hash, Clone)]
 pub enum StepRunnerType {
-    #[serde(alias = "shell", alias = "SHELL")]
+    #[default]
     SHELL,
-    #[serde(alias = "markdown", alias = "MARKDOWN")]
     MARKDOWN,
-    #[serde(alias = "python", alias = "PYTHON", alias = "py", alias = "PY")]
     PYTHON,
-    #[serde(alias = "link", alias = "LINK", alias = "href", alias = "HREF")]
     LINK,
-    #[serde(alias = "cocmd", alias = "COCMD")]
     COCMD,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Default)]
+impl StepRunnerType {
+    // every spelling cocmd.yaml can use
+    pub const NAMES: &'static [(&'static str, Self)] = &[
+        ("shell", Self::SHELL),
+        ("SHELL", Self::SHELL),
+        ("markdown", Self::MARKDOWN),
+        ("MARKDOWN", Self::MARKDOWN),
+        ("python", Self::PYTHON),
+        ("PYTHON", Self::PYTHON),
+        ("py", Self::PYTHON),
+        ("PY", Self::PYTHON),
+        ("link", Self::LINK),
+        ("LINK", Self::LINK),
+        ("href", Self::LINK),
+        ("HREF", Self::LINK),
+        ("cocmd", Self::COCMD),
+        ("COCMD", Self::COCMD),
+    ];
+}
+
+impl<'de> serde::Deserialize<'de> for StepRunnerType {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        deserialize_named(d, Self::NAMES)
+    }
+}
+
+impl JsonSchema for StepRunnerType {
+    fn schema_name() -> Cow<'static, str> {
+        "StepRunnerType".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        let mut schema = named_schema(Self::NAMES);
+        schema.insert(
+            "description".to_string(),
+            "how to run the content of the step".into(),
+        );
+        schema
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub enum ParamType {
+    #[default]
+    STRING,
+    INT,
+    BOOL,
+    PATH,
+    ENUM,
+}
+
+impl ParamType {
+    // every spelling cocmd.yaml can use
+    pub const NAMES: &'static [(&'static str, Self)] = &[
+        ("string", Self::STRING),
+        ("STRING", Self::STRING),
+        ("str", Self::STRING),
+        ("int", Self::INT),
+        ("INT", Self::INT),
+        ("integer", Self::INT),
+        ("bool", Self::BOOL),
+        ("BOOL", Self::BOOL),
+        ("boolean", Self::BOOL),
+        ("path", Self::PATH),
+        ("PATH", Self::PATH),
+        ("enum", Self::ENUM),
+        ("ENUM", Self::ENUM),
+    ];
+}
+
+impl<'de> serde::Deserialize<'de> for ParamType {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        deserialize_named(d, Self::NAMES)
+    }
+}
+
+impl JsonSchema for ParamType {
+    fn schema_name() -> Cow<'static, str> {
+        "ParamType".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        named_schema(Self::NAMES)
+    }
+}
+
+// yaml scalars that are read as strings (`default: 8080`), for the json schema
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[serde(untagged)]
+enum Scalar {
+    String(String),
+    Number(f64),
+    Bool(bool),
+}
+
+impl std::fmt::Display for ParamType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParamType::STRING => write!(f, "string"),
+            ParamType::INT => write!(f, "int"),
+            ParamType::BOOL => write!(f, "bool"),
+            ParamType::PATH => write!(f, "path"),
+            ParamType::ENUM => write!(f, "enum"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash, Clone, Default)]
 pub struct StepParamModel {
     pub name: String,
+    /// remember the value for the next runs
+    #[serde(default)]
     pub save: bool,
+    #[serde(rename = "type", default)]
+    pub param_type: ParamType,
+    #[serde(default, deserialize_with = "deserialize_scalar")]
+    #[schemars(with = "Option<Scalar>")]
+    pub default: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_scalars")]
+    #[schemars(with = "Option<Vec<Scalar>>")]
+    pub choices: Option<Vec<String>>,
+    pub description: Option<String>,
+    /// when false, an empty value is accepted (default: true)
+    pub required: Option<bool>,
+    /// regex the whole value has to match
+    pub pattern: Option<String>,
+    /// masked when asked, and saved in the encrypted vault instead of saved_params.yaml
+    #[serde(default)]
+    pub secret: bool,
+    /// shell command that prints the value, e.g. `pass show deploy/token` (secret params)
+    pub secret_command: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+// yaml gives us numbers and bools for `default: 8080` or `choices: [1, 2]`, keep them as strings
+fn scalar_to_string(value: serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(s) => Some(s),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        serde_yaml::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn deserialize_scalar<'de, D: Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+    Ok(Option::<serde_yaml::Value>::deserialize(d)?.and_then(scalar_to_string))
+}
+
+fn deserialize_scalars<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<String>>, D::Error> {
+    Ok(Option::<Vec<serde_yaml::Value>>::deserialize(d)?
+        .map(|values| values.into_iter().filter_map(scalar_to_string).collect()))
+}
+
+fn deserialize_scalar_map<'de, D: Deserializer<'de>>(
+    d: D,
+) -> Result<Option<BTreeMap<String, String>>, D::Error> {
+    Option::<BTreeMap<String, serde_yaml::Value>>::deserialize(d)?
+        .map(|values| {
+            values
+                .into_iter()
+                .map(|(key, value)| match scalar_to_string(value) {
+                    Some(value) => Ok((key, value)),
+                    None => Err(D::Error::custom(format!("env '{}' must be a scalar", key))),
+                })
+                .collect()
+        })
+        .transpose()
+}
+
+// the script level `env:` is either the os the script supports (`env: osx`, the original meaning)
+// or a map of environment variables for the steps
+#[derive(Debug, Serialize, JsonSchema, PartialEq, Eq, Hash, Clone)]
+#[serde(untagged)]
+pub enum ScriptEnv {
+    Os(OS),
+    Vars(#[schemars(with = "BTreeMap<String, Scalar>")] BTreeMap<String, String>),
+}
+
+impl<'de> serde::Deserialize<'de> for ScriptEnv {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        match serde_yaml::Value::deserialize(d)? {
+            serde_yaml::Value::Mapping(mapping) => {
+                deserialize_scalar_map(serde_yaml::Value::Mapping(mapping))
+                    .map(|vars| ScriptEnv::Vars(vars.unwrap_or_default()))
+                    .map_err(D::Error::custom)
+            }
+            value => serde_yaml::from_value(value)
+                .map(ScriptEnv::Os)
+                .map_err(D::Error::custom),
+        }
+    }
+}
+
+// `for_each:` is a list of items, or a param / registered output (by name or as a `{{ }}`
+// template) whose value is split on commas and new lines
+#[derive(Debug, Serialize, JsonSchema, PartialEq, Eq, Hash, Clone)]
+#[serde(untagged)]
+pub enum ForEach {
+    Items(#[schemars(with = "Vec<Scalar>")] Vec<String>),
+    Source(String),
+}
+
+impl<'de> serde::Deserialize<'de> for ForEach {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        match serde_yaml::Value::deserialize(d)? {
+            serde_yaml::Value::Sequence(values) => values
+                .into_iter()
+                .map(|value| {
+                    scalar_to_string(value)
+                        .ok_or_else(|| D::Error::custom("for_each items must be scalars"))
+                })
+                .collect::<Result<_, _>>()
+                .map(ForEach::Items),
+            value => scalar_to_string(value).map(ForEach::Source).ok_or_else(|| {
+                D::Error::custom("for_each must be a list or the name of a param or output")
+            }),
+        }
+    }
+}
+
+impl StepParamModel {
+    pub fn is_required(&self) -> bool {
+        self.required.unwrap_or(true)
+    }
+
+    // check a value against the declaration. returns the normalized value
+    // (e.g. "yes" becomes "true" for bools) or a message describing the problem
+    pub fn validate(&self, value: &str) -> Result<String, String> {
+        let value = value.trim();
+
+        if value.is_empty() {
+            if self.is_required() {
+                return Err(format!("parameter '{}' is required", self.name));
+            }
+            return Ok(String::new());
+        }
+
+        let value = match self.param_type {
+            ParamType::STRING | ParamType::PATH | ParamType::ENUM => value.to_string(),
+            ParamType::INT => value
+                .parse::<i64>()
+                .map_err(|_| {
+                    format!(
+                        "parameter '{}' must be an integer, got '{}'",
+                        self.name, value
+                    )
+                })?
+                .to_string(),
+            ParamType::BOOL => match value.to_lowercase().as_str() {
+                "true" | "yes" | "y" | "1" => "true".to_string(),
+                "false" | "no" | "n" | "0" => "false".to_string(),
+                _ => {
+                    return Err(format!(
+                        "parameter '{}' must be true or false, got '{}'",
+                        self.name, value
+                    ))
+                }
+            },
+        };
+
+        if let Some(choices) = &self.choices {
+            if !choices.contains(&value) {
+                return Err(format!(
+                    "parameter '{}' must be one of [{}], got '{}'",
+                    self.name,
+                    choices.join(", "),
+                    value
+                ));
+            }
+        } else if self.param_type == ParamType::ENUM {
+            return Err(format!(
+                "parameter '{}' is an enum but declares no choices",
+                self.name
+            ));
+        }
+
+        if let Some(pattern) = &self.pattern {
+            let re = Regex::new(&format!("^(?:{})$", pattern))
+                .map_err(|e| format!("parameter '{}' has an invalid pattern: {}", self.name, e))?;
+            if !re.is_match(&value) {
+                return Err(format!(
+                    "parameter '{}' must match '{}', got '{}'",
+                    self.name, pattern, value
+                ));
+            }
+        }
+
+        Ok(value)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash, Clone)]
 pub struct StepModel {
+    /// not needed for `parallel` blocks, which don't run anything themselves (default: shell)
+    #[serde(default)]
     pub runner: StepRunnerType,
+    /// the command, script, markdown or link to run. a template: {{ param | default('x') }},
+    /// {{ env.NAME }}, {% if os == 'linux' %}..{% endif %}
     pub content: Option<String>,
+    /// read the content from this file, relative to the package
     pub file: Option<String>,
     pub title: String,
     pub params: Option<Vec<StepParamModel>>,
+    /// ask for approval before running the step
     pub approval_message: Option<String>,
+    /// condition expression, the step is skipped when it evaluates to false
+    pub when: Option<String>,
+    /// how many times to re-run the step after it fails
+    pub retries: Option<u32>,
+    /// seconds to wait before the first retry, doubled for every following retry
+    pub retry_delay: Option<u64>,
+    /// seconds after which the step is killed and counted as failed
+    pub timeout: Option<u64>,
+    /// a failure of this step doesn't stop the script or fail the run
+    pub continue_on_error: Option<bool>,
+    /// save the trimmed stdout and the exit code of the step for later steps, as {{ name }}
+    pub register: Option<String>,
+    /// environment variables for the step, override the script level ones
+    #[serde(default, deserialize_with = "deserialize_scalar_map")]
+    #[schemars(with = "Option<BTreeMap<String, Scalar>>")]
+    pub env: Option<BTreeMap<String, String>>,
+    /// working directory for the step, overrides the script level one
+    pub cwd: Option<String>,
+    /// steps to run at the same time. the env, cwd and continue_on_error of the block
+    /// are defaults for its steps
+    pub parallel: Option<Vec<StepModel>>,
+    /// how many steps of a `parallel` block run at once (default: all of them)
+    pub max_parallel: Option<usize>,
+    /// run the step once for every item, as {{ item }} and {{ index }}
+    pub for_each: Option<ForEach>,
+    /// an undefined variable in a template fails the step, overrides the script level `strict`
+    pub strict: Option<bool>,
 }
 
 impl StepModel {
@@ -55,12 +378,291 @@ impl StepModel {
             params
         }
     }
+
+    // the steps of a `parallel` block, with the block settings applied
+    pub fn parallel_steps(&self) -> Vec<StepModel> {
+        self.parallel
+            .iter()
+            .flatten()
+            .map(|step| {
+                let mut step = step.clone();
+                let mut env = self.env.clone().unwrap_or_default();
+                env.extend(step.env.unwrap_or_default());
+                step.env = Some(env).filter(|env| !env.is_empty());
+                step.cwd = match (step.cwd, &self.cwd) {
+                    (Some(cwd), Some(block_cwd))
+                        if Path::new(&cwd).is_relative() && !cwd.starts_with('~') =>
+                    {
+                        Some(Path::new(block_cwd).join(cwd).to_string_lossy().to_string())
+                    }
+                    (cwd, block_cwd) => cwd.or(block_cwd.clone()),
+                };
+                step.continue_on_error = step.continue_on_error.or(self.continue_on_error);
+                step
+            })
+            .collect()
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash, Clone)]
 pub struct ScriptModel {
     pub steps: Vec<StepModel>,
-    pub env: Option<OS>,
+    /// the os the script supports
+    pub os: Option<OS>,
+    /// environment variables for all steps. for backward compatibility, `env: <os>` works like `os:`
+    pub env: Option<ScriptEnv>,
+    /// working directory for all steps
+    pub cwd: Option<String>,
     pub description: Option<String>,
+    /// params for all steps
     pub params: Option<Vec<StepParamModel>>,
+    /// condition expression, the whole script is skipped when it evaluates to false
+    pub when: Option<String>,
+    /// stop running steps after the first failure (default: true)
+    pub fail_fast: Option<bool>,
+    /// teardown steps that run only when one of the steps failed
+    pub on_failure: Option<Vec<StepModel>>,
+    /// teardown steps that run whether or not the steps succeeded
+    pub always: Option<Vec<StepModel>>,
+    /// automations ("pkg.automation", or just "automation" in the same package) to run before this one
+    pub depends_on: Option<Vec<String>>,
+    /// an undefined variable in a template fails the step instead of being left as it is (default: false)
+    pub strict: Option<bool>,
+}
+
+impl ScriptModel {
+    pub fn supported_os(&self) -> Option<OS> {
+        match (&self.os, &self.env) {
+            (Some(os), _) => Some(*os),
+            (None, Some(ScriptEnv::Os(os))) => Some(*os),
+            _ => None,
+        }
+    }
+
+    pub fn env_vars(&self) -> BTreeMap<String, String> {
+        match &self.env {
+            Some(ScriptEnv::Vars(vars)) => vars.clone(),
+            _ => BTreeMap::new(),
+        }
+    }
+
+    // script level env with the step level env on top
+    pub fn step_env_vars(&self, step: &StepModel) -> BTreeMap<String, String> {
+        let mut vars = self.env_vars();
+        vars.extend(step.env.clone().unwrap_or_default());
+        vars
+    }
+
+    // a relative step cwd is relative to the script cwd
+    pub fn step_cwd(&self, step: &StepModel) -> Option<String> {
+        match (&step.cwd, &self.cwd) {
+            (Some(step_cwd), Some(script_cwd))
+                if Path::new(step_cwd).is_relative() && !step_cwd.starts_with('~') =>
+            {
+                Some(
+                    Path::new(script_cwd)
+                        .join(step_cwd)
+                        .to_string_lossy()
+                        .to_string(),
+                )
+            }
+            (Some(step_cwd), _) => Some(step_cwd.clone()),
+            (None, script_cwd) => script_cwd.clone(),
+        }
+    }
+
+    // all steps, including teardown steps and the steps of `parallel` blocks
+    pub fn all_steps(&self) -> impl Iterator<Item = &StepModel> {
+        self.steps
+            .iter()
+            .chain(self.on_failure.iter().flatten())
+            .chain(self.always.iter().flatten())
+            .flat_map(|step| std::iter::once(step).chain(step.parallel.iter().flatten()))
+    }
+
+    // every param declaration of the script and its steps. a param declared in several
+    // places shows up once, with the script level declaration first
+    pub fn all_params(&self) -> Vec<StepParamModel> {
+        let mut params: Vec<StepParamModel> = self.params.clone().unwrap_or_default();
+        for step in self.all_steps() {
+            for param in step.params.iter().flatten() {
+                if !params.iter().any(|p| p.name == param.name) {
+                    params.push(param.clone());
+                }
+            }
+        }
+        params
+    }
+
+    // every declaration of every param, used to validate values given on the command line
+    pub fn param_declarations(&self) -> Vec<StepParamModel> {
+        let mut params: Vec<StepParamModel> = self.params.clone().unwrap_or_default();
+        for step in self.all_steps() {
+            params.extend(step.params.iter().flatten().cloned());
+        }
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn param(param_type: ParamType) -> StepParamModel {
+        StepParamModel {
+            name: "p".to_string(),
+            param_type,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_validate_param() {
+        assert_eq!(param(ParamType::STRING).validate(" abc "), Ok("abc".into()));
+        assert!(param(ParamType::STRING).validate("").is_err());
+
+        let optional = StepParamModel {
+            required: Some(false),
+            ..param(ParamType::INT)
+        };
+        assert_eq!(optional.validate(""), Ok("".into()));
+
+        assert_eq!(param(ParamType::INT).validate("42"), Ok("42".into()));
+        assert!(param(ParamType::INT).validate("4.2").is_err());
+
+        assert_eq!(param(ParamType::BOOL).validate("Yes"), Ok("true".into()));
+        assert_eq!(param(ParamType::BOOL).validate("0"), Ok("false".into()));
+        assert!(param(ParamType::BOOL).validate("maybe").is_err());
+
+        let region = StepParamModel {
+            choices: Some(vec!["eu".to_string(), "us".to_string()]),
+            ..param(ParamType::ENUM)
+        };
+        assert_eq!(region.validate("eu"), Ok("eu".into()));
+        assert!(region.validate("asia").is_err());
+        assert!(param(ParamType::ENUM).validate("eu").is_err());
+
+        let version = StepParamModel {
+            pattern: Some(r"\d+\.\d+".to_string()),
+            ..param(ParamType::STRING)
+        };
+        assert_eq!(version.validate("1.2"), Ok("1.2".into()));
+        assert!(version.validate("v1.2").is_err());
+    }
+
+    #[test]
+    fn test_deserialize_param() {
+        let param: StepParamModel = serde_yaml::from_str(
+            "name: region\ntype: enum\nchoices: [eu, us]\ndefault: eu\ndescription: aws region",
+        )
+        .unwrap();
+        assert_eq!(param.param_type, ParamType::ENUM);
+        assert!(!param.save);
+        assert!(param.is_required());
+        assert_eq!(param.default, Some("eu".to_string()));
+
+        let param: StepParamModel =
+            serde_yaml::from_str("name: port\ntype: int\ndefault: 8080\nchoices: [8080, 9090]")
+                .unwrap();
+        assert_eq!(param.default, Some("8080".to_string()));
+        assert_eq!(
+            param.choices,
+            Some(vec!["8080".to_string(), "9090".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_script_env() {
+        let script: ScriptModel = serde_yaml::from_str(
+            r#"
+            env: osx
+            steps: []
+            "#,
+        )
+        .unwrap();
+        assert_eq!(script.supported_os(), Some(OS::MacOS));
+        assert!(script.env_vars().is_empty());
+
+        let script: ScriptModel = serde_yaml::from_str(
+            r#"
+            os: linux
+            env:
+              STAGE: dev
+              PORT: 8080
+            cwd: /tmp
+            steps:
+              - title: build
+                runner: shell
+                content: make
+                env:
+                  STAGE: prod
+                cwd: ./website
+            "#,
+        )
+        .unwrap();
+        assert_eq!(script.supported_os(), Some(OS::Linux));
+
+        let step = &script.steps[0];
+        let vars = script.step_env_vars(step);
+        assert_eq!(vars["STAGE"], "prod");
+        assert_eq!(vars["PORT"], "8080");
+        assert_eq!(script.step_cwd(step), Some("/tmp/./website".to_string()));
+    }
+
+    #[test]
+    fn test_deserialize_for_each() {
+        let step: StepModel =
+            serde_yaml::from_str("{title: t, content: x, for_each: [api, 8080, true]}").unwrap();
+        assert_eq!(
+            step.for_each,
+            Some(ForEach::Items(vec![
+                "api".into(),
+                "8080".into(),
+                "true".into()
+            ]))
+        );
+
+        let step: StepModel =
+            serde_yaml::from_str("{title: t, content: x, for_each: '{{ repos }}'}").unwrap();
+        assert_eq!(step.for_each, Some(ForEach::Source("{{ repos }}".into())));
+
+        assert!(serde_yaml::from_str::<StepModel>("{title: t, for_each: {a: b}}").is_err());
+    }
+
+    #[test]
+    fn test_parallel_steps() {
+        let script: ScriptModel = serde_yaml::from_str(
+            r#"
+            steps:
+              - title: toolchains
+                max_parallel: 2
+                env: {CI: "1"}
+                cwd: /tmp
+                continue_on_error: true
+                parallel:
+                  - title: rust
+                    content: rustup update
+                    env: {CI: "0"}
+                  - title: node
+                    runner: shell
+                    content: nvm install
+                    cwd: web
+                    continue_on_error: false
+            "#,
+        )
+        .unwrap();
+
+        let block = &script.steps[0];
+        assert_eq!(block.max_parallel, Some(2));
+        assert_eq!(script.all_steps().count(), 3);
+
+        let steps = block.parallel_steps();
+        assert_eq!(steps[0].runner, StepRunnerType::SHELL);
+        assert_eq!(steps[0].env.as_ref().unwrap()["CI"], "0");
+        assert_eq!(steps[0].cwd, Some("/tmp".to_string()));
+        assert_eq!(steps[0].continue_on_error, Some(true));
+        assert_eq!(steps[1].env.as_ref().unwrap()["CI"], "1");
+        assert_eq!(steps[1].cwd, Some("/tmp/web".to_string()));
+        assert_eq!(steps[1].continue_on_error, Some(false));
+    }
 }
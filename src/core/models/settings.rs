@@ -2,21 +2,55 @@ use std::collections::HashMap;
 use std::fs::{self, OpenOptions};
 use std::path::{Path, PathBuf};
 
+use anyhow::{bail, Result};
 use log::error;
+use serde_derive::{Deserialize, Serialize};
 
+use super::user_config::UserConfig;
 use crate::core::utils::io::from_yaml_file;
 use crate::core::utils::sys::get_os;
+use crate::core::vault::Vault;
 use crate::core::{consts, utils::sys::OS};
 
+// saved_params.yaml
+//   global:
+//     region: eu
+//   packages:
+//     demo:
+//       region: us
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SavedParams {
+    #[serde(default)]
+    pub global: HashMap<String, String>,
+    #[serde(default)]
+    pub packages: HashMap<String, HashMap<String, String>>,
+}
+
+// before scopes the file was a plain map of global params
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ParamsFile {
+    Scoped(SavedParams),
+    Global(HashMap<String, String>),
+}
+
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Settings {
     pub home: String,
     pub terminal: String,
     pub packages_file: PathBuf,
+    pub lock_file: PathBuf,
     pub runtime_dir: PathBuf,
     pub scan_depth: usize,
     pub os: OS,
+    // saved params of every package, global ones apply to all packages
     pub params: HashMap<String, String>,
+    pub package_params: HashMap<String, HashMap<String, String>>,
+    // secret params live here, never in params
+    pub vault: Vault,
+    // config.yaml (or --config) with the COCMD_* environment variables on top
+    pub config: UserConfig,
     // packages_manager: PackagesManager, // You'll need to define this
     // credentials: CredsConfigModel, // You'll need to define this
 }
@@ -24,9 +58,9 @@ pub struct Settings {
 impl Settings {
     pub fn new(home: Option<&str>, terminal: Option<&str>) -> Self {
         let home = home.unwrap_or(&consts::HOME);
-        let terminal = terminal.unwrap_or(consts::DEFAULT_TERMINAL);
         let runtime_dir = Path::new(home).join(consts::RUNTIME_DIR);
         let packages_file = Path::new(&home).join(consts::SOURCES_FILE);
+        let lock_file = Path::new(&home).join(consts::LOCK_FILE);
         let params_file_path = Path::new(&home).join(consts::PARAMS_FILE);
 
         // Create directories and files
@@ -44,47 +78,104 @@ impl Settings {
             .open(&packages_file)
             .unwrap();
 
+        let saved_params = Settings::read_params(params_file_path.as_path());
+        let config_file_path = Path::new(home).join(consts::CONFIG_FILE);
+        let config = UserConfig::load(&config_file_path).unwrap_or_else(|err| {
+            error!("{:#}", err);
+            UserConfig::default()
+        });
+        let terminal = terminal
+            .or(config.shell.as_deref())
+            .unwrap_or(consts::DEFAULT_TERMINAL);
+
         // Initialize other fields
         Settings {
             home: home.to_string(),
             terminal: terminal.to_string(),
             runtime_dir,
             packages_file,
-            scan_depth: 2,
+            lock_file,
+            scan_depth: config.scan_depth,
             os: get_os(), // packages_manager: PackagesManager::new(), // Initialize this
             // credentials: CredsConfigModel::new(), // Initialize this
-            params: Settings::read_params(params_file_path.as_path()),
+            params: saved_params.global,
+            package_params: saved_params.packages,
+            vault: Vault::new(Path::new(home), !config.non_interactive),
+            config,
+        }
+    }
+
+    // use another config file than the one in the cocmd home (--config)
+    pub fn load_config(&mut self, path: &Path) -> Result<()> {
+        if !path.exists() {
+            bail!("config file {} doesn't exist", path.display());
+        }
+        self.config = UserConfig::load(path)?;
+        self.scan_depth = self.config.scan_depth;
+        self.vault.interactive = !self.config.non_interactive;
+        if let Some(shell) = &self.config.shell {
+            self.terminal = shell.clone();
         }
+        Ok(())
     }
 
-    // create function 'read_params' that read from home/consts::PARAMS_FILE path yaml file and return a map of params
-    // from params, should return HashMap<String, String>
-    pub fn read_params(params_file_path: &Path) -> HashMap<String, String> {
-        let params: Result<HashMap<String, String>, String> =
+    pub fn read_params(params_file_path: &Path) -> SavedParams {
+        let params: Result<ParamsFile, String> =
             from_yaml_file(params_file_path).map_err(|e| e.to_string());
         match params {
-            Ok(params_res) => {
-                // Successfully loaded the configuration
-                // You can use 'config' here
-                params_res
-            }
+            Ok(ParamsFile::Scoped(params)) => params,
+            Ok(ParamsFile::Global(global)) => SavedParams {
+                global,
+                ..Default::default()
+            },
             Err(err) => {
                 // Handle the error, for example, log it
                 error!("{}: {}", params_file_path.to_str().unwrap(), err);
-                HashMap::new()
+                SavedParams::default()
             }
         }
     }
 
-    // get a specific param from self.params
-    pub fn get_param(&self, param_name: &str) -> Option<String> {
-        self.params.get(param_name).cloned()
+    // a saved param, the package scope comes before the global one
+    pub fn get_param(&self, param_name: &str, package: Option<&str>) -> Option<String> {
+        package
+            .and_then(|package| self.package_params.get(package))
+            .and_then(|params| params.get(param_name))
+            .or_else(|| self.params.get(param_name))
+            .cloned()
+    }
+
+    // all the saved params a package sees
+    pub fn saved_params(&self, package: Option<&str>) -> HashMap<String, String> {
+        let mut params = self.params.clone();
+        if let Some(package_params) = package.and_then(|package| self.package_params.get(package)) {
+            params.extend(package_params.clone());
+        }
+        params
+    }
+
+    // saved to home/consts::PARAMS_FILE when the settings are dropped
+    pub fn save_param(&mut self, param_name: &str, param_value: &str, package: Option<&str>) {
+        let params = match package {
+            Some(package) => self.package_params.entry(package.to_string()).or_default(),
+            None => &mut self.params,
+        };
+        params.insert(param_name.to_string(), param_value.to_string());
     }
 
-    // create a function save_param that save a param to self.params and to home/consts::PARAMS_FILE
-    pub fn save_param(&mut self, param_name: &str, param_value: &str) {
-        self.params
-            .insert(param_name.to_string(), param_value.to_string());
+    // the removed value, if there was one in this scope
+    pub fn unset_param(&mut self, param_name: &str, package: Option<&str>) -> Option<String> {
+        match package {
+            Some(package) => {
+                let params = self.package_params.get_mut(package)?;
+                let value = params.remove(param_name);
+                if params.is_empty() {
+                    self.package_params.remove(package);
+                }
+                value
+            }
+            None => self.params.remove(param_name),
+        }
     }
 }
 
@@ -95,7 +186,40 @@ impl Drop for Settings {
         let _ = fs::File::create(&params_file_path);
         let _ = fs::write(
             &params_file_path,
-            serde_yaml::to_string(&self.params).unwrap(),
+            serde_yaml::to_string(&SavedParams {
+                global: self.params.clone(),
+                packages: self.package_params.clone(),
+            })
+            .unwrap(),
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_read_params() {
+        let home = TempDir::new("cocmd").unwrap();
+        let path = home.path().join(consts::PARAMS_FILE);
+
+        // written before params had scopes
+        fs::write(&path, "region: eu\nglobal: x\n").unwrap();
+        let params = Settings::read_params(&path);
+        assert_eq!(params.global["region"], "eu");
+        assert_eq!(params.global["global"], "x");
+        assert!(params.packages.is_empty());
+
+        fs::write(
+            &path,
+            "global:\n  region: eu\npackages:\n  demo:\n    region: us\n",
+        )
+        .unwrap();
+        let params = Settings::read_params(&path);
+        assert_eq!(params.global["region"], "eu");
+        assert_eq!(params.packages["demo"]["region"], "us");
+    }
+}
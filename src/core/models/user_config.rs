@@ -0,0 +1,377 @@
+// config.yaml in the cocmd home, the user's own settings:
+//
+//   scan_depth: 3
+//   shell: zsh
+//   registries:
+//     - name: acme
+//       url: https://hub.acme.internal/package_index.json
+//       priority: 10
+//     - name: cocmd
+//       url: https://github.com/cocmd/hub/releases/latest/download/package_index.json
+//   cache_ttl: 600
+//   color: false
+//   markdown: true
+//   non_interactive: false
+//
+// every key can be overridden with a COCMD_<KEY> environment variable (e.g. COCMD_CACHE_TTL=0),
+// COCMD_REGISTRIES is `name=url,name=url`, in priority order. `hub_urls` (COCMD_HUB_URLS) is
+// the older form of registries, a list of urls. a variable with a bad value is reported and
+// ignored, the rest of the config still applies.
+//
+// --config (or COCMD_CONFIG) points to another file, nested cocmd runs get it through COCMD_CONFIG
+
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use log::error;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::package_provider::hub::COCMD_HUB_PACKAGE_INDEX_URL;
+
+pub const DEFAULT_REGISTRY: &str = "cocmd";
+
+// a hub, its packages are `name:package` when they have to come from it
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Registry {
+    pub name: String,
+    // the package_index.json, or a file:// directory that has one
+    pub url: String,
+    // a package is taken from the registry with the highest priority that has it, registries
+    // with the same priority in the order they're listed
+    #[serde(default)]
+    pub priority: i64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserConfig {
+    // how deep `cocmd install <dir>` looks for cocmd.yaml files
+    pub scan_depth: usize,
+    // the shell steps and aliases run in, $SHELL when not set
+    pub shell: Option<String>,
+    pub registries: Vec<Registry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hub_urls: Option<Vec<String>>,
+    // seconds the hub index is used before it's fetched again
+    pub cache_ttl: u64,
+    pub color: bool,
+    // when false, output is printed as plain markdown text
+    pub markdown: bool,
+    // never ask: confirmations are accepted and params without a value take their default
+    pub non_interactive: bool,
+}
+
+impl Default for UserConfig {
+    fn default() -> Self {
+        UserConfig {
+            scan_depth: 2,
+            shell: None,
+            registries: vec![Registry {
+                name: DEFAULT_REGISTRY.to_string(),
+                url: COCMD_HUB_PACKAGE_INDEX_URL.to_string(),
+                priority: 0,
+            }],
+            hub_urls: None,
+            cache_ttl: 60 * 60,
+            color: true,
+            markdown: true,
+            non_interactive: false,
+        }
+    }
+}
+
+// looks an environment variable up, std::env::var for real and a fixed set in tests
+type Env<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+fn env_value<T: FromStr>(env: Env, name: &str) -> Option<T> {
+    let value = env(name).filter(|value| !value.trim().is_empty())?;
+    match value.trim().parse() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            ignore_env(name, &value, "not a valid value");
+            None
+        }
+    }
+}
+
+fn env_bool(env: Env, name: &str) -> Option<bool> {
+    let value = env_value::<String>(env, name)?;
+    match value.to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => {
+            ignore_env(name, &value, "not a valid value, use true or false");
+            None
+        }
+    }
+}
+
+fn ignore_env(name: &str, value: &str, reason: &str) {
+    error!("ignoring {}={}: {}", name, value, reason);
+}
+
+// COCMD_REGISTRIES, `name=url,name=url`
+fn env_registries(list: &str) -> Result<Vec<Registry>> {
+    let registries = split_list(list)
+        .map(|registry| match registry.split_once('=') {
+            Some((name, url)) => Ok(Registry {
+                name: name.trim().to_string(),
+                url: url.trim().to_string(),
+                priority: 0,
+            }),
+            None => bail!("not a list of name=url"),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let config = UserConfig {
+        registries,
+        ..Default::default()
+    };
+    config.check_registries()?;
+    Ok(config.registries)
+}
+
+impl UserConfig {
+    // the file, with the environment on top. a missing file means the defaults
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::load_with_env(path, &|name| std::env::var(name).ok())
+    }
+
+    fn load_with_env(path: &Path, env: Env) -> Result<Self> {
+        let mut config = if path.exists() {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("unable to read {}", path.display()))?;
+            if content.trim().is_empty() {
+                UserConfig::default()
+            } else {
+                let config: UserConfig = serde_yaml::from_str(&content)
+                    .with_context(|| format!("{} is not a valid cocmd config", path.display()))?;
+                if config.hub_urls.is_some()
+                    && config.registries != UserConfig::default().registries
+                {
+                    bail!(
+                        "{} has both registries and hub_urls, hub_urls is the older form of \
+                         registries",
+                        path.display()
+                    );
+                }
+                config
+            }
+        } else {
+            UserConfig::default()
+        };
+        config.apply_env(env);
+        config.check_registries()?;
+        Ok(config)
+    }
+
+    fn apply_env(&mut self, env: Env) {
+        if let Some(scan_depth) = env_value(env, "COCMD_SCAN_DEPTH") {
+            self.scan_depth = scan_depth;
+        }
+        if let Some(shell) = env_value(env, "COCMD_SHELL") {
+            self.shell = Some(shell);
+        }
+        if let Some(registries) = env_value::<String>(env, "COCMD_REGISTRIES") {
+            match env_registries(&registries) {
+                Ok(env_registries) => {
+                    self.hub_urls = None;
+                    self.registries = env_registries;
+                }
+                Err(e) => ignore_env("COCMD_REGISTRIES", &registries, &format!("{:#}", e)),
+            }
+        }
+        if let Some(hub_urls) = env_value::<String>(env, "COCMD_HUB_URLS") {
+            let hub_urls: Vec<String> = split_list(&hub_urls).map(String::from).collect();
+            if !hub_urls.is_empty() {
+                self.hub_urls = Some(hub_urls);
+            }
+        }
+        if let Some(cache_ttl) = env_value(env, "COCMD_CACHE_TTL") {
+            self.cache_ttl = cache_ttl;
+        }
+        // https://no-color.org
+        if env("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            self.color = false;
+        }
+        if let Some(color) = env_bool(env, "COCMD_COLOR") {
+            self.color = color;
+        }
+        if let Some(markdown) = env_bool(env, "COCMD_MARKDOWN") {
+            self.markdown = markdown;
+        }
+        if let Some(non_interactive) = env_bool(env, "COCMD_NON_INTERACTIVE") {
+            self.non_interactive = non_interactive;
+        }
+        if let Some(hub_urls) = self.hub_urls.take() {
+            self.registries = hub_urls
+                .into_iter()
+                .enumerate()
+                .map(|(i, url)| Registry {
+                    name: if i == 0 {
+                        DEFAULT_REGISTRY.to_string()
+                    } else {
+                        format!("{}{}", DEFAULT_REGISTRY, i + 1)
+                    },
+                    url,
+                    priority: 0,
+                })
+                .collect();
+        }
+    }
+
+    fn check_registries(&self) -> Result<()> {
+        if self.registries.is_empty() {
+            bail!("at least one registry is needed");
+        }
+        for (i, registry) in self.registries.iter().enumerate() {
+            if registry.name.is_empty()
+                || !registry
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                bail!(
+                    "'{}' is not a valid registry name, use letters, digits, - and _",
+                    registry.name
+                );
+            }
+            if self.registries[..i].iter().any(|r| r.name == registry.name) {
+                bail!("registry '{}' is configured twice", registry.name);
+            }
+        }
+        Ok(())
+    }
+
+    // the registries a package is looked up in, in this order
+    pub fn registries_by_priority(&self) -> Vec<Registry> {
+        let mut registries = self.registries.clone();
+        registries.sort_by_key(|registry| std::cmp::Reverse(registry.priority));
+        registries
+    }
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    // the tests don't see the COCMD_* variables of whoever runs them
+    fn no_env(_: &str) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn test_load_config() {
+        let home = TempDir::new("cocmd").unwrap();
+        let path = home.path().join("config.yaml");
+
+        let config = UserConfig::load_with_env(&path, &no_env).unwrap();
+        assert_eq!(config.scan_depth, 2);
+        assert_eq!(config.registries.len(), 1);
+        assert_eq!(config.registries[0].name, DEFAULT_REGISTRY);
+        assert_eq!(config.registries[0].url, COCMD_HUB_PACKAGE_INDEX_URL);
+
+        std::fs::write(
+            &path,
+            "scan_depth: 4\nshell: zsh\nhub_urls: [https://a, https://b]\ncache_ttl: 0\nnon_interactive: true\n",
+        )
+        .unwrap();
+        let config = UserConfig::load_with_env(&path, &no_env).unwrap();
+        assert_eq!(config.scan_depth, 4);
+        assert_eq!(config.shell.as_deref(), Some("zsh"));
+        let urls: Vec<&str> = config.registries.iter().map(|r| r.url.as_str()).collect();
+        assert_eq!(urls, vec!["https://a", "https://b"]);
+        assert_eq!(config.registries[1].name, "cocmd2");
+        assert_eq!(config.cache_ttl, 0);
+        assert!(config.non_interactive);
+
+        std::fs::write(&path, "scan_dpeth: 4\n").unwrap();
+        let error = format!(
+            "{:#}",
+            UserConfig::load_with_env(&path, &no_env).unwrap_err()
+        );
+        assert!(error.contains("unknown field `scan_dpeth`"), "{}", error);
+    }
+
+    #[test]
+    fn test_registries() {
+        let home = TempDir::new("cocmd").unwrap();
+        let path = home.path().join("config.yaml");
+
+        std::fs::write(
+            &path,
+            "registries:\n  - name: cocmd\n    url: https://public\n  - name: acme\n    url: file:///srv/hub\n    priority: 10\n",
+        )
+        .unwrap();
+        let config = UserConfig::load_with_env(&path, &no_env).unwrap();
+        let names: Vec<String> = config
+            .registries_by_priority()
+            .into_iter()
+            .map(|r| r.name)
+            .collect();
+        assert_eq!(names, vec!["acme", "cocmd"]);
+
+        std::fs::write(
+            &path,
+            "registries:\n  - name: acme\n    url: https://a\n  - name: acme\n    url: https://b\n",
+        )
+        .unwrap();
+        let error = UserConfig::load_with_env(&path, &no_env)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(error, "registry 'acme' is configured twice");
+
+        std::fs::write(&path, "registries:\n  - name: a:b\n    url: https://a\n").unwrap();
+        assert!(UserConfig::load_with_env(&path, &no_env).is_err());
+    }
+
+    #[test]
+    fn test_env() {
+        let home = TempDir::new("cocmd").unwrap();
+        let path = home.path().join("config.yaml");
+        std::fs::write(&path, "scan_depth: 4\ncolor: true\n").unwrap();
+
+        let env = |name: &str| match name {
+            "COCMD_SCAN_DEPTH" => Some("6".to_string()),
+            "COCMD_REGISTRIES" => Some("acme=https://acme, cocmd=https://public".to_string()),
+            "NO_COLOR" => Some("1".to_string()),
+            "COCMD_NON_INTERACTIVE" => Some("yes".to_string()),
+            _ => None,
+        };
+        let config = UserConfig::load_with_env(&path, &env).unwrap();
+        assert_eq!(config.scan_depth, 6);
+        let names: Vec<&str> = config.registries.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["acme", "cocmd"]);
+        assert!(!config.color);
+        assert!(config.non_interactive);
+
+        // a bad variable is ignored, the file and the other variables still apply
+        let env = |name: &str| match name {
+            "COCMD_COLOR" => Some("maybe".to_string()),
+            "COCMD_SCAN_DEPTH" => Some("deep".to_string()),
+            "COCMD_REGISTRIES" => Some("acme=https://acme, acme=https://other".to_string()),
+            "COCMD_HUB_URLS" => Some(",".to_string()),
+            "COCMD_NON_INTERACTIVE" => Some("yes".to_string()),
+            _ => None,
+        };
+        let config = UserConfig::load_with_env(&path, &env).unwrap();
+        assert_eq!(config.scan_depth, 4);
+        assert!(config.color);
+        assert_eq!(config.registries, UserConfig::default().registries);
+        assert!(config.non_interactive);
+        let env = |name: &str| (name == "COCMD_REGISTRIES").then(|| "acme".to_string());
+        assert_eq!(
+            UserConfig::load_with_env(&path, &env).unwrap().registries,
+            UserConfig::default().registries
+        );
+    }
+}
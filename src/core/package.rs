@@ -9,9 +9,10 @@ use log::{error, warn};
 
 use super::utils::io::exists;
 use crate::core::consts;
+use crate::core::models::migrations::load_package_config;
 use crate::core::models::package_config_model::Automation;
 use crate::core::models::package_config_model::PackageConfigModel;
-use crate::core::utils::io::{from_yaml_file, normalize_path};
+use crate::core::utils::io::normalize_path;
 use crate::core::utils::sys::OS;
 use crate::output::print_md;
 use crate::Settings;
@@ -35,17 +36,17 @@ impl Package {
             let config_file_path = Path::new(&package.location).join(consts::SOURCE_CONFIG_FILE);
 
             if config_file_path.exists() {
-                let config: Result<PackageConfigModel, String> =
-                    from_yaml_file(&config_file_path).map_err(|e| e.to_string());
-                match config {
+                // incompatible packages are refused, older layouts are migrated
+                match load_package_config(&config_file_path) {
                     Ok(config_res) => {
-                        // Successfully loaded the configuration
-                        // You can use 'config' here
                         package.cocmd_config = Some(config_res);
                     }
                     Err(err) => {
-                        // Handle the error, for example, log it
-                        error!("{}: {}", config_file_path.to_str().unwrap(), err);
+                        error!(
+                            "{:#}\nrun `cocmd validate {}` for the details",
+                            err,
+                            package.location.display()
+                        );
                     }
                 };
             } else {
@@ -68,9 +69,7 @@ impl Package {
             let config_file_path = Path::new(&self.location).join(consts::SOURCE_CONFIG_FILE);
 
             if config_file_path.exists() {
-                let config: Result<PackageConfigModel, String> =
-                    from_yaml_file(&config_file_path).map_err(|e| e.to_string());
-                config.is_ok()
+                load_package_config(&config_file_path).is_ok()
             } else {
                 false
             }
@@ -104,6 +103,13 @@ impl Package {
             })
     }
 
+    pub fn requirements(&self) -> Vec<(String, Option<String>)> {
+        self.cocmd_config
+            .as_ref()
+            .map(|config| config.requirements())
+            .unwrap_or_default()
+    }
+
     pub fn paths(&self, absolute: bool) -> Vec<String> {
         match &self.cocmd_config {
             Some(config) => {
@@ -165,7 +171,12 @@ impl Package {
             output += "| --- | --- | --- | --- |\n";
 
             for automation in &automations {
-                let env = &automation.content.as_ref().unwrap().env.unwrap_or(OS::Any);
+                let env = &automation
+                    .content
+                    .as_ref()
+                    .unwrap()
+                    .supported_os()
+                    .unwrap_or(OS::Any);
                 let package_name = self.name();
 
                 output += &format!(
@@ -181,6 +192,32 @@ impl Package {
                 );
             }
             output += "\n";
+
+            for automation in &automations {
+                let params = automation.content.as_ref().unwrap().all_params();
+                if params.is_empty() {
+                    continue;
+                }
+
+                output += &format!("### {}.{} params\n", self.name(), automation.name);
+                output += "| param | type | default | required | description |\n";
+                output += "| --- | --- | --- | --- | --- |\n";
+                for param in &params {
+                    let param_type = match &param.choices {
+                        Some(choices) => format!("{} ({})", param.param_type, choices.join("/")),
+                        None => param.param_type.to_string(),
+                    };
+                    output += &format!(
+                        "| `{}` | {} | {} | {} | {} |\n",
+                        param.name,
+                        param_type,
+                        param.default.as_deref().unwrap_or(""),
+                        if param.is_required() { "yes" } else { "no" },
+                        param.description.as_deref().unwrap_or("")
+                    );
+                }
+                output += "\n";
+            }
         }
 
         if let Some(alias) = &self.aliases() {
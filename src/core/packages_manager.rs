@@ -1,35 +1,70 @@
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
+use anyhow::Result;
 use log::error;
 
 use super::utils::packages::extract_package_name_and_version;
+use crate::core::lockfile::{LockedPackage, Lockfile};
 use crate::core::models::package_config_model::Automation;
 use crate::core::package::Package;
 use crate::core::utils::io::{file_read_lines, file_write_lines};
-use crate::package_provider::get_provider;
+use crate::core::utils::repository::find_project_dir;
+use crate::package_provider::{get_provider, PackageProvider};
 use crate::Settings;
 
+// the automations of the project we stand in are `./name`
+pub const PROJECT_PREFIX: &str = "./";
+
 #[derive(Debug, Clone)]
 pub struct PackagesManager {
     pub settings: Settings,
     pub packages_file: PathBuf,
     pub packages: HashMap<String, Package>,
+    // packages.txt lines whose provider can't be built now (a registry that's gone), with the
+    // reason. they stay in the file
+    pub unresolved: Vec<(String, String)>,
+    // the nearest cocmd.yaml up from the cwd, usable without installing it
+    pub project: Option<Package>,
 }
 
 impl PackagesManager {
     pub fn new(settings: Settings) -> Self {
         let packages_file = settings.packages_file.clone();
-        let packages = Self::load_packages(&packages_file, &settings);
+        let (packages, unresolved) = Self::load_packages(&packages_file, &settings);
         Self {
             settings,
             packages_file,
             packages,
+            unresolved,
+            project: None,
         }
     }
 
     pub fn reload(&mut self) {
-        self.packages = Self::load_packages(&self.packages_file, &self.settings)
+        (self.packages, self.unresolved) = Self::load_packages(&self.packages_file, &self.settings);
+        if let Some(project) = &self.project {
+            let location = project.location.clone();
+            self.project = Some(Self::load_project_package(&location, &self.settings));
+        }
+    }
+
+    // look for a project from `cwd` up, for run, show and browse
+    pub fn load_project(&mut self, cwd: &Path) {
+        self.project = find_project_dir(cwd)
+            .map(|location| Self::load_project_package(&location, &self.settings));
+    }
+
+    fn load_project_package(location: &Path, settings: &Settings) -> Package {
+        Package::new(location.to_string_lossy().to_string(), location, settings)
+    }
+
+    // `./name` -> `project.name`, other keys as they are
+    pub fn automation_key(&self, key: &str) -> String {
+        match (key.strip_prefix(PROJECT_PREFIX), &self.project) {
+            (Some(name), Some(project)) => format!("{}.{}", project.name(), name),
+            _ => key.to_string(),
+        }
     }
 
     pub fn remove_package(&mut self, package_name: &str) -> Result<(), String> {
@@ -42,8 +77,7 @@ impl PackagesManager {
 
         if let Some(uri) = package_uri {
             // Get the provider
-            let provider =
-                get_provider(&uri, &self.settings.runtime_dir, None).map_err(|e| e.to_string())?;
+            let provider = get_provider(&uri, &self.settings, None).map_err(|e| e.to_string())?;
 
             self.packages.remove(&uri);
 
@@ -64,12 +98,43 @@ impl PackagesManager {
 
             // Update the packages.txt file regardless of provider type
             self.save()
-                .map_err(|e| format!("Failed to update packages file: {}", e))
+                .map_err(|e| format!("Failed to update packages file: {}", e))?;
+            self.unlock_package(package_name)
+                .map_err(|e| format!("Failed to update lock file: {:#}", e))
         } else {
             Err(format!("Package '{}' not found.", package_name))
         }
     }
 
+    // record what was installed for `package` in cocmd.lock
+    pub fn lock_package(&self, package: &Package, provider: &dyn PackageProvider) -> Result<()> {
+        let mut lockfile = Lockfile::load(&self.settings.lock_file)?;
+        lockfile.insert(LockedPackage {
+            name: package.name().to_string(),
+            uri: package.uri.clone(),
+            ..provider.lock()?
+        });
+        lockfile.save(&self.settings.lock_file)
+    }
+
+    // the version the package was installed with, when it was given one
+    pub fn locked_constraint(&self, package_name: &str) -> Option<String> {
+        Lockfile::load(&self.settings.lock_file)
+            .ok()?
+            .packages
+            .into_iter()
+            .find(|locked| locked.name == package_name)
+            .and_then(|locked| locked.constraint)
+    }
+
+    fn unlock_package(&self, package_name: &str) -> Result<()> {
+        let mut lockfile = Lockfile::load(&self.settings.lock_file)?;
+        if lockfile.remove(package_name) {
+            lockfile.save(&self.settings.lock_file)?;
+        }
+        Ok(())
+    }
+
     pub fn add_package(&mut self, package: Package) {
         self.packages.insert(package.uri.clone(), package);
         self.save();
@@ -77,8 +142,13 @@ impl PackagesManager {
 
     pub fn save(&mut self) -> Result<(), String> {
         // Convert the HashMap into a Vec of package URIs
-        let package_strings: Vec<String> =
+        let mut package_strings: Vec<String> =
             self.packages.values().map(|s| s.uri.to_string()).collect();
+        for (uri, _) in &self.unresolved {
+            if !self.packages.contains_key(uri) {
+                package_strings.push(uri.clone());
+            }
+        }
 
         // Write the updated list of packages back to the packages.txt file
         match file_write_lines(&self.packages_file, &package_strings) {
@@ -93,46 +163,78 @@ impl PackagesManager {
         }
     }
 
-    fn load_packages(packages_file: &Path, settings: &Settings) -> HashMap<String, Package> {
+    fn load_packages(
+        packages_file: &Path,
+        settings: &Settings,
+    ) -> (HashMap<String, Package>, Vec<(String, String)>) {
         match file_read_lines(packages_file) {
             Ok(lines) => {
                 let mut packages = HashMap::new();
+                let mut unresolved = vec![];
                 for line in lines {
                     let uri = line.trim().to_string();
 
                     let (package_uri, version) = extract_package_name_and_version(&uri);
-                    let provider = get_provider(&package_uri, &settings.runtime_dir, version);
-                    if let Err(err) = provider {
-                        error!("failed to get location for {} - {}", uri, err);
-                        continue;
-                    }
+                    let provider = match get_provider(&package_uri, settings, version) {
+                        Ok(provider) => provider,
+                        Err(err) => {
+                            error!("failed to get location for {} - {:#}", uri, err);
+                            unresolved.push((uri, format!("{:#}", err)));
+                            continue;
+                        }
+                    };
 
-                    let package =
-                        Package::new(uri.clone(), &provider.unwrap().local_path(), settings);
+                    let package = Package::new(uri.clone(), &provider.local_path(), settings);
 
                     packages.insert(package.uri.clone(), package);
                 }
-                packages
+                (packages, unresolved)
             }
             Err(err) => {
                 error!("failed reading {:?} - {}", packages_file, err);
-                HashMap::new()
+                (HashMap::new(), vec![])
             }
         }
     }
 
+    // the project's automations are there as `./name` and under the project name, where they
+    // take the place of an installed package with the same name
     pub fn automations(&self) -> HashMap<String, Automation> {
         let mut automations = HashMap::new();
         for (_name, package) in self.packages.iter() {
+            if self
+                .project
+                .as_ref()
+                .is_some_and(|project| project.name() == package.name())
+            {
+                continue;
+            }
             for automation in package.automations(&self.settings, Some(true)) {
                 let key = format!("{}.{}", package.name(), automation.name);
                 automations.insert(key, automation);
             }
         }
+        if let Some(project) = &self.project {
+            for automation in project.automations(&self.settings, Some(true)) {
+                automations.insert(
+                    format!("{}{}", PROJECT_PREFIX, automation.name),
+                    automation.clone(),
+                );
+                automations.insert(
+                    format!("{}.{}", project.name(), automation.name),
+                    automation,
+                );
+            }
+        }
         automations
     }
 
     pub fn get_package(&self, uri: String) -> Option<&Package> {
+        if let Some(project) = &self.project {
+            if uri == "." || uri == project.name() || uri == project.uri {
+                return Some(project);
+            }
+        }
         let mut id = uri.clone();
         if !self.packages.contains_key(&uri) {
             // look for packages .name() value and compare with uri. if yes, uri should be the package.uri
@@ -156,3 +258,74 @@ impl PackagesManager {
         self.packages.get(&id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::cmd::add::install_package;
+
+    fn write_package(dir: &Path, name: &str, automation: &str) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(
+            dir.join("cocmd.yaml"),
+            format!(
+                "name: {}\nautomations:\n  - name: {}\n    content:\n      steps:\n        - title: s\n          runner: shell\n          content: echo {}\n",
+                name,
+                automation,
+                dir.display()
+            ),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_project_automations() {
+        let root = TempDir::new("cocmd").unwrap();
+        let home = root.path().join("home");
+        let installed = root.path().join("installed");
+        let project = root.path().join("project");
+        write_package(&installed, "demo", "build");
+        write_package(&project, "demo", "build");
+        fs::create_dir_all(project.join("src")).unwrap();
+
+        let mut packages_manager = PackagesManager::new(Settings::new(home.to_str(), None));
+        install_package(&mut packages_manager, installed.to_str().unwrap(), true).unwrap();
+        packages_manager.load_project(&project.join("src"));
+        packages_manager.reload();
+
+        let content = |key: &str| content_of(&packages_manager, key);
+        // the project wins over the installed package with the same name
+        assert_eq!(content("./build"), format!("echo {}", project.display()));
+        assert_eq!(content("demo.build"), format!("echo {}", project.display()));
+        assert_eq!(packages_manager.automation_key("./build"), "demo.build");
+        assert_eq!(
+            packages_manager
+                .get_package(".".to_string())
+                .unwrap()
+                .location,
+            project
+        );
+
+        packages_manager.load_project(root.path());
+        assert!(packages_manager.project.is_none());
+        assert_eq!(
+            content_of(&packages_manager, "demo.build"),
+            format!("echo {}", installed.display())
+        );
+    }
+
+    fn content_of(packages_manager: &PackagesManager, key: &str) -> String {
+        packages_manager.automations()[key]
+            .content
+            .as_ref()
+            .unwrap()
+            .steps[0]
+            .content
+            .clone()
+            .unwrap()
+    }
+}
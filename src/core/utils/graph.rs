@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+
+// order nodes so every node comes after its dependencies (depth first, post order).
+// each node shows up once, and a cycle is an error that shows the path, e.g. "a -> b -> a"
+pub fn topological_order<F>(roots: &[String], mut dependencies: F) -> Result<Vec<String>>
+where
+    F: FnMut(&str) -> Result<Vec<String>>,
+{
+    fn visit<F>(
+        node: &str,
+        dependencies: &mut F,
+        path: &mut Vec<String>,
+        done: &mut HashSet<String>,
+        order: &mut Vec<String>,
+    ) -> Result<()>
+    where
+        F: FnMut(&str) -> Result<Vec<String>>,
+    {
+        if done.contains(node) {
+            return Ok(());
+        }
+        if let Some(pos) = path.iter().position(|n| n == node) {
+            let mut cycle = path[pos..].to_vec();
+            cycle.push(node.to_string());
+            bail!("dependency cycle detected: {}", cycle.join(" -> "));
+        }
+
+        path.push(node.to_string());
+        for dependency in dependencies(node)? {
+            visit(&dependency, dependencies, path, done, order)?;
+        }
+        path.pop();
+
+        done.insert(node.to_string());
+        order.push(node.to_string());
+        Ok(())
+    }
+
+    let mut path = vec![];
+    let mut done = HashSet::new();
+    let mut order = vec![];
+    for root in roots {
+        visit(root, &mut dependencies, &mut path, &mut done, &mut order)?;
+    }
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn order(graph: &[(&str, &[&str])], root: &str) -> Result<Vec<String>> {
+        let graph: HashMap<String, Vec<String>> = graph
+            .iter()
+            .map(|(node, deps)| {
+                (
+                    node.to_string(),
+                    deps.iter().map(|d| d.to_string()).collect(),
+                )
+            })
+            .collect();
+        topological_order(&[root.to_string()], |node| {
+            Ok(graph.get(node).cloned().unwrap_or_default())
+        })
+    }
+
+    #[test]
+    fn test_topological_order() {
+        let graph: &[(&str, &[&str])] = &[
+            ("env", &["clone"]),
+            ("clone", &["git", "node", "docker"]),
+            ("node", &["git"]),
+        ];
+        assert_eq!(
+            order(graph, "env").unwrap(),
+            vec!["git", "node", "docker", "clone", "env"]
+        );
+        assert_eq!(order(graph, "git").unwrap(), vec!["git"]);
+    }
+
+    #[test]
+    fn test_topological_order_cycle() {
+        let graph: &[(&str, &[&str])] = &[("a", &["b"]), ("b", &["c"]), ("c", &["a"])];
+        let err = order(graph, "a").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "dependency cycle detected: a -> b -> c -> a"
+        );
+    }
+}
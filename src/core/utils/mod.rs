@@ -1,5 +1,9 @@
 pub mod cmd;
+pub mod graph;
 pub mod io;
+pub mod names;
 pub mod packages;
 pub mod repository;
+pub mod spanned_yaml;
 pub mod sys;
+pub mod version;
@@ -0,0 +1,69 @@
+// enums that accept several spellings of every variant. serde aliases don't make it to the json
+// schema, so the spellings are listed once and both deserializing and the schema use the list
+use std::fmt;
+
+use schemars::{json_schema, Schema};
+use serde::de::{self, Visitor};
+use serde::Deserializer;
+
+pub fn deserialize_named<'de, D: Deserializer<'de>, T: Clone>(
+    d: D,
+    names: &'static [(&'static str, T)],
+) -> Result<T, D::Error> {
+    // a visitor, so that an unknown name is reported where it is
+    struct NamedVisitor<T: 'static>(&'static [(&'static str, T)]);
+
+    impl<T: Clone> Visitor<'_> for NamedVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "one of {}", spellings(self.0).join(", "))
+        }
+
+        fn visit_str<E: de::Error>(self, name: &str) -> Result<T, E> {
+            self.0
+                .iter()
+                .find(|(spelling, _)| *spelling == name)
+                .map(|(_, value)| value.clone())
+                .ok_or_else(|| {
+                    E::custom(format!(
+                        "unknown variant `{}`, expected one of {}",
+                        name,
+                        spellings(self.0).join(", ")
+                    ))
+                })
+        }
+    }
+
+    d.deserialize_str(NamedVisitor(names))
+}
+
+fn spellings<T>(names: &[(&'static str, T)]) -> Vec<&'static str> {
+    names.iter().map(|(spelling, _)| *spelling).collect()
+}
+
+pub fn named_schema<T>(names: &[(&'static str, T)]) -> Schema {
+    json_schema!({
+        "type": "string",
+        "enum": spellings(names)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_named() {
+        const NAMES: &[(&str, u8)] = &[("one", 1), ("One", 1), ("two", 2)];
+        let parse = |yaml: &str| deserialize_named(serde_yaml::Deserializer::from_str(yaml), NAMES);
+        assert_eq!(parse("One").unwrap(), 1);
+        assert_eq!(parse("two").unwrap(), 2);
+        let err = parse("three").unwrap_err().to_string();
+        assert!(err.contains("expected one of one, One, two"), "{}", err);
+        assert_eq!(
+            named_schema(NAMES).as_value()["enum"],
+            serde_json::json!(["one", "One", "two"])
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use crate::core::packages_manager::PackagesManager;
+use crate::core::utils::version::is_version_constraint;
 
 pub fn get_all_paths(packages_manager: &PackagesManager) -> Vec<String> {
     packages_manager
@@ -20,21 +21,19 @@ pub fn get_package_name_from_uri(playbook_uri: &str) -> String {
     playbook_uri.split('.').next().unwrap().to_string()
 }
 
+// `name@constraint`. the `@` of urls like `git@github.com:org/repo` isn't a version separator
 pub fn extract_package_name_and_version(package: &str) -> (String, Option<String>) {
-    let mut package_name = package.to_string();
-    let mut package_version = None;
-
-    if let Some(version_index) = package_name.find('@') {
-        package_version = Some(package_name.split_off(version_index + 1));
-        package_name.pop();
+    if let Some((name, version)) = package.rsplit_once('@') {
+        if is_version_constraint(version) {
+            return (name.to_string(), Some(version.trim().to_string()));
+        }
     }
 
-    (package_name, package_version)
+    (package.to_string(), None)
 }
 
 // write a test for extract_package_name_and_version
 #[cfg(test)]
-
 mod tests {
     use super::*;
 
@@ -49,5 +48,19 @@ mod tests {
         let (package_name, package_version) = extract_package_name_and_version(package);
         assert_eq!(package_name, "cocmd");
         assert_eq!(package_version, None);
+
+        let (package_name, package_version) = extract_package_name_and_version("docker@>=1, <2");
+        assert_eq!(package_name, "docker");
+        assert_eq!(package_version, Some(">=1, <2".to_string()));
+
+        for package in [
+            "git@github.com:mzsrtgzr2/cocmd.git",
+            "git@192.168.1.10:group/repo.git",
+            "ssh://git@10.0.0.5:2222/g/r.git",
+        ] {
+            let (package_name, package_version) = extract_package_name_and_version(package);
+            assert_eq!(package_name, package);
+            assert_eq!(package_version, None);
+        }
     }
 }
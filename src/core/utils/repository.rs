@@ -41,3 +41,36 @@ pub fn find_cocmd_files(package_label: &Path, scan_depth: usize) -> Vec<String>
 
     result
 }
+
+// the nearest directory with a cocmd.yaml, from `start` up to the root. the way
+// find_cocmd_files looks down, but for the project we stand in
+pub fn find_project_dir(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| dir.join(consts::SOURCE_CONFIG_FILE).is_file())
+        .map(Path::to_path_buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_find_project_dir() {
+        let root = TempDir::new("cocmd").unwrap();
+        let project = root.path().join("project");
+        let nested = project.join("src").join("deep");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(project.join(consts::SOURCE_CONFIG_FILE), "name: project\n").unwrap();
+
+        assert_eq!(find_project_dir(&nested), Some(project.clone()));
+        assert_eq!(find_project_dir(&project), Some(project.clone()));
+        assert_eq!(find_project_dir(root.path()), None);
+        assert_eq!(
+            find_cocmd_files(root.path(), 2),
+            vec![project.to_string_lossy().to_string()]
+        );
+    }
+}
@@ -0,0 +1,216 @@
+// a yaml tree that remembers where every node is in the source, to point at problems in a file.
+// serde_yaml only knows the location of the error it stopped at
+
+use std::collections::HashMap;
+
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::{Marker, TScalarStyle};
+
+// 1-based, like editors and compilers show it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn start() -> Self {
+        Position { line: 1, column: 1 }
+    }
+
+    fn from_marker(marker: &Marker) -> Self {
+        Position {
+            line: marker.line(),
+            column: marker.col() + 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Scalar(String),
+    Sequence(Vec<Spanned>),
+    Mapping(Vec<(Spanned, Spanned)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned {
+    pub node: Node,
+    pub start: Position,
+    // how many characters to highlight, from start
+    pub len: usize,
+}
+
+impl Spanned {
+    pub fn get(&self, key: &str) -> Option<&Spanned> {
+        match &self.node {
+            Node::Mapping(entries) => entries
+                .iter()
+                .find(|(k, _)| k.as_str() == Some(key))
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn key(&self, key: &str) -> Option<&Spanned> {
+        match &self.node {
+            Node::Mapping(entries) => entries
+                .iter()
+                .find(|(k, _)| k.as_str() == Some(key))
+                .map(|(k, _)| k),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match &self.node {
+            Node::Scalar(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn items(&self) -> &[Spanned] {
+        match &self.node {
+            Node::Sequence(items) => items,
+            _ => &[],
+        }
+    }
+
+    pub fn values(&self) -> Vec<&Spanned> {
+        match &self.node {
+            Node::Mapping(entries) => entries.iter().map(|(_, value)| value).collect(),
+            _ => vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub message: String,
+    pub position: Position,
+}
+
+struct Frame {
+    node: Spanned,
+    anchor: usize,
+    key: Option<Spanned>,
+}
+
+#[derive(Default)]
+struct TreeBuilder {
+    stack: Vec<Frame>,
+    anchors: HashMap<usize, Spanned>,
+    root: Option<Spanned>,
+}
+
+impl TreeBuilder {
+    fn push_node(&mut self, node: Spanned, anchor: usize) {
+        if anchor > 0 {
+            self.anchors.insert(anchor, node.clone());
+        }
+        match self.stack.last_mut() {
+            None => {
+                // only the first document counts
+                if self.root.is_none() {
+                    self.root = Some(node);
+                }
+            }
+            Some(frame) => match &mut frame.node.node {
+                Node::Sequence(items) => items.push(node),
+                Node::Mapping(entries) => match frame.key.take() {
+                    None => frame.key = Some(node),
+                    Some(key) => entries.push((key, node)),
+                },
+                Node::Scalar(_) => unreachable!(),
+            },
+        }
+    }
+
+    fn open(&mut self, node: Node, anchor: usize, marker: &Marker) {
+        self.stack.push(Frame {
+            node: Spanned {
+                node,
+                start: Position::from_marker(marker),
+                len: 1,
+            },
+            anchor,
+            key: None,
+        });
+    }
+}
+
+impl MarkedEventReceiver for TreeBuilder {
+    fn on_event(&mut self, event: Event, marker: Marker) {
+        match event {
+            Event::Scalar(value, style, anchor, _) => {
+                let first_line = value.lines().next().unwrap_or_default().chars().count();
+                let len = match style {
+                    TScalarStyle::Plain => first_line,
+                    TScalarStyle::SingleQuoted | TScalarStyle::DoubleQuoted => first_line + 2,
+                    _ => 1,
+                };
+                let node = Spanned {
+                    node: Node::Scalar(value),
+                    start: Position::from_marker(&marker),
+                    len: len.max(1),
+                };
+                self.push_node(node, anchor);
+            }
+            Event::SequenceStart(anchor, _) => self.open(Node::Sequence(vec![]), anchor, &marker),
+            Event::MappingStart(anchor, _) => self.open(Node::Mapping(vec![]), anchor, &marker),
+            Event::SequenceEnd | Event::MappingEnd => {
+                if let Some(frame) = self.stack.pop() {
+                    self.push_node(frame.node, frame.anchor);
+                }
+            }
+            Event::Alias(anchor) => {
+                if let Some(node) = self.anchors.get(&anchor).cloned() {
+                    self.push_node(node, 0);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// None for an empty file
+pub fn load(source: &str) -> Result<Option<Spanned>, SyntaxError> {
+    let mut builder = TreeBuilder::default();
+    Parser::new_from_str(source)
+        .load(&mut builder, false)
+        .map_err(|e| SyntaxError {
+            message: e.info().to_string(),
+            position: Position::from_marker(e.marker()),
+        })?;
+    Ok(builder.root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let source = "name: demo\npaths:\n  - bin\n  - 'tools'\nbase: &base\n  x: 1\ncopy: *base\n";
+        let root = load(source).unwrap().unwrap();
+
+        let name = root.get("name").unwrap();
+        assert_eq!(name.as_str(), Some("demo"));
+        assert_eq!(name.start, Position { line: 1, column: 7 });
+        assert_eq!(root.key("name").unwrap().start, Position::start());
+
+        let paths = root.get("paths").unwrap().items();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[1].start, Position { line: 4, column: 5 });
+        assert_eq!(paths[1].len, 7);
+
+        assert_eq!(
+            root.get("copy").unwrap().get("x").unwrap().as_str(),
+            Some("1")
+        );
+        assert_eq!(load("").unwrap(), None);
+
+        let error = load("name: [demo\n").unwrap_err();
+        assert_eq!(error.position.line, 2);
+    }
+}
@@ -1,26 +1,58 @@
+use std::borrow::Cow;
 use std::fmt::Display;
 
-use serde_derive::{Deserialize, Serialize};
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use serde::Deserializer;
+use serde_derive::Serialize;
 
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Hash, Clone, Copy)]
+use crate::core::utils::names::{deserialize_named, named_schema};
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, PartialEq, Eq, Serialize, Hash, Clone, Copy)]
 pub enum OS {
-    #[serde(alias = "windows", alias = "Windows")]
     Windows,
-    #[serde(
-        alias = "linux",
-        alias = "Linux",
-        alias = "deb",
-        alias = "debian",
-        alias = "Debian"
-    )]
     Linux,
-    #[serde(alias = "osx", alias = "Mac", alias = "Macos")]
     MacOS,
     Other,
-    #[serde(alias = "any")]
     Any,
 }
 
+impl OS {
+    // every spelling cocmd.yaml can use
+    pub const NAMES: &'static [(&'static str, Self)] = &[
+        ("Windows", Self::Windows),
+        ("windows", Self::Windows),
+        ("Linux", Self::Linux),
+        ("linux", Self::Linux),
+        ("deb", Self::Linux),
+        ("debian", Self::Linux),
+        ("Debian", Self::Linux),
+        ("MacOS", Self::MacOS),
+        ("osx", Self::MacOS),
+        ("Mac", Self::MacOS),
+        ("Macos", Self::MacOS),
+        ("Other", Self::Other),
+        ("Any", Self::Any),
+        ("any", Self::Any),
+    ];
+}
+
+impl<'de> serde::Deserialize<'de> for OS {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        deserialize_named(d, Self::NAMES)
+    }
+}
+
+impl JsonSchema for OS {
+    fn schema_name() -> Cow<'static, str> {
+        "OS".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        named_schema(Self::NAMES)
+    }
+}
+
 impl Display for OS {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
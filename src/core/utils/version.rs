@@ -0,0 +1,194 @@
+use std::cmp::Ordering;
+
+use anyhow::{anyhow, Result};
+use semver::{Version, VersionReq};
+
+// a version constraint of a package spec, e.g. `docker@^1.2`.
+// `latest` / `*` / nothing - the newest release
+// `1.2.0`, `1.2`       - exactly this version (`1.2` is any 1.2.x)
+// `^1.2`, `~0.3`, `>=1,<2` and anything else semver understands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionConstraint {
+    Latest,
+    Req(VersionReq),
+}
+
+impl VersionConstraint {
+    pub fn parse(constraint: &str) -> Result<Self> {
+        let constraint = constraint.trim();
+        if matches!(constraint, "" | "*" | "latest") {
+            return Ok(VersionConstraint::Latest);
+        }
+
+        // a bare version is an exact match, not semver's default of `^`
+        let bare = constraint.trim_start_matches('v');
+        let req = if bare.starts_with(|c: char| c.is_ascii_digit()) && !bare.contains(',') {
+            format!("={}", bare)
+        } else {
+            constraint.to_string()
+        };
+        VersionReq::parse(&req)
+            .map(VersionConstraint::Req)
+            .map_err(|e| anyhow!("invalid version constraint '{}': {}", constraint, e))
+    }
+
+    pub fn matches(&self, version: &str) -> bool {
+        match (self, parse_version(version)) {
+            (VersionConstraint::Latest, _) => true,
+            (VersionConstraint::Req(req), Some(version)) => req.matches(&version),
+            (VersionConstraint::Req(_), None) => false,
+        }
+    }
+
+    // the newest of `versions` that satisfies the constraint. prereleases are only picked for
+    // `latest` when there's no release at all, and for a range when it names a prerelease itself
+    pub fn best_match<'a>(&self, versions: &[&'a str]) -> Option<&'a str> {
+        let mut candidates: Vec<&str> = versions
+            .iter()
+            .copied()
+            .filter(|version| self.matches(version))
+            .collect();
+        candidates.sort_by(|a, b| compare_versions(a, b));
+
+        if *self == VersionConstraint::Latest {
+            let release = candidates
+                .iter()
+                .rev()
+                .find(|v| parse_version(v).is_some_and(|v| v.pre.is_empty()));
+            if let Some(release) = release {
+                return Some(release);
+            }
+        }
+        candidates.last().copied()
+    }
+}
+
+// lenient version parsing: a leading `v` and missing minor / patch are fine ("v1.2" is 1.2.0)
+pub fn parse_version(version: &str) -> Option<Version> {
+    let version = version.trim().trim_start_matches('v');
+    if let Ok(version) = Version::parse(version) {
+        return Some(version);
+    }
+
+    let (core, rest) = match version.find(['-', '+']) {
+        Some(index) => version.split_at(index),
+        None => (version, ""),
+    };
+    let mut parts: Vec<&str> = core.split('.').collect();
+    if parts.is_empty() || parts.len() > 3 {
+        return None;
+    }
+    parts.resize(3, "0");
+    Version::parse(&format!("{}{}", parts.join("."), rest)).ok()
+}
+
+// semver order, with prereleases before their release. versions that aren't semver come first
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    match (parse_version(a), parse_version(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Greater,
+        (None, Some(_)) => Ordering::Less,
+        (None, None) => natord::compare(a, b),
+    }
+}
+
+// a full version like `1.2.0`, as opposed to a range
+pub fn is_exact_version(version: &str) -> bool {
+    Version::parse(version.trim().trim_start_matches('v')).is_ok()
+}
+
+// check a package version against the version of a requirement
+pub fn version_matches(constraint: &str, version: &str) -> bool {
+    VersionConstraint::parse(constraint).is_ok_and(|constraint| constraint.matches(version))
+}
+
+// does this look like the version part of `name@version` (and not like `git@github.com` or
+// `git@10.0.0.5:group/repo.git`, a version has no `:` or `/`)
+pub fn is_version_constraint(s: &str) -> bool {
+    let s = s.trim();
+    if s.contains([':', '/']) {
+        return false;
+    }
+    s == "latest"
+        || s.starts_with(|c: char| c.is_ascii_digit() || "^~=<>*".contains(c))
+        || (s.starts_with('v') && s[1..].starts_with(|c: char| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constraint_matches() {
+        let matches = |constraint: &str, version: &str| version_matches(constraint, version);
+
+        assert!(matches("1.2.0", "1.2.0"));
+        assert!(matches("v1.2.0", "1.2.0"));
+        assert!(!matches("1.2.0", "1.2.1"));
+        assert!(matches("1.2", "1.2.7"));
+        assert!(matches("^1.2", "1.9.0"));
+        assert!(!matches("^1.2", "2.0.0"));
+        assert!(matches("~0.3", "0.3.4"));
+        assert!(!matches("~0.3", "0.4.0"));
+        assert!(matches(">=1,<2", "1.5.0"));
+        assert!(matches(">=1, <2", "1.0.0"));
+        assert!(!matches(">=1,<2", "2.0.0"));
+        assert!(matches("latest", "0.0.1"));
+        assert!(matches("*", "3.0.0-beta.1"));
+        assert!(!matches("^1.2", "1.3.0-beta.1"));
+        assert!(matches(">=1.3.0-beta.1", "1.3.0-beta.2"));
+        assert!(!matches("^1.2", "not a version"));
+        assert!(VersionConstraint::parse(">=x").is_err());
+    }
+
+    #[test]
+    fn test_best_match() {
+        let versions = [
+            "0.9.0",
+            "1.10.0",
+            "1.2.0",
+            "1.9.0",
+            "2.0.0-rc.1",
+            "2.0.0-beta.2",
+            "2.0.0-beta.10",
+        ];
+        let best = |constraint: &str| {
+            VersionConstraint::parse(constraint)
+                .unwrap()
+                .best_match(&versions)
+        };
+
+        assert_eq!(best("latest"), Some("1.10.0"));
+        assert_eq!(best("^1.2"), Some("1.10.0"));
+        assert_eq!(best("~1.9"), Some("1.9.0"));
+        assert_eq!(best("<1"), Some("0.9.0"));
+        assert_eq!(best(">=2.0.0-beta.1"), Some("2.0.0-rc.1"));
+        assert_eq!(best("<2.0.0-rc.1, >=2.0.0-beta.1"), Some("2.0.0-beta.10"));
+        assert_eq!(best("^3"), None);
+
+        let prereleases = ["1.0.0-alpha", "1.0.0-beta"];
+        assert_eq!(
+            VersionConstraint::Latest.best_match(&prereleases),
+            Some("1.0.0-beta")
+        );
+    }
+
+    #[test]
+    fn test_is_version_constraint() {
+        for s in [
+            "1.2", "v1", "^1.2", "~0.3", ">=1,<2", "latest", "*", "=1.0.0",
+        ] {
+            assert!(is_version_constraint(s), "{}", s);
+        }
+        for s in [
+            "github.com:org/repo.git",
+            "gitlab.com/x",
+            "",
+            "vim",
+            "192.168.1.10:group/repo.git",
+            "10.0.0.5:2222/g/r.git",
+        ] {
+            assert!(!is_version_constraint(s), "{}", s);
+        }
+    }
+}
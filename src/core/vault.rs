@@ -0,0 +1,343 @@
+// encrypted storage for the values of `secret: true` params, kept apart from the plain
+// saved_params.yaml.
+//
+// the vault file holds the secrets as yaml, encrypted with aes-256-gcm. the key is derived
+// (pbkdf2-sha256) from, in this order:
+//   - the file in $COCMD_VAULT_KEY_FILE
+//   - ~/.cocmd/vault.key
+//   - $COCMD_VAULT_PASSPHRASE
+//   - a passphrase the user types in
+// the vault is only unlocked when a secret is actually needed.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use dialoguer::Password;
+use openssl::hash::MessageDigest;
+use openssl::pkcs5::pbkdf2_hmac;
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::core::consts;
+
+const KEY_FILE_ENV: &str = "COCMD_VAULT_KEY_FILE";
+const PASSPHRASE_ENV: &str = "COCMD_VAULT_PASSPHRASE";
+const FORMAT_VERSION: u32 = 1;
+const ITERATIONS: usize = 200_000;
+// authenticated with the secrets, so a vault can't pass for anything else
+const AAD: &[u8] = b"cocmd-vault";
+
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    iterations: usize,
+    // hex encoded
+    salt: String,
+    nonce: String,
+    tag: String,
+    data: String,
+}
+
+#[derive(PartialEq, Eq, Clone)]
+struct Unlocked {
+    key: Vec<u8>,
+    salt: Vec<u8>,
+    secrets: BTreeMap<String, String>,
+}
+
+#[derive(PartialEq, Eq, Clone)]
+pub struct Vault {
+    path: PathBuf,
+    key_file: PathBuf,
+    unlocked: Option<Unlocked>,
+    // false when the passphrase can't be asked for (non_interactive in the user config)
+    pub interactive: bool,
+}
+
+// never print the secrets
+impl fmt::Debug for Vault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Vault")
+            .field("path", &self.path)
+            .field("unlocked", &self.unlocked.is_some())
+            .finish()
+    }
+}
+
+fn derive_key(material: &[u8], salt: &[u8], iterations: usize) -> Result<Vec<u8>> {
+    let mut key = vec![0u8; 32];
+    pbkdf2_hmac(
+        material,
+        salt,
+        iterations,
+        MessageDigest::sha256(),
+        &mut key,
+    )?;
+    Ok(key)
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>> {
+    let mut bytes = vec![0u8; len];
+    rand_bytes(&mut bytes)?;
+    Ok(bytes)
+}
+
+impl Vault {
+    pub fn new(home: &Path, interactive: bool) -> Self {
+        Vault {
+            path: home.join(consts::VAULT_FILE),
+            key_file: home.join(consts::VAULT_KEY_FILE),
+            unlocked: None,
+            interactive,
+        }
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    // what the key is derived from
+    fn key_material(&self, creating: bool) -> Result<Vec<u8>> {
+        if let Ok(key_file) = std::env::var(KEY_FILE_ENV) {
+            return fs::read(&key_file).with_context(|| format!("unable to read {}", key_file));
+        }
+        if self.key_file.exists() {
+            return fs::read(&self.key_file)
+                .with_context(|| format!("unable to read {}", self.key_file.display()));
+        }
+        if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+            return Ok(passphrase.into_bytes());
+        }
+
+        if !self.interactive {
+            bail!(
+                "the vault is locked, set {} or {} (or create {})",
+                PASSPHRASE_ENV,
+                KEY_FILE_ENV,
+                self.key_file.display()
+            );
+        }
+        let mut prompt = Password::new();
+        if creating {
+            prompt = prompt
+                .with_prompt("Choose a passphrase for the cocmd vault (secret params)")
+                .with_confirmation("Repeat the passphrase", "The passphrases don't match");
+        } else {
+            prompt = prompt.with_prompt("Passphrase of the cocmd vault");
+        }
+        let passphrase = prompt
+            .interact()
+            .context("a passphrase is needed to use the vault")?;
+        if passphrase.is_empty() {
+            bail!("a passphrase is needed to use the vault");
+        }
+        Ok(passphrase.into_bytes())
+    }
+
+    fn unlock(&mut self) -> Result<&mut Unlocked> {
+        if self.unlocked.is_none() {
+            self.unlocked = Some(if self.exists() {
+                self.open()?
+            } else {
+                let salt = random_bytes(16)?;
+                Unlocked {
+                    key: derive_key(&self.key_material(true)?, &salt, ITERATIONS)?,
+                    salt,
+                    secrets: BTreeMap::new(),
+                }
+            });
+        }
+        Ok(self.unlocked.as_mut().unwrap())
+    }
+
+    fn open(&self) -> Result<Unlocked> {
+        let content = fs::read_to_string(&self.path)
+            .with_context(|| format!("unable to read {}", self.path.display()))?;
+        let file: VaultFile = serde_yaml::from_str(&content)
+            .with_context(|| format!("{} is not a cocmd vault", self.path.display()))?;
+        if file.version > FORMAT_VERSION {
+            bail!(
+                "{} was written by a newer cocmd, please upgrade cocmd",
+                self.path.display()
+            );
+        }
+        let decode = |field: &str| {
+            hex::decode(field).with_context(|| format!("{} is corrupted", self.path.display()))
+        };
+        let salt = decode(&file.salt)?;
+
+        let key = derive_key(&self.key_material(false)?, &salt, file.iterations)?;
+        let data = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &key,
+            Some(&decode(&file.nonce)?),
+            AAD,
+            &decode(&file.data)?,
+            &decode(&file.tag)?,
+        )
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "unable to unlock {}: wrong passphrase or key file",
+                self.path.display()
+            )
+        })?;
+        let secrets = serde_yaml::from_slice(&data)
+            .with_context(|| format!("{} is corrupted", self.path.display()))?;
+
+        Ok(Unlocked { key, salt, secrets })
+    }
+
+    fn write(&self) -> Result<()> {
+        let Some(unlocked) = &self.unlocked else {
+            return Ok(());
+        };
+        let nonce = random_bytes(12)?;
+        let mut tag = vec![0u8; 16];
+        let data = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &unlocked.key,
+            Some(&nonce),
+            AAD,
+            serde_yaml::to_string(&unlocked.secrets)?.as_bytes(),
+            &mut tag,
+        )?;
+        let file = VaultFile {
+            version: FORMAT_VERSION,
+            iterations: ITERATIONS,
+            salt: hex::encode(&unlocked.salt),
+            nonce: hex::encode(nonce),
+            tag: hex::encode(tag),
+            data: hex::encode(data),
+        };
+
+        fs::write(&self.path, serde_yaml::to_string(&file)?)
+            .with_context(|| format!("unable to write {}", self.path.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&self.path, fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+
+    // a saved secret, the package scope comes before the global one. a missing vault isn't
+    // created (or asked for) just to look
+    pub fn get(&mut self, name: &str, package: Option<&str>) -> Result<Option<String>> {
+        if self.unlocked.is_none() && !self.exists() {
+            return Ok(None);
+        }
+        let secrets = &self.unlock()?.secrets;
+        Ok(package
+            .and_then(|package| secrets.get(&scoped_name(name, Some(package))))
+            .or_else(|| secrets.get(name))
+            .cloned())
+    }
+
+    pub fn set(&mut self, name: &str, value: &str, package: Option<&str>) -> Result<()> {
+        let key = scoped_name(name, package);
+        let unlocked = self.unlock()?;
+        if unlocked.secrets.get(&key).map(String::as_str) == Some(value) {
+            return Ok(());
+        }
+        unlocked.secrets.insert(key, value.to_string());
+        self.write()
+    }
+
+    // false when there was no such secret in this scope
+    pub fn remove(&mut self, name: &str, package: Option<&str>) -> Result<bool> {
+        if self.unlocked.is_none() && !self.exists() {
+            return Ok(false);
+        }
+        if self
+            .unlock()?
+            .secrets
+            .remove(&scoped_name(name, package))
+            .is_none()
+        {
+            return Ok(false);
+        }
+        self.write()?;
+        Ok(true)
+    }
+
+    // (package, name) of every secret, the package is None for global ones
+    pub fn names(&mut self) -> Result<Vec<(Option<String>, String)>> {
+        if self.unlocked.is_none() && !self.exists() {
+            return Ok(vec![]);
+        }
+        Ok(self
+            .unlock()?
+            .secrets
+            .keys()
+            .map(|key| match key.split_once('/') {
+                Some((package, name)) => (Some(package.to_string()), name.to_string()),
+                None => (None, key.clone()),
+            })
+            .collect())
+    }
+}
+
+// package secrets are kept as "package/name"
+fn scoped_name(name: &str, package: Option<&str>) -> String {
+    match package {
+        Some(package) => format!("{}/{}", package, name),
+        None => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_vault() {
+        let home = TempDir::new("cocmd").unwrap();
+        fs::write(home.path().join(consts::VAULT_KEY_FILE), "key one").unwrap();
+
+        let mut vault = Vault::new(home.path(), false);
+        assert_eq!(vault.get("token", None).unwrap(), None);
+        assert!(!vault.exists());
+
+        vault.set("token", "s3cr3t", None).unwrap();
+        vault.set("token", "demo-s3cr3t", Some("demo")).unwrap();
+        vault.set("other", "x", None).unwrap();
+        assert!(vault.remove("other", None).unwrap());
+        assert!(!vault.remove("other", None).unwrap());
+
+        let content = fs::read_to_string(home.path().join(consts::VAULT_FILE)).unwrap();
+        assert!(!content.contains("s3cr3t"));
+        assert!(!content.contains("token"));
+        assert!(!format!("{:?}", vault).contains("s3cr3t"));
+
+        let mut reopened = Vault::new(home.path(), false);
+        assert_eq!(
+            reopened.get("token", None).unwrap(),
+            Some("s3cr3t".to_string())
+        );
+        assert_eq!(
+            reopened.get("token", Some("demo")).unwrap(),
+            Some("demo-s3cr3t".to_string())
+        );
+        assert_eq!(
+            reopened.get("token", Some("other")).unwrap(),
+            Some("s3cr3t".to_string())
+        );
+        assert_eq!(
+            reopened.names().unwrap(),
+            vec![
+                (Some("demo".to_string()), "token".to_string()),
+                (None, "token".to_string())
+            ]
+        );
+
+        fs::write(home.path().join(consts::VAULT_KEY_FILE), "key two").unwrap();
+        let mut wrong_key = Vault::new(home.path(), false);
+        assert!(wrong_key.get("token", None).is_err());
+    }
+}
@@ -6,28 +6,35 @@ pub(crate) mod output;
 pub(crate) mod package_provider;
 pub(crate) mod runner;
 pub(crate) mod tui_app;
+use std::path::PathBuf;
 use std::process::ExitCode;
 
 use anyhow::Error;
 use clap::{Parser, Subcommand};
 use cmd::add;
 use cmd::docs::run_docs;
+use cmd::outdated::run_outdated;
+use cmd::params;
 use cmd::profile_loader::run_profile_loader;
+use cmd::refresh::run_refresh;
 use cmd::run::run_automation;
+use cmd::schema;
 use cmd::setup::run_setup;
 #[cfg(feature = "howto")]
 use cmd::show::howto;
 use cmd::show::{show_package, show_packages};
 use cmd::uninstall::uninstall_package;
+use cmd::validate::run_validate;
 use dialoguer::{Confirm, MultiSelect};
 use itertools::Itertools;
-use log::trace;
+use log::{error, trace};
 use tui_app::tui_runner;
 
+use crate::core::consts;
 pub(crate) use crate::core::models::settings::Settings;
 use crate::core::packages_manager::PackagesManager;
 use crate::output::print_md;
-use crate::output::set_logging_level;
+use crate::output::{set_logging_level, set_rendering};
 
 /// Main CLI struct with meta-information
 #[derive(Parser)]
@@ -43,6 +50,10 @@ struct Cli {
     #[arg(short, long, default_value_t = false)]
     no_verbose: bool,
 
+    /// Config file to use instead of config.yaml in the cocmd home, $COCMD_CONFIG when not set
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
     /// Subcommands
     #[command(subcommand)]
     command: Commands,
@@ -57,8 +68,22 @@ enum Commands {
     /// Profile Loader command - Loads profiles
     ProfileLoader,
 
-    /// Refresh command - Refreshes something (add a description here)
-    Refresh,
+    /// Refresh command - Updates the installed packages to their newest version
+    Refresh {
+        /// Names of the packages to refresh, all of them when none are given
+        names: Vec<String>,
+
+        /// Only show what would be updated
+        #[arg(long = "dry-run", default_value_t = false)]
+        dry_run: bool,
+    },
+
+    /// Outdated command - Lists the installed packages and the latest version of each
+    Outdated {
+        /// Print the report as JSON
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
 
     /// Docs command with a name argument - Generates and displays documentation
     Docs {
@@ -95,6 +120,14 @@ enum Commands {
 
         #[arg(long = "yes", short = 'y', default_value_t = false)]
         dont_ask: bool,
+
+        /// Install exactly the packages and versions in cocmd.lock
+        #[arg(long, conflicts_with = "names")]
+        locked: bool,
+
+        /// Lock file for --locked, instead of the one in the cocmd home
+        #[arg(long, requires = "locked")]
+        lockfile: Option<PathBuf>,
     },
 
     /// Uninstall command with a package name argument - Uninstalls a specific package
@@ -107,6 +140,18 @@ enum Commands {
 
     /// Setup command with a shell argument - Set up the CLI tool, specify shell
     Setup(SetupArgs),
+
+    /// Params command with subcommands - Shows and changes saved params
+    Params(ParamsArgs),
+
+    /// Schema command - Prints the JSON schema of cocmd.yaml, for editors and validation
+    Schema,
+
+    /// Validate command - Checks a package (cocmd.yaml and the files it uses) and reports problems with their line and column
+    Validate {
+        /// Path to the package directory or its cocmd.yaml (default: the current directory)
+        path: Option<String>,
+    },
 }
 
 /// Arguments for the 'show' subcommand with meta-information
@@ -130,6 +175,54 @@ enum ShowCommands {
     Packages,
 }
 
+/// Arguments for the 'params' subcommand with meta-information
+#[derive(Parser)]
+struct ParamsArgs {
+    /// Subcommands for 'params' command
+    #[command(subcommand)]
+    params_commands: ParamsCommands,
+}
+
+/// Subcommands enum for 'params' - params are saved globally or for a package, the package
+/// value comes first
+#[derive(Subcommand)]
+enum ParamsCommands {
+    /// List subcommand - Shows the saved params and their scope (secret values are masked)
+    List {
+        /// Only what this package sees
+        #[arg(long, short)]
+        package: Option<String>,
+    },
+
+    /// Get subcommand - Prints the saved value of a param
+    Get {
+        name: String,
+        /// The package scope to look in first
+        #[arg(long, short)]
+        package: Option<String>,
+    },
+
+    /// Set subcommand - Saves a param, asks for the value when it's not given
+    Set {
+        name: String,
+        value: Option<String>,
+        /// Save it for this package only
+        #[arg(long, short)]
+        package: Option<String>,
+        /// Save it in the encrypted vault
+        #[arg(long, short, default_value_t = false)]
+        secret: bool,
+    },
+
+    /// Unset subcommand - Removes a saved param
+    Unset {
+        name: String,
+        /// Remove it from this package scope
+        #[arg(long, short)]
+        package: Option<String>,
+    },
+}
+
 /// Arguments for the 'setup' subcommand with meta-information
 #[derive(Parser)]
 struct SetupArgs {
@@ -147,8 +240,32 @@ fn main() -> ExitCode {
         set_logging_level(!cli.no_verbose);
     }
 
-    let settings = Settings::new(None, None);
+    let mut settings = Settings::new(None, None);
+    let config = cli.config.clone().or_else(|| {
+        std::env::var_os(consts::CONFIG_ENV)
+            .filter(|config| !config.is_empty())
+            .map(PathBuf::from)
+    });
+    if let Some(config) = &config {
+        if let Err(e) = settings.load_config(config) {
+            error!("{:#}", e);
+            return ExitCode::from(1);
+        }
+        // steps with the cocmd runner and whatever else we run inherit it
+        let config = std::fs::canonicalize(config).unwrap_or_else(|_| config.clone());
+        std::env::set_var(consts::CONFIG_ENV, config);
+    }
+    set_rendering(settings.config.color, settings.config.markdown);
     let mut packages_manager = PackagesManager::new(settings);
+    // run, show and browse see the automations of the project we stand in
+    if matches!(
+        cli.command,
+        Commands::Run { .. } | Commands::Show(_) | Commands::Browse
+    ) {
+        if let Ok(cwd) = std::env::current_dir() {
+            packages_manager.load_project(&cwd);
+        }
+    }
 
     let mut res: Result<(), Error> = Ok(());
 
@@ -176,8 +293,17 @@ fn main() -> ExitCode {
         Commands::ProfileLoader => {
             res = run_profile_loader(&mut packages_manager);
         }
-        Commands::Refresh => {
-            todo!("Refresh command not implemented yet")
+        Commands::Refresh { names, dry_run } => {
+            res = run_refresh(&mut packages_manager, &names, dry_run);
+            if let Err(e) = &res {
+                error!("{:#}", e);
+            }
+        }
+        Commands::Outdated { json } => {
+            res = run_outdated(&packages_manager, json);
+            if let Err(e) = &res {
+                error!("{:#}", e);
+            }
         }
         Commands::Docs { name, raw_markdown } => match name {
             Some(name) => {
@@ -208,13 +334,29 @@ fn main() -> ExitCode {
                 res = show_packages(&mut packages_manager);
             }
         },
-        Commands::Install { names, dont_ask } => {
+        Commands::Install {
+            names,
+            dont_ask,
+            locked,
+            lockfile,
+        } => {
             let selected_names;
-            if let Some(names) = names {
+            if locked {
+                let lock_file =
+                    lockfile.unwrap_or_else(|| packages_manager.settings.lock_file.clone());
+                res = add::install_locked(&mut packages_manager, &lock_file);
+                if let Err(e) = &res {
+                    error!("{:#}", e);
+                }
+                selected_names = vec![];
+            } else if let Some(names) = names {
                 selected_names = names.clone();
+            } else if packages_manager.settings.config.non_interactive {
+                error!("name the packages to install, nothing can be selected when non_interactive is on");
+                return ExitCode::from(1);
             } else {
                 let index = package_provider::hub::CocmdHubPackageProvider::get_index(
-                    &packages_manager.settings.runtime_dir,
+                    &packages_manager.settings,
                     false,
                 )
                 .expect("unable to get index from hub");
@@ -239,12 +381,38 @@ fn main() -> ExitCode {
             }
             trace!("Ok, I will install: {}", selected_names.join(", "));
             for name in selected_names {
+                let dont_ask = dont_ask || packages_manager.settings.config.non_interactive;
                 res = add::install_package(&mut packages_manager, &name, dont_ask);
             }
         }
         Commands::Remove => {
             println!("'cocmd remove' was used");
         }
+        Commands::Params(args) => match args.params_commands {
+            ParamsCommands::List { package } => {
+                res = params::list_params(&mut packages_manager, package);
+            }
+            ParamsCommands::Get { name, package } => {
+                res = params::get_param(&mut packages_manager, &name, package);
+            }
+            ParamsCommands::Set {
+                name,
+                value,
+                package,
+                secret,
+            } => {
+                res = params::set_param(&mut packages_manager, &name, value, package, secret);
+            }
+            ParamsCommands::Unset { name, package } => {
+                res = params::unset_param(&mut packages_manager, &name, package);
+            }
+        },
+        Commands::Schema => {
+            res = schema::print_schema();
+        }
+        Commands::Validate { path } => {
+            res = run_validate(&packages_manager, path);
+        }
     }
 
     // if res returned an error, print it to stderr
@@ -279,7 +447,7 @@ mod tests {
         assert!(package.is_some());
 
         let index = package_provider::hub::CocmdHubPackageProvider::get_index(
-            &packages_manager.settings.runtime_dir,
+            &packages_manager.settings,
             false,
         )
         .expect("unable to get index from hub");
@@ -1,9 +1,23 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use log::{Level, LevelFilter};
 use termimad::MadSkin;
 
 // Global variable to store the log level.
 static mut LOG_LEVEL: Level = Level::Info; // Initialize with a default level.
 
+// `color` and `markdown` of the user config
+static COLOR: AtomicBool = AtomicBool::new(true);
+static MARKDOWN: AtomicBool = AtomicBool::new(true);
+
+pub fn set_rendering(color: bool, markdown: bool) {
+    COLOR.store(color, Ordering::Relaxed);
+    MARKDOWN.store(markdown, Ordering::Relaxed);
+    // the styles of console and dialoguer
+    console::set_colors_enabled(color);
+    console::set_colors_enabled_stderr(color);
+}
+
 pub fn set_logging_level(verbose: bool) {
     let level = if verbose { Level::Debug } else { Level::Info };
 
@@ -20,7 +34,15 @@ pub fn set_logging_level(verbose: bool) {
 
 pub fn print_md(markdown: &str) {
     // print with termimad to stdout
-    let skin = MadSkin::default();
+    if !MARKDOWN.load(Ordering::Relaxed) {
+        println!("{}", markdown);
+        return;
+    }
+    let skin = if COLOR.load(Ordering::Relaxed) {
+        MadSkin::default()
+    } else {
+        MadSkin::no_style()
+    };
     skin.print_text(markdown);
 }
 
@@ -21,24 +21,42 @@ use std::{path::Path, path::PathBuf, process::Command};
 
 use anyhow::{bail, Context, Result};
 
-use super::PackageProvider;
-use super::{util::git::GitParts, GIT_PROVIDER};
+use super::util::git::{is_commit_sha, GitParts};
+use super::GIT_PROVIDER;
+use super::{PackageProvider, UpdateCheck};
+use crate::core::lockfile::LockedPackage;
 
 pub struct GitPackageProvider {
     package: String,
+    // where the repository is cloned, local_path is the package's directory in it
+    checkout_path: PathBuf,
     local_path: PathBuf,
     git_parts: GitParts,
 }
 
 impl GitPackageProvider {
     pub fn new(package: &str, git_parts: &GitParts, runtime_dir: &Path) -> Self {
-        // localpath is in runtime_dir with the name of the repo
-        let binding = runtime_dir.join(format!("{}.{}", git_parts.author, git_parts.name));
-        let local_path = binding.as_path();
+        // checkout is in runtime_dir, see GitParts::dir_name. repos cloned before it are in
+        // runtime_dir/<author>.<name> and stay there
+        let legacy_path = runtime_dir.join(format!("{}.{}", git_parts.author, git_parts.name));
+        let checkout_path = if git_parts.reference.is_none()
+            && git_parts.subdir.is_none()
+            && !git_parts.author.contains('/')
+            && legacy_path.is_dir()
+        {
+            legacy_path
+        } else {
+            runtime_dir.join(git_parts.dir_name())
+        };
+        let local_path = match &git_parts.subdir {
+            Some(subdir) => checkout_path.join(subdir),
+            None => checkout_path.clone(),
+        };
         Self {
             package: package.to_string(),
             git_parts: (*git_parts).clone(),
-            local_path: local_path.to_path_buf(),
+            checkout_path,
+            local_path,
         }
     }
 
@@ -52,12 +70,34 @@ impl GitPackageProvider {
         false
     }
 
+    fn git(&self, args: &[&str]) -> Result<String> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.checkout_path)
+            .args(args)
+            .output()
+            .context("git command reported error")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("git command exited with non-zero status: {}", stderr);
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    // shallow, and sparse when the package is in a directory of the repo
     fn clone_repo(&self) -> Result<()> {
-        let mut args = vec!["clone"];
+        let mut args = vec!["clone", "--quiet", "--depth", "1"];
+        if self.git_parts.subdir.is_some() {
+            args.extend(["--filter=blob:none", "--sparse"]);
+        }
+        let reference = self.git_parts.reference.as_deref();
+        if let Some(branch) = reference.filter(|reference| !is_commit_sha(reference)) {
+            args.extend(["--branch", branch]);
+        }
 
-        args.push(self.package.as_str());
+        args.push(self.git_parts.url.as_str());
 
-        let dest_dir_str = self.local_path.to_str().unwrap();
+        let dest_dir_str = self.checkout_path.to_str().unwrap();
         args.push(dest_dir_str);
 
         let output = Command::new("git")
@@ -68,12 +108,68 @@ impl GitPackageProvider {
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
             bail!("git command exited with non-zero status: {}", stderr);
-        } else {
-            Ok(())
+        }
+
+        if let Some(subdir) = &self.git_parts.subdir {
+            self.git(&["sparse-checkout", "set", subdir])?;
+        }
+        if let Some(commit) = reference.filter(|reference| is_commit_sha(reference)) {
+            self.checkout_commit(commit)?;
+        }
+        Ok(())
+    }
+
+    // fetched first when the shallow clone doesn't have it
+    fn checkout_commit(&self, commit: &str) -> Result<()> {
+        if self
+            .git(&["cat-file", "-e", &format!("{}^{{commit}}", commit)])
+            .is_err()
+            && self
+                .git(&["fetch", "--quiet", "--depth", "1", "origin", commit])
+                .is_err()
+        {
+            // servers that don't serve a commit by its sha, or an abbreviated one
+            if self.git(&["rev-parse", "--is-shallow-repository"])? == "true" {
+                self.git(&["fetch", "--quiet", "--unshallow", "origin"])?;
+            } else {
+                self.git(&["fetch", "--quiet", "origin"])?;
+            }
+        }
+        self.git(&["checkout", "--quiet", commit])
+            .with_context(|| format!("{} doesn't have commit {}", self.package, commit))?;
+        Ok(())
+    }
+
+    // a tag or a commit stays where it is, a branch (or no ref) is followed
+    fn is_pinned(&self) -> bool {
+        match &self.git_parts.reference {
+            Some(reference) => self
+                .git(&[
+                    "rev-parse",
+                    "--verify",
+                    "--quiet",
+                    &format!("refs/remotes/origin/{}", reference),
+                ])
+                .is_err(),
+            None => false,
+        }
+    }
+
+    // the remote branch the checkout follows
+    fn tracked_branch(&self) -> Result<String> {
+        match &self.git_parts.reference {
+            Some(branch) => Ok(format!("origin/{}", branch)),
+            None => self
+                .git(&["rev-parse", "--abbrev-ref", "origin/HEAD"])
+                .with_context(|| format!("unable to find the default branch of {}", self.package)),
         }
     }
 }
 
+fn short_commit(commit: &str) -> String {
+    commit.chars().take(7).collect()
+}
+
 impl PackageProvider for GitPackageProvider {
     fn name(&self) -> String {
         GIT_PROVIDER.to_string()
@@ -83,6 +179,10 @@ impl PackageProvider for GitPackageProvider {
         self.local_path.to_path_buf()
     }
 
+    fn get_installation_path(&self) -> PathBuf {
+        self.checkout_path.clone()
+    }
+
     fn package(&self) -> String {
         self.package.clone()
     }
@@ -96,4 +196,173 @@ impl PackageProvider for GitPackageProvider {
 
         Ok(self.local_path.clone())
     }
+
+    fn lock(&self) -> Result<LockedPackage> {
+        Ok(LockedPackage {
+            uri: self.package.clone(),
+            provider: self.name(),
+            commit: Some(self.git(&["rev-parse", "HEAD"])?),
+            ..Default::default()
+        })
+    }
+
+    // the locked commit, fetched when the clone doesn't have it
+    fn download_locked(&self, locked: &LockedPackage) -> Result<PathBuf> {
+        if !self.is_exists_locally() {
+            self.download()?;
+        }
+        if let Some(commit) = &locked.commit {
+            self.checkout_commit(commit)?;
+        }
+        Ok(self.local_path.clone())
+    }
+
+    fn installed_revision(&self) -> Option<String> {
+        self.git(&["rev-parse", "--short", "HEAD"]).ok()
+    }
+
+    // the local HEAD against the remote branch it follows. a pinned tag or commit is never
+    // outdated
+    fn check_update(&self) -> Result<UpdateCheck> {
+        let head = self.git(&["rev-parse", "HEAD"])?;
+        if self.is_pinned() {
+            return Ok(UpdateCheck {
+                installed: Some(short_commit(&head)),
+                latest: Some(short_commit(&head)),
+                outdated: false,
+                ..Default::default()
+            });
+        }
+        let remote_ref = match (
+            self.git(&["symbolic-ref", "--quiet", "HEAD"]),
+            &self.git_parts.reference,
+        ) {
+            (Ok(branch), _) => branch,
+            (Err(_), Some(branch)) => format!("refs/heads/{}", branch),
+            (Err(_), None) => "HEAD".to_string(),
+        };
+        let remote = self.git(&["ls-remote", "--exit-code", "origin", &remote_ref])?;
+        let latest = remote
+            .split_whitespace()
+            .next()
+            .with_context(|| format!("{} has no {}", self.package, remote_ref))?;
+        Ok(UpdateCheck {
+            outdated: latest != head,
+            installed: Some(short_commit(&head)),
+            latest: Some(short_commit(latest)),
+            ..Default::default()
+        })
+    }
+
+    // fast-forward the checkout to the branch it follows. a checkout at a locked commit goes
+    // back to that branch, a pinned tag or commit stays
+    fn refresh(&self, dry_run: bool) -> Result<Option<String>> {
+        if self.is_pinned() {
+            return Ok(self.installed_revision());
+        }
+        self.git(&["fetch", "--quiet", "origin"])?;
+        let on_branch = self.git(&["symbolic-ref", "--quiet", "HEAD"]).is_ok();
+        let upstream = if on_branch {
+            "@{upstream}".to_string()
+        } else {
+            self.tracked_branch()?
+        };
+        if dry_run {
+            return Ok(Some(self.git(&["rev-parse", "--short", &upstream])?));
+        }
+        if !on_branch {
+            let branch = upstream.trim_start_matches("origin/");
+            self.git(&["checkout", "--quiet", branch])?;
+        }
+        self.git(&["merge", "--quiet", "--ff-only", &upstream])
+            .with_context(|| format!("unable to fast-forward {}", self.package))?;
+        Ok(self.installed_revision())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::package_provider::util::git::extract_git_url_parts;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=cocmd", "-c", "user.email=cocmd@localhost"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+    }
+
+    fn commit(work: &Path, version: &str) {
+        fs::create_dir_all(work.join("pkg")).unwrap();
+        fs::write(
+            work.join("pkg").join("cocmd.yaml"),
+            format!("name: pkg\nversion: {}\n", version),
+        )
+        .unwrap();
+        git(work, &["add", "-A"]);
+        git(work, &["commit", "--quiet", "-m", version]);
+    }
+
+    fn provider(uri: &str, runtime_dir: &Path) -> GitPackageProvider {
+        GitPackageProvider::new(uri, &extract_git_url_parts(uri).unwrap(), runtime_dir)
+    }
+
+    fn installed_version(provider: &GitPackageProvider) -> String {
+        fs::read_to_string(provider.local_path().join("cocmd.yaml")).unwrap()
+    }
+
+    #[test]
+    fn test_bare_repository() {
+        let root = TempDir::new("cocmd").unwrap();
+        let work = root.path().join("work");
+        let bare = root.path().join("tools.git");
+        let runtime_dir = root.path().join("runtime");
+        fs::create_dir_all(&work).unwrap();
+        git(&work, &["init", "--quiet", "--initial-branch", "main"]);
+        commit(&work, "1.0.0");
+        git(&work, &["tag", "v1"]);
+        git(
+            root.path(),
+            &["clone", "--quiet", "--bare", "work", "tools.git"],
+        );
+        git(&work, &["remote", "add", "origin", bare.to_str().unwrap()]);
+
+        let uri = format!("file://{}//pkg", bare.display());
+        let latest = provider(&uri, &runtime_dir);
+        let pinned = provider(&format!("{}#v1", uri), &runtime_dir);
+        latest.download().unwrap();
+        pinned.download().unwrap();
+        assert_ne!(latest.local_path(), pinned.local_path());
+        assert!(installed_version(&latest).contains("1.0.0"));
+
+        commit(&work, "1.1.0");
+        git(&work, &["push", "--quiet", "origin", "main"]);
+        assert!(latest.check_update().unwrap().outdated);
+        assert!(!pinned.check_update().unwrap().outdated);
+
+        latest.refresh(false).unwrap();
+        pinned.refresh(false).unwrap();
+        assert!(installed_version(&latest).contains("1.1.0"));
+        assert!(installed_version(&pinned).contains("1.0.0"));
+        assert!(!latest.check_update().unwrap().outdated);
+
+        // the locked commit, in a runtime dir of its own
+        let locked = pinned.lock().unwrap();
+        let other = provider(&uri, &root.path().join("other"));
+        other.download_locked(&locked).unwrap();
+        assert!(installed_version(&other).contains("1.0.0"));
+
+        // the path of the bare repository is the same repository
+        let bare_path = provider(&format!("{}//pkg", bare.display()), &runtime_dir);
+        assert_eq!(bare_path.name(), GIT_PROVIDER);
+        assert_eq!(bare_path.local_path(), latest.local_path());
+    }
 }
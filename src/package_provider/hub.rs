@@ -22,31 +22,44 @@ use std::{
     time::UNIX_EPOCH,
 };
 
-use anyhow::{anyhow, Context, Result};
-use log::{info, trace};
+use anyhow::{anyhow, bail, Context, Result};
+use itertools::Itertools;
+use log::{info, trace, warn};
 use serde::{Deserialize, Serialize};
 
 use super::util::path::resolve_hub_package_locally;
-use super::PackageProvider;
 use super::{
     util::download::download_and_extract_zip_verify_sha256, util::download::read_string_from_url,
     COCMDHUB_PROVIDER,
 };
+use super::{PackageProvider, UpdateCheck};
+use crate::core::lockfile::LockedPackage;
+use crate::core::models::user_config::Registry;
+use crate::core::utils::version::{compare_versions, is_exact_version, VersionConstraint};
 use crate::core::{
     consts, models::package_config_model::PackageConfigModel, utils::io::from_yaml_file,
 };
+use crate::Settings;
 
 pub const COCMD_HUB_PACKAGE_INDEX_URL: &str =
     "https://github.com/cocmd/hub/releases/latest/download/package_index.json";
 
 const PACKAGE_INDEX_CACHE_FILE: &str = "package_index_cache.json";
-const PACKAGE_INDEX_CACHE_INVALIDATION_SECONDS: u64 = 60 * 60;
+// what a file:// registry directory has
+const PACKAGE_INDEX_FILE: &str = "package_index.json";
+// runtime/registries/<name> has the index cache of the registry and its `name:package` packages
+const REGISTRIES_DIR: &str = "registries";
 
 pub struct CocmdHubPackageProvider {
     package: String,
     version: String,
+    // the version it was asked for, `version` is what it resolved to
+    constraint: Option<String>,
     local_path: PathBuf,
     runtime_dir: PathBuf,
+    // by priority
+    registries: Vec<Registry>,
+    cache_ttl: u64,
 }
 
 impl PackageProvider for CocmdHubPackageProvider {
@@ -74,114 +87,318 @@ impl PackageProvider for CocmdHubPackageProvider {
     }
 
     fn download(&self) -> Result<PathBuf> {
-        let index = Self::get_index(&self.runtime_dir, false)?;
-        // .context("unable to get package index from cocmd hub")?;
-
-        let package_info = index
-            .get_package(&self.package, &Some(self.version.clone()))
-            .ok_or_else(|| {
-                anyhow!(
-                    "unable to find package '{}@{}' in the cocmd hub",
-                    &self.package,
-                    &self.version
-                )
-            })?;
+        let (package_info, archive_sha256) = self.served_archive(None)?;
+        self.download_archive(&package_info, &archive_sha256)
+    }
 
-        let archive_sha256 = read_string_from_url(&package_info.archive_sha256_url)
-            .context("unable to read archive sha256 signature")?;
+    fn lock(&self) -> Result<LockedPackage> {
+        let (package_info, archive_sha256) = self.served_archive(None)?;
+        Ok(LockedPackage {
+            uri: self.package.clone(),
+            provider: self.name(),
+            registry: Some(package_info.registry),
+            version: Some(self.version.clone()),
+            constraint: self.constraint.clone(),
+            sha256: Some(archive_sha256),
+            ..Default::default()
+        })
+    }
 
-        // remove self.local_path if exists
-        if self.local_path.exists() {
-            std::fs::remove_dir_all(&self.local_path)?;
+    // the hub has to still serve the locked version, with the same archive
+    fn download_locked(&self, locked: &LockedPackage) -> Result<PathBuf> {
+        let registry = locked.registry.as_deref();
+        let (package_info, archive_sha256) = self.served_archive(registry).with_context(|| {
+            format!("the hub no longer serves {} {}", self.package, self.version)
+        })?;
+        if let Some(locked_sha256) = &locked.sha256 {
+            if locked_sha256 != &archive_sha256 {
+                bail!(
+                    "checksum mismatch for {} {}: locked {}, the hub serves {}",
+                    self.package,
+                    self.version,
+                    locked_sha256,
+                    archive_sha256
+                );
+            }
         }
-
-        download_and_extract_zip_verify_sha256(
-            &package_info.archive_url,
-            &self.local_path,
-            Some(&archive_sha256),
-        )?;
-
-        Ok(self.local_path.clone())
+        if self.is_exists_locally() {
+            return Ok(self.local_path.clone());
+        }
+        self.download_archive(&package_info, &archive_sha256)
     }
 
     fn package(&self) -> String {
         self.package.clone()
     }
+
+    fn installed_revision(&self) -> Option<String> {
+        let config_file_path = self.local_path.join(consts::SOURCE_CONFIG_FILE);
+        from_yaml_file::<PackageConfigModel>(&config_file_path)
+            .ok()
+            .and_then(|config| config.version)
+    }
+
+    fn check_update(&self) -> Result<UpdateCheck> {
+        let index = Self::fetch_index(&self.runtime_dir, &self.registries, self.cache_ttl, false)?;
+        let latest = index.get_package(&self.package, &self.constraint)?.version;
+        let newest = index.get_package(&self.package, &None)?.version;
+        let installed = self.installed_revision();
+        Ok(UpdateCheck {
+            outdated: installed
+                .as_deref()
+                .is_none_or(|installed| compare_versions(&latest, installed).is_gt()),
+            installed,
+            newest: compare_versions(&newest, &latest).is_gt().then_some(newest),
+            latest: Some(latest),
+        })
+    }
+
+    // the newest version in the index that satisfies the constraint, the caller fetches a fresh
+    // index first
+    fn refresh(&self, dry_run: bool) -> Result<Option<String>> {
+        let index = Self::fetch_index(&self.runtime_dir, &self.registries, self.cache_ttl, false)?;
+        let package_info = index.get_package(&self.package, &self.constraint)?;
+        let installed = self.installed_revision();
+        if dry_run || installed.as_deref() == Some(package_info.version.as_str()) {
+            return Ok(Some(package_info.version));
+        }
+
+        let archive_sha256 = read_string_from_url(&package_info.archive_sha256_url)
+            .context("unable to read archive sha256 signature")?;
+        self.download_archive(&package_info, archive_sha256.trim())?;
+        Ok(Some(package_info.version))
+    }
 }
 
 impl CocmdHubPackageProvider {
-    pub fn new(package: &String, runtime_dir: &Path, version: Option<String>) -> Self {
-        let binding = runtime_dir.join(package);
+    pub fn new(package: &str, settings: &Settings, version: Option<String>) -> Result<Self> {
+        let runtime_dir = settings.runtime_dir.as_path();
+        let registries = Self::package_registries(package, settings)?;
+        let cache_ttl = settings.config.cache_ttl;
+        let base_dir = match split_registry(package) {
+            (Some(registry), _) => runtime_dir.join(REGISTRIES_DIR).join(registry),
+            (None, _) => runtime_dir.to_path_buf(),
+        };
+        let name = split_registry(package).1;
+        let binding = base_dir.join(name);
 
         let default_path = binding.as_path();
 
         // if version not provided use the latest version you find in index
-        // if version is provided, use it
-        let mut version = version.clone();
+        // if it's a range, use the newest version in the index that satisfies it.
+        // an exact version is used as is.
+        // when nothing satisfies it the constraint is kept, and download() reports the problem
+        let constraint = version.clone();
+        let mut version = version;
         if version.is_none() {
-            let index = Self::get_index(runtime_dir, false).unwrap();
+            let index = Self::fetch_index(runtime_dir, &registries, cache_ttl, false)?;
             version = index
                 .get_package(package, &None)
                 .map(|package| package.version)
-                .unwrap_or_else(|| "0.0.0".to_string())
+                .unwrap_or_else(|_| "0.0.0".to_string())
                 .into();
+        } else if !version.as_deref().is_some_and(is_exact_version) {
+            if let Ok(index) = Self::fetch_index(runtime_dir, &registries, cache_ttl, false) {
+                if let Ok(package) = index.get_package(package, &version) {
+                    version = Some(package.version);
+                }
+            }
         }
 
-        let res = resolve_hub_package_locally(runtime_dir, package.as_str(), version.as_deref());
+        let res = resolve_hub_package_locally(&base_dir, name, version.as_deref());
 
-        Self {
-            package: (*package.clone()).to_string(),
+        Ok(Self {
+            package: package.to_string(),
             local_path: res.unwrap_or_else(|_| default_path.to_path_buf()),
             runtime_dir: runtime_dir.to_path_buf(),
+            registries,
+            cache_ttl,
             version: version.unwrap(),
+            constraint,
+        })
+    }
+
+    // the registries a package can come from: all of them by priority, or the one it names
+    fn package_registries(package: &str, settings: &Settings) -> Result<Vec<Registry>> {
+        let registries = settings.config.registries_by_priority();
+        match split_registry(package) {
+            (Some(name), _) => match registries.into_iter().find(|r| r.name == name) {
+                Some(registry) => Ok(vec![registry]),
+                None => bail!("registry '{}' is not configured", name),
+            },
+            (None, _) => Ok(registries),
+        }
+    }
+
+    // the package in the index and the sha256 of its archive. with a registry, the package
+    // has to come from it
+    fn served_archive(&self, registry: Option<&str>) -> Result<(PackageInfo, String)> {
+        let index = Self::fetch_index(&self.runtime_dir, &self.registries, self.cache_ttl, false)?;
+        // .context("unable to get package index from cocmd hub")?;
+
+        let package = match (registry, split_registry(&self.package)) {
+            (Some(registry), (None, name)) => format!("{}:{}", registry, name),
+            _ => self.package.clone(),
+        };
+        let package_info = index.get_package(&package, &Some(self.version.clone()))?;
+
+        let archive_sha256 = read_string_from_url(&package_info.archive_sha256_url)
+            .context("unable to read archive sha256 signature")?;
+        Ok((package_info, archive_sha256.trim().to_string()))
+    }
+
+    fn download_archive(
+        &self,
+        package_info: &PackageInfo,
+        archive_sha256: &str,
+    ) -> Result<PathBuf> {
+        // remove self.local_path if exists
+        if self.local_path.exists() {
+            std::fs::remove_dir_all(&self.local_path)?;
         }
+
+        download_and_extract_zip_verify_sha256(
+            &package_info.archive_url,
+            &self.local_path,
+            Some(archive_sha256),
+        )?;
+
+        Ok(self.local_path.clone())
+    }
+
+    // the packages of all the registries in the config, by priority. every registry's index is
+    // cached for `cache_ttl` seconds
+    pub fn get_index(settings: &Settings, force_update: bool) -> Result<PackageIndex> {
+        Self::fetch_index(
+            &settings.runtime_dir,
+            &settings.config.registries_by_priority(),
+            settings.config.cache_ttl,
+            force_update,
+        )
+    }
+
+    fn fetch_index(
+        runtime_dir: &Path,
+        registries: &[Registry],
+        cache_ttl: u64,
+        force_update: bool,
+    ) -> Result<PackageIndex> {
+        let mut index = PackageIndex {
+            last_update: 0,
+            packages: vec![],
+        };
+        let mut errors = vec![];
+        for registry in registries {
+            let registry_index =
+                match Self::fetch_registry_index(runtime_dir, registry, cache_ttl, force_update) {
+                    Ok(registry_index) => registry_index,
+                    Err(e) => {
+                        let e = e.context(format!(
+                            "unable to get the index of registry '{}'",
+                            registry.name
+                        ));
+                        // one broken registry doesn't take the others down
+                        match Self::stale_registry_index(runtime_dir, registry) {
+                            Some(stale_index) => {
+                                warn!("{:#}, using the cached index", e);
+                                stale_index
+                            }
+                            None => {
+                                warn!("{:#}, skipping it", e);
+                                errors.push(e);
+                                continue;
+                            }
+                        }
+                    }
+                };
+            index.last_update = index.last_update.max(registry_index.last_update);
+            index.packages.extend(
+                registry_index
+                    .packages
+                    .into_iter()
+                    .map(|package| PackageInfo {
+                        registry: registry.name.clone(),
+                        ..package
+                    }),
+            );
+        }
+        if errors.len() == registries.len() {
+            if let Some(e) = errors.pop() {
+                return Err(e);
+            }
+        }
+        Ok(index)
     }
 
-    pub fn get_index(runtime_dir: &Path, force_update: bool) -> Result<PackageIndex> {
-        let old_index = Self::get_index_from_cache(runtime_dir)?;
+    fn fetch_registry_index(
+        runtime_dir: &Path,
+        registry: &Registry,
+        cache_ttl: u64,
+        force_update: bool,
+    ) -> Result<PackageIndex> {
+        let cache_dir = runtime_dir.join(REGISTRIES_DIR).join(&registry.name);
+        let old_index = Self::get_index_from_cache(&cache_dir)?;
 
         if let Some(old_index) = old_index {
-            if !force_update {
+            // a cache of another url doesn't count
+            if !force_update && old_index.url == registry.url {
                 let current_time = std::time::SystemTime::now().duration_since(UNIX_EPOCH)?;
                 let current_unix = current_time.as_secs();
-                if old_index.cached_at >= (current_unix - PACKAGE_INDEX_CACHE_INVALIDATION_SECONDS)
-                {
-                    trace!("using cached package index");
+                if old_index.cached_at >= current_unix.saturating_sub(cache_ttl) {
+                    trace!("using cached package index of {}", registry.name);
                     return Ok(old_index.index);
                 }
             }
         }
 
-        let new_index = CocmdHubPackageProvider::download_index()?;
-        Self::save_index_to_cache(runtime_dir, new_index.clone())?;
+        let new_index = CocmdHubPackageProvider::download_index(&registry.url)?;
+        Self::save_index_to_cache(&cache_dir, &registry.url, new_index.clone())?;
         Ok(new_index)
     }
 
-    fn download_index() -> Result<PackageIndex> {
+    // the cached index of the registry however old it is, when it's of the same url
+    fn stale_registry_index(runtime_dir: &Path, registry: &Registry) -> Option<PackageIndex> {
+        let cache_dir = runtime_dir.join(REGISTRIES_DIR).join(&registry.name);
+        Self::get_index_from_cache(&cache_dir)
+            .ok()
+            .flatten()
+            .filter(|cached| cached.url == registry.url)
+            .map(|cached| cached.index)
+    }
+
+    fn download_index(url: &str) -> Result<PackageIndex> {
         info!("fetching from hub...");
-        let json_body = read_string_from_url(COCMD_HUB_PACKAGE_INDEX_URL)?;
-        let index: PackageIndex = serde_json::from_str(&json_body)?;
-        Ok(index)
+        let index_url = match url.strip_prefix("file://") {
+            Some(path) if Path::new(path).is_dir() => {
+                format!("{}/{}", url.trim_end_matches('/'), PACKAGE_INDEX_FILE)
+            }
+            _ => url.to_string(),
+        };
+        let json_body = read_string_from_url(&index_url)?;
+        serde_json::from_str(&json_body)
+            .with_context(|| format!("{} is not a cocmd package index", index_url))
     }
 
-    fn get_index_from_cache(runtime_dir: &Path) -> Result<Option<CachedPackageIndex>> {
-        let target_file = runtime_dir.join(PACKAGE_INDEX_CACHE_FILE);
+    fn get_index_from_cache(cache_dir: &Path) -> Result<Option<CachedPackageIndex>> {
+        let target_file = cache_dir.join(PACKAGE_INDEX_CACHE_FILE);
         if !target_file.is_file() {
             return Ok(None);
         }
 
         let content =
             std::fs::read_to_string(&target_file).context("unable to read package index cache")?;
-        let index: CachedPackageIndex = serde_json::from_str(&content)?;
-        Ok(Some(index))
+        // a cache from an older cocmd is fetched again
+        Ok(serde_json::from_str(&content).ok())
     }
 
-    fn save_index_to_cache(runtime_dir: &Path, index: PackageIndex) -> Result<()> {
-        let target_file = runtime_dir.join(PACKAGE_INDEX_CACHE_FILE);
+    fn save_index_to_cache(cache_dir: &Path, url: &str, index: PackageIndex) -> Result<()> {
+        std::fs::create_dir_all(cache_dir)?;
+        let target_file = cache_dir.join(PACKAGE_INDEX_CACHE_FILE);
         let current_time = std::time::SystemTime::now().duration_since(UNIX_EPOCH)?;
         let current_unix = current_time.as_secs();
         let cached_index = CachedPackageIndex {
             cached_at: current_unix,
+            url: url.to_string(),
             index,
         };
         let serialized_res = serde_json::to_string(&cached_index);
@@ -197,9 +414,20 @@ impl CocmdHubPackageProvider {
     }
 }
 
+// `registry:name` -> (Some(registry), name)
+pub fn split_registry(package: &str) -> (Option<&str>, &str) {
+    match package.split_once(':') {
+        Some((registry, name)) if !registry.is_empty() && !name.starts_with("//") => {
+            (Some(registry), name)
+        }
+        _ => (None, package),
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CachedPackageIndex {
     cached_at: u64,
+    url: String,
     index: PackageIndex,
 }
 
@@ -219,30 +447,66 @@ pub struct PackageInfo {
 
     pub archive_url: String,
     pub archive_sha256_url: String,
+
+    // the registry it came from, not in the index itself
+    #[serde(default)]
+    pub registry: String,
 }
 
 impl PackageIndex {
-    pub fn get_package(&self, name: &str, version: &Option<String>) -> Option<PackageInfo> {
-        let mut matching_packages: Vec<PackageInfo> = self
+    // the newest version of the package that satisfies the constraint (any version when None),
+    // from the first registry that has one. `registry:name` looks in that registry only
+    pub fn get_package(&self, name: &str, version: &Option<String>) -> Result<PackageInfo> {
+        let (registry, name) = split_registry(name);
+        let matching_packages: Vec<&PackageInfo> = self
             .packages
             .iter()
             .filter(|package| package.name == name)
-            .cloned()
+            .filter(|package| registry.is_none_or(|registry| package.registry == registry))
             .collect();
+        if matching_packages.is_empty() {
+            match registry {
+                Some(registry) => bail!(
+                    "unable to find package '{}' in registry '{}'",
+                    name,
+                    registry
+                ),
+                None => bail!("unable to find package '{}' in the cocmd hub", name),
+            }
+        }
 
-        matching_packages.sort_by(|a, b| natord::compare(&a.version, &b.version));
-
-        if let Some(explicit_version) = version {
-            let exact_match = matching_packages
-                .clone()
-                .into_iter()
-                .find(|package| package.version == *explicit_version);
-            if exact_match.is_some() {
-                return exact_match.clone();
+        let constraint = VersionConstraint::parse(version.as_deref().unwrap_or_default())?;
+        for registry in matching_packages
+            .iter()
+            .map(|package| package.registry.as_str())
+            .unique()
+        {
+            let versions: Vec<&str> = matching_packages
+                .iter()
+                .filter(|package| package.registry == registry)
+                .map(|package| package.version.as_str())
+                .collect();
+            if let Some(best) = constraint.best_match(&versions) {
+                return Ok(matching_packages
+                    .into_iter()
+                    .find(|package| package.registry == registry && package.version == best)
+                    .cloned()
+                    .unwrap());
             }
         }
 
-        matching_packages.into_iter().last()
+        let mut available: Vec<&str> = matching_packages
+            .iter()
+            .map(|package| package.version.as_str())
+            .unique()
+            .collect();
+        available.sort_by(|a, b| compare_versions(a, b));
+        bail!(
+            "no version of package '{}' satisfies '{}' in the cocmd hub (available: {})",
+            name,
+            version.as_deref().unwrap_or_default(),
+            available.join(", ")
+        )
     }
 }
 
@@ -250,7 +514,7 @@ impl PackageIndex {
 // download package called "docker" from the hub
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
 
     use temp_testdir::TempDir;
 
@@ -258,19 +522,19 @@ mod tests {
 
     #[test]
     fn test_get_index() {
-        let runtime_dir = TempDir::default();
-        let index =
-            CocmdHubPackageProvider::get_index(runtime_dir.to_path_buf().as_path(), true).unwrap();
+        let tmp_home_dir = TempDir::default();
+        let settings = Settings::new(tmp_home_dir.to_str(), None);
+        let index = CocmdHubPackageProvider::get_index(&settings, true).unwrap();
         assert!(!index.packages.is_empty());
     }
 
     #[test]
     fn test_get_package() {
-        let runtime_dir = TempDir::default();
-        let provider = CocmdHubPackageProvider::new(&"docker".to_string(), &runtime_dir, None);
+        let tmp_home_dir = TempDir::default();
+        let settings = Settings::new(tmp_home_dir.to_str(), None);
+        let provider = CocmdHubPackageProvider::new("docker", &settings, None).unwrap();
         provider.download();
-        let index =
-            CocmdHubPackageProvider::get_index(runtime_dir.to_path_buf().as_path(), true).unwrap();
+        let index = CocmdHubPackageProvider::get_index(&settings, true).unwrap();
         let package = index.get_package("docker", &None).unwrap();
         assert_eq!(package.name, "docker");
     }
@@ -279,15 +543,12 @@ mod tests {
     fn test_get_package_with_version() {
         // generate a temporary directory, with random name for every test execution
         // it should be deleted in the end of the test automatically
-        let runtime_dir = TempDir::default();
-        let provider = CocmdHubPackageProvider::new(
-            &"aws-s3".to_string(),
-            &runtime_dir,
-            Some(String::from("0.0.0")),
-        );
+        let tmp_home_dir = TempDir::default();
+        let settings = Settings::new(tmp_home_dir.to_str(), None);
+        let provider =
+            CocmdHubPackageProvider::new("aws-s3", &settings, Some(String::from("0.0.0"))).unwrap();
         provider.download();
-        let index =
-            CocmdHubPackageProvider::get_index(runtime_dir.to_path_buf().as_path(), true).unwrap();
+        let index = CocmdHubPackageProvider::get_index(&settings, true).unwrap();
         let package = index
             .get_package("aws-s3", &Some("0.0.0".to_string()))
             .unwrap();
@@ -297,30 +558,276 @@ mod tests {
 
     #[test]
     fn test_get_package_with_version_not_found() {
-        let runtime_dir = TempDir::default();
-        let provider = CocmdHubPackageProvider::new(
-            &"docker".to_string(),
-            &runtime_dir,
-            Some(String::from("0.0.0")),
-        );
+        let tmp_home_dir = TempDir::default();
+        let settings = Settings::new(tmp_home_dir.to_str(), None);
+        let provider =
+            CocmdHubPackageProvider::new("docker", &settings, Some(String::from("0.0.0"))).unwrap();
         provider.download();
-        let index =
-            CocmdHubPackageProvider::get_index(runtime_dir.to_path_buf().as_path(), true).unwrap();
-        let package = index
-            .get_package("docker", &Some("20.10.9".to_string()))
-            .unwrap();
-        assert_eq!(package.name, "docker");
-        assert_eq!(package.version, "0.0.0");
+        let index = CocmdHubPackageProvider::get_index(&settings, true).unwrap();
+        let res = index.get_package("docker", &Some("20.10.9".to_string()));
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("no version of package 'docker' satisfies '20.10.9'"));
     }
 
     #[test]
     fn test_get_package_not_found() {
-        let runtime_dir = TempDir::default();
-        let provider = CocmdHubPackageProvider::new(&"docker".to_string(), &runtime_dir, None);
+        let tmp_home_dir = TempDir::default();
+        let settings = Settings::new(tmp_home_dir.to_str(), None);
+        let provider = CocmdHubPackageProvider::new("docker", &settings, None).unwrap();
         provider.download();
-        let index =
-            CocmdHubPackageProvider::get_index(runtime_dir.to_path_buf().as_path(), true).unwrap();
+        let index = CocmdHubPackageProvider::get_index(&settings, true).unwrap();
         let package = index.get_package("docker2", &None);
-        assert!(package.is_none());
+        assert!(package.is_err());
+    }
+
+    #[test]
+    fn test_get_package_constraints() {
+        let package = |version: &str| PackageInfo {
+            name: "docker".to_string(),
+            title: "docker".to_string(),
+            author: "cocmd".to_string(),
+            description: "".to_string(),
+            version: version.to_string(),
+            archive_url: "".to_string(),
+            archive_sha256_url: "".to_string(),
+            registry: "".to_string(),
+        };
+        let index = PackageIndex {
+            last_update: 0,
+            packages: ["1.2.0", "1.10.0", "2.0.0-beta.1", "0.3.1"]
+                .into_iter()
+                .map(package)
+                .collect(),
+        };
+        let version = |constraint: Option<&str>| {
+            index
+                .get_package("docker", &constraint.map(String::from))
+                .map(|package| package.version)
+        };
+
+        assert_eq!(version(None).unwrap(), "1.10.0");
+        assert_eq!(version(Some("latest")).unwrap(), "1.10.0");
+        assert_eq!(version(Some("^1.2")).unwrap(), "1.10.0");
+        assert_eq!(version(Some("1.2.0")).unwrap(), "1.2.0");
+        assert_eq!(version(Some("~0.3")).unwrap(), "0.3.1");
+        assert_eq!(version(Some(">=2.0.0-alpha")).unwrap(), "2.0.0-beta.1");
+        assert_eq!(
+            version(Some("^3")).unwrap_err().to_string(),
+            "no version of package 'docker' satisfies '^3' in the cocmd hub \
+             (available: 0.3.1, 1.2.0, 1.10.0, 2.0.0-beta.1)"
+        );
+    }
+
+    #[test]
+    fn test_check_update() {
+        let tmp_home_dir = tempdir::TempDir::new("cocmd").unwrap();
+        let settings = Settings::new(tmp_home_dir.path().to_str(), None);
+        let package = |version: &str| PackageInfo {
+            name: "docker".to_string(),
+            title: "docker".to_string(),
+            author: "cocmd".to_string(),
+            description: "".to_string(),
+            version: version.to_string(),
+            archive_url: "".to_string(),
+            archive_sha256_url: "".to_string(),
+            registry: "".to_string(),
+        };
+        let index = PackageIndex {
+            last_update: 0,
+            packages: vec![package("1.2.0"), package("1.10.0"), package("2.0.0")],
+        };
+        let registry = &settings.config.registries[0];
+        CocmdHubPackageProvider::save_index_to_cache(
+            &settings
+                .runtime_dir
+                .join(REGISTRIES_DIR)
+                .join(&registry.name),
+            &registry.url,
+            index,
+        )
+        .unwrap();
+
+        let installed = |version: &str, constraint: Option<&str>| {
+            let dir = settings.runtime_dir.join("docker");
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(
+                dir.join(consts::SOURCE_CONFIG_FILE),
+                format!("name: docker\nversion: {}\n", version),
+            )
+            .unwrap();
+            CocmdHubPackageProvider::new("docker", &settings, constraint.map(String::from))
+                .unwrap()
+                .check_update()
+                .unwrap()
+        };
+        assert_eq!(
+            installed("1.2.0", None),
+            UpdateCheck {
+                installed: Some("1.2.0".to_string()),
+                latest: Some("2.0.0".to_string()),
+                outdated: true,
+                newest: None,
+            }
+        );
+        assert!(!installed("2.0.0", None).outdated);
+        // a pinned package is never outdated, the newer versions are reported apart
+        assert_eq!(
+            installed("1.2.0", Some("1.2.0")),
+            UpdateCheck {
+                installed: Some("1.2.0".to_string()),
+                latest: Some("1.2.0".to_string()),
+                outdated: false,
+                newest: Some("2.0.0".to_string()),
+            }
+        );
+        assert_eq!(
+            installed("1.2.0", Some("^1")),
+            UpdateCheck {
+                installed: Some("1.2.0".to_string()),
+                latest: Some("1.10.0".to_string()),
+                outdated: true,
+                newest: Some("2.0.0".to_string()),
+            }
+        );
+    }
+
+    // a registry in a directory, with the archive of every version
+    pub(crate) fn write_registry(dir: &Path, packages: &[(&str, &str)]) {
+        use sha2::Digest;
+
+        std::fs::create_dir_all(dir).unwrap();
+        let mut infos = vec![];
+        for (name, version) in packages {
+            let archive = dir.join(format!("{}-{}.zip", name, version));
+            let mut zip = zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+            zip.start_file(consts::SOURCE_CONFIG_FILE, Default::default())
+                .unwrap();
+            std::io::Write::write_all(
+                &mut zip,
+                format!("name: {}\nversion: {}\n", name, version).as_bytes(),
+            )
+            .unwrap();
+            zip.finish().unwrap();
+            let sha256 = hex::encode(sha2::Sha256::digest(std::fs::read(&archive).unwrap()));
+            std::fs::write(archive.with_extension("sha256"), sha256).unwrap();
+            infos.push(serde_json::json!({
+                "name": name,
+                "title": name,
+                "author": "cocmd",
+                "description": "",
+                "version": version,
+                "archive_url": format!("file://{}", archive.display()),
+                "archive_sha256_url": format!("file://{}", archive.with_extension("sha256").display()),
+            }));
+        }
+        std::fs::write(
+            dir.join(PACKAGE_INDEX_FILE),
+            serde_json::json!({ "last_update": 0, "packages": infos }).to_string(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_registries() {
+        let root = tempdir::TempDir::new("cocmd").unwrap();
+        let public = root.path().join("public");
+        let acme = root.path().join("acme");
+        write_registry(
+            &public,
+            &[("docker", "1.0.0"), ("docker", "2.0.0"), ("k8s", "1.0.0")],
+        );
+        write_registry(&acme, &[("docker", "1.5.0")]);
+        let home = root.path().join("home");
+        std::fs::create_dir_all(&home).unwrap();
+        std::fs::write(
+            home.join(consts::CONFIG_FILE),
+            format!(
+                "registries:\n  - name: cocmd\n    url: file://{}\n  - name: acme\n    url: file://{}\n    priority: 10\n",
+                public.display(),
+                acme.display()
+            ),
+        )
+        .unwrap();
+        let settings = Settings::new(home.to_str(), None);
+
+        let index = CocmdHubPackageProvider::get_index(&settings, false).unwrap();
+        let resolve = |package: &str, version: Option<&str>| {
+            let info = index
+                .get_package(package, &version.map(String::from))
+                .unwrap();
+            format!("{}:{}@{}", info.registry, info.name, info.version)
+        };
+        // acme comes first, unless it has nothing that satisfies the constraint
+        assert_eq!(resolve("docker", None), "acme:docker@1.5.0");
+        assert_eq!(resolve("docker", Some("^2")), "cocmd:docker@2.0.0");
+        assert_eq!(resolve("cocmd:docker", None), "cocmd:docker@2.0.0");
+        assert_eq!(resolve("k8s", None), "cocmd:k8s@1.0.0");
+        assert!(index.get_package("acme:k8s", &None).is_err());
+
+        // every registry has a cache of its own
+        for registry in ["cocmd", "acme"] {
+            assert!(settings
+                .runtime_dir
+                .join(REGISTRIES_DIR)
+                .join(registry)
+                .join(PACKAGE_INDEX_CACHE_FILE)
+                .is_file());
+        }
+
+        let provider = CocmdHubPackageProvider::new("cocmd:docker", &settings, None).unwrap();
+        let path = provider.download().unwrap();
+        assert_eq!(
+            path,
+            settings
+                .runtime_dir
+                .join(REGISTRIES_DIR)
+                .join("cocmd")
+                .join("docker")
+        );
+        assert!(provider.is_exists_locally());
+        let locked = provider.lock().unwrap();
+        assert_eq!(locked.registry.as_deref(), Some("cocmd"));
+        assert_eq!(locked.version.as_deref(), Some("2.0.0"));
+
+        let provider = CocmdHubPackageProvider::new("docker", &settings, None).unwrap();
+        assert_eq!(provider.version, "1.5.0");
+        assert!(provider.download().is_ok());
+    }
+
+    #[test]
+    fn test_broken_registry() {
+        let root = tempdir::TempDir::new("cocmd").unwrap();
+        let public = root.path().join("public");
+        let acme = root.path().join("acme");
+        write_registry(&public, &[("docker", "1.0.0")]);
+        write_registry(&acme, &[("k8s", "1.5.0")]);
+        let home = root.path().join("home");
+        std::fs::create_dir_all(&home).unwrap();
+        std::fs::write(
+            home.join(consts::CONFIG_FILE),
+            format!(
+                "registries:\n  - name: cocmd\n    url: file://{}\n  - name: acme\n    url: file://{}\n  - name: gone\n    url: file://{}\n",
+                public.display(),
+                acme.display(),
+                root.path().join("gone").display()
+            ),
+        )
+        .unwrap();
+        let settings = Settings::new(home.to_str(), None);
+
+        // an unreachable registry is skipped
+        let index = CocmdHubPackageProvider::get_index(&settings, true).unwrap();
+        assert!(index.get_package("docker", &None).is_ok());
+        let provider = CocmdHubPackageProvider::new("cocmd:docker", &settings, None).unwrap();
+        assert_eq!(provider.version, "1.0.0");
+        assert!(CocmdHubPackageProvider::new("gone:docker", &settings, None).is_err());
+        assert!(CocmdHubPackageProvider::new("nope:docker", &settings, None).is_err());
+
+        // a registry that broke after it was cached is served from the cache
+        std::fs::write(acme.join(PACKAGE_INDEX_FILE), "not an index").unwrap();
+        let index = CocmdHubPackageProvider::get_index(&settings, true).unwrap();
+        assert_eq!(index.get_package("k8s", &None).unwrap().version, "1.5.0");
     }
 }
@@ -19,10 +19,11 @@
 
 use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 
 use super::PackageProvider;
 use super::LOCAL_PROVIDER;
+use crate::core::lockfile::LockedPackage;
 
 pub struct LocalPackageProvider {
     package: String,
@@ -51,6 +52,24 @@ impl PackageProvider for LocalPackageProvider {
         Ok(self.local_path.to_path_buf())
     }
 
+    // nothing to fetch, the package only has to still be there
+    fn refresh(&self, _dry_run: bool) -> Result<Option<String>> {
+        if !self.local_path.exists() {
+            bail!("{} doesn't exist any more", self.local_path.display());
+        }
+        Ok(None)
+    }
+
+    fn download_locked(&self, _locked: &LockedPackage) -> Result<PathBuf> {
+        if !self.local_path.exists() {
+            bail!(
+                "{} doesn't exist on this machine",
+                self.local_path.display()
+            );
+        }
+        Ok(self.local_path.to_path_buf())
+    }
+
     fn package(&self) -> String {
         self.package.clone()
     }
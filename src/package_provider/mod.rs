@@ -17,10 +17,13 @@
  * along with cocmd.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::path::Path;
 use std::path::PathBuf;
 
 use anyhow::Result;
+use serde_derive::Serialize;
+
+use crate::core::lockfile::LockedPackage;
+use crate::Settings;
 
 mod util;
 
@@ -32,6 +35,18 @@ pub const LOCAL_PROVIDER: &str = "local";
 pub const GIT_PROVIDER: &str = "git";
 pub const COCMDHUB_PROVIDER: &str = "cocmd-hub";
 
+// what check_update found. latest is None when the provider can't tell (local packages), and
+// it's the newest version the package was installed to accept. `newest` is a newer one outside
+// of that
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpdateCheck {
+    pub installed: Option<String>,
+    pub latest: Option<String>,
+    pub outdated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub newest: Option<String>,
+}
+
 pub trait PackageProvider {
     fn name(&self) -> String;
     fn package(&self) -> String;
@@ -44,14 +59,45 @@ pub trait PackageProvider {
         self.local_path().exists()
     }
     fn download(&self) -> Result<PathBuf>;
-    // TODO: fn check update available? (probably should be only available in the hub)
 
-    fn is_provider_local(&self) -> bool {
-        self.name() == LOCAL_PROVIDER
+    // what cocmd.lock keeps of the installed package, the caller fills in its name
+    fn lock(&self) -> Result<LockedPackage> {
+        Ok(LockedPackage {
+            uri: self.package(),
+            provider: self.name(),
+            ..Default::default()
+        })
+    }
+
+    // install exactly what was locked, or fail
+    fn download_locked(&self, _locked: &LockedPackage) -> Result<PathBuf> {
+        if self.is_exists_locally() {
+            return Ok(self.local_path());
+        }
+        self.download()
     }
 
-    fn is_provider_git(&self) -> bool {
-        self.name() == GIT_PROVIDER
+    // the installed version or commit, shown by `cocmd refresh`
+    fn installed_revision(&self) -> Option<String> {
+        None
+    }
+
+    // bring the installed package up to date and return the revision it's at now. with
+    // dry_run nothing changes, it's the revision it would be at
+    fn refresh(&self, _dry_run: bool) -> Result<Option<String>> {
+        Ok(self.installed_revision())
+    }
+
+    // whether something newer than the installed package is available, nothing is downloaded
+    fn check_update(&self) -> Result<UpdateCheck> {
+        Ok(UpdateCheck {
+            installed: self.installed_revision(),
+            ..Default::default()
+        })
+    }
+
+    fn is_provider_local(&self) -> bool {
+        self.name() == LOCAL_PROVIDER
     }
 
     fn is_provider_hub(&self) -> bool {
@@ -61,27 +107,27 @@ pub trait PackageProvider {
 
 pub fn get_provider(
     uri: &String,
-    runtime_dir: &Path,
+    settings: &Settings,
     version: Option<String>,
 ) -> Result<Box<dyn PackageProvider>> {
+    let runtime_dir = settings.runtime_dir.as_path();
     // parse "package" if it's a local path create a LocalPackageProvider
     // if it's a git url create a GitPackageProvider
     // otherwise look for it in the hub and create a HubPackageProvider
 
-    if let Some(local_path) = util::path::extract_local_path(uri) {
-        Ok(Box::new(local::LocalPackageProvider::new(uri, &local_path)))
-    } else if let Some(github_parts) = util::git::extract_git_url_parts(uri) {
-        return Ok(Box::new(git::GitPackageProvider::new(
+    // git first, a bare repository is a path too
+    if let Some(git_parts) = util::git::extract_git_url_parts(uri) {
+        Ok(Box::new(git::GitPackageProvider::new(
             uri,
-            &github_parts,
+            &git_parts,
             runtime_dir,
-        )));
+        )))
+    } else if let Some(local_path) = util::path::extract_local_path(uri) {
+        Ok(Box::new(local::LocalPackageProvider::new(uri, &local_path)))
     } else {
-        return Ok(Box::new(hub::CocmdHubPackageProvider::new(
-            uri,
-            runtime_dir,
-            version,
-        )));
+        Ok(Box::new(hub::CocmdHubPackageProvider::new(
+            uri, settings, version,
+        )?))
     }
 }
 
@@ -89,6 +135,8 @@ pub fn get_provider(
 // and that the correct provider is returned for each case
 #[cfg(test)]
 mod tests {
+    use std::path::Path;
+
     use temp_testdir::TempDir;
 
     use super::*;
@@ -96,12 +144,8 @@ mod tests {
     #[test]
     fn test_get_provider() {
         let tmp_home_dir = TempDir::default();
-        let runtime_dir = tmp_home_dir.join("runtime");
-
-        // create runtime_dir if not exists
-        if !runtime_dir.exists() {
-            std::fs::create_dir_all(&runtime_dir).unwrap();
-        }
+        let settings = Settings::new(tmp_home_dir.to_str(), None);
+        let runtime_dir = settings.runtime_dir.clone();
 
         let git_url = "git@github.com:mzsrtgzt2/cocmd.git";
         let git_url2 = "https://github.com/mzsrtgzr2/cocmd";
@@ -111,26 +155,24 @@ mod tests {
             .to_string_lossy()
             .to_string();
 
-        let provider =
-            get_provider(&git_url.to_string(), &runtime_dir.to_path_buf(), None).unwrap();
+        let provider = get_provider(&git_url.to_string(), &settings, None).unwrap();
         assert_eq!(provider.name(), GIT_PROVIDER);
-        assert!(provider.is_provider_git());
-        assert_eq!(
-            provider.local_path(),
-            runtime_dir.join("mzsrtgzt2.cocmd").to_path_buf()
-        );
+        assert_eq!(provider.name(), GIT_PROVIDER);
+        let git_path = provider.local_path();
+        assert_eq!(git_path.parent(), Some(runtime_dir.as_path()));
+        assert!(git_path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("cocmd-"));
 
-        let provider =
-            get_provider(&git_url2.to_string(), &runtime_dir.to_path_buf(), None).unwrap();
+        let provider = get_provider(&git_url2.to_string(), &settings, None).unwrap();
         assert_eq!(provider.name(), GIT_PROVIDER);
-        assert!(provider.is_provider_git());
-        assert_eq!(
-            provider.local_path(),
-            runtime_dir.join("mzsrtgzr2.cocmd").to_path_buf()
-        );
+        assert_eq!(provider.name(), GIT_PROVIDER);
+        assert_eq!(provider.local_path().parent(), Some(runtime_dir.as_path()));
+        assert_ne!(provider.local_path(), git_path);
 
-        let provider =
-            get_provider(&hub_url.to_string(), &runtime_dir.to_path_buf(), None).unwrap();
+        let provider = get_provider(&hub_url.to_string(), &settings, None).unwrap();
         assert_eq!(provider.name(), COCMDHUB_PROVIDER);
         assert!(provider.is_provider_hub());
         assert_eq!(
@@ -138,7 +180,7 @@ mod tests {
             runtime_dir.join("cocmd-hub").to_path_buf()
         );
 
-        let provider = get_provider(&local_url, &runtime_dir.to_path_buf(), None).unwrap();
+        let provider = get_provider(&local_url, &settings, None).unwrap();
         assert_eq!(provider.name(), LOCAL_PROVIDER);
         assert!(provider.is_provider_local());
         assert_eq!(provider.local_path(), Path::new(&local_url).to_path_buf());
@@ -46,10 +46,15 @@ pub fn read_string_from_url(url: &str) -> Result<String> {
 }
 
 fn download(url: &str) -> Result<Vec<u8>> {
+    // a registry in a local directory
+    if let Some(path) = url.strip_prefix("file://") {
+        return std::fs::read(path).with_context(|| format!("unable to read {}", path));
+    }
+
     let client = reqwest::blocking::Client::builder();
     let client = client.build()?;
 
-    let mut response = client.get(url).send()?;
+    let mut response = client.get(url).send()?.error_for_status()?;
 
     let mut buffer = Vec::new();
     copy(&mut response, &mut buffer)?;
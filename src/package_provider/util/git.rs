@@ -17,10 +17,12 @@
  * along with cocmd.  If not, see <https://www.gnu.org/licenses/>.
  */
 #![allow(dead_code)]
+use std::path::Path;
 use std::process::Command;
 
-use lazy_static::lazy_static;
-use regex::Regex;
+use sha2::{Digest, Sha256};
+
+use crate::core::consts;
 
 pub fn is_private_repo(url: &str) -> bool {
     if let Ok(output) = Command::new("git").arg("ls-remote").arg(url).output() {
@@ -31,27 +33,254 @@ pub fn is_private_repo(url: &str) -> bool {
 
     false
 }
-lazy_static! {
-    static ref GIT_REGEX: Regex =
-        Regex::new(r"^(https://|git@)(?P<host>[^:/]+)([/|:])(?P<author>.*?)/(?P<name>.*?)(/|\.|$)")
-            .unwrap();
-}
+
+// the git urls cocmd understands:
+//   https://host[:port]/group/sub/repo[.git]    (http:// and git:// too)
+//   ssh://[user@]host[:port]/group/repo[.git]
+//   user@host:group/repo[.git]
+//   file:///srv/repos/repo.git
+//   /srv/repos/repo.git                         (a bare repository, or a path ending with .git)
+// each of them can end with `//path/in/repo` and `#ref`
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct GitParts {
+    // empty for file:// urls and paths
     pub host: String,
+    pub port: Option<u16>,
+    // the groups the repo is in, `group/sub` of gitlab.com/group/sub/repo
     pub author: String,
     pub name: String,
+    // the repository to clone, without the suffixes below
+    pub url: String,
+    // `#v1.2.0`: a branch, a tag or a commit
+    pub reference: Option<String>,
+    // `//path/in/repo`: the package is in this directory of the repository
+    pub subdir: Option<String>,
+}
+
+impl GitParts {
+    // the directory of the checkout in the runtime dir. readable, and unique per repository,
+    // directory and ref: the same repository over https and ssh shares it, the same name on
+    // two hosts doesn't
+    pub fn dir_name(&self) -> String {
+        let mut identity = format!(
+            "{}:{}/{}/{}",
+            self.host,
+            self.port.unwrap_or(0),
+            self.author,
+            self.name
+        );
+        let mut readable = self.name.clone();
+        if let Some(subdir) = &self.subdir {
+            identity.push_str(&format!("//{}", subdir));
+            readable.push_str(&format!(".{}", subdir.replace('/', ".")));
+        }
+        if let Some(reference) = &self.reference {
+            identity.push_str(&format!("#{}", reference));
+            readable.push_str(&format!("@{}", reference.replace('/', "-")));
+        }
+        let readable: String = readable
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || "._-@".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let hash = hex::encode(Sha256::digest(identity.as_bytes()));
+        format!("{}-{}", readable, &hash[..12])
+    }
+}
+
+// `url[//path/in/repo][#ref]`
+fn split_suffixes(uri: &str) -> (&str, Option<String>, Option<String>) {
+    let (rest, reference) = match uri.split_once('#') {
+        Some((rest, reference)) if !reference.is_empty() => (rest, Some(reference.to_string())),
+        Some((rest, _)) => (rest, None),
+        None => (uri, None),
+    };
+    // `file:///srv` has the path right after the scheme
+    let scheme_end = rest
+        .find(":///")
+        .map_or_else(|| rest.find("://").map_or(0, |pos| pos + 3), |pos| pos + 4);
+    match rest[scheme_end..].find("//") {
+        Some(pos) => {
+            let subdir = rest[scheme_end + pos + 2..].trim_matches('/');
+            (
+                &rest[..scheme_end + pos],
+                (!subdir.is_empty()).then(|| subdir.to_string()),
+                reference,
+            )
+        }
+        None => (rest, None, reference),
+    }
+}
+
+// host, port and the path of the repository on it
+fn split_location(url: &str) -> Option<(String, Option<u16>, String)> {
+    if let Some((scheme, rest)) = url.split_once("://") {
+        return match scheme {
+            "https" | "http" | "git" | "ssh" | "git+ssh" | "ssh+git" => {
+                let (authority, path) = rest.split_once('/')?;
+                let host_port = authority
+                    .rsplit_once('@')
+                    .map_or(authority, |(_, host)| host);
+                let (host, port) = match host_port.rsplit_once(':') {
+                    Some((host, port)) => (host, Some(port.parse().ok()?)),
+                    None => (host_port, None),
+                };
+                if host.is_empty() {
+                    return None;
+                }
+                Some((host.to_lowercase(), port, path.to_string()))
+            }
+            "file" => Some((String::new(), None, rest.to_string())),
+            _ => None,
+        };
+    }
+
+    // scp-like `user@host:path`
+    if let Some((user_host, path)) = url.split_once(':') {
+        if let Some((_, host)) = user_host.split_once('@') {
+            if !host.is_empty() && !host.contains('/') && !path.starts_with('/') {
+                return Some((host.to_lowercase(), None, path.to_string()));
+            }
+        }
+    }
+
+    // a path to a bare repository
+    let path = Path::new(url);
+    let is_bare_repo = path.join("HEAD").is_file() && path.join("objects").is_dir();
+    let is_git_path = url.ends_with(".git") && !path.join(consts::SOURCE_CONFIG_FILE).exists();
+    if path.is_absolute() && (is_bare_repo || is_git_path) {
+        return Some((String::new(), None, url.to_string()));
+    }
+    None
 }
 
 pub fn extract_git_url_parts(url: &str) -> Option<GitParts> {
-    let captures = GIT_REGEX.captures(url)?;
-    let host = captures.name("host")?;
-    let author = captures.name("author")?;
-    let name = captures.name("name")?;
+    let (repo_url, subdir, reference) = split_suffixes(url);
+    let (host, port, path) = split_location(repo_url)?;
+    let path = path.trim_matches('/');
+    let (author, name) = path.rsplit_once('/').unwrap_or(("", path));
+    let name = name.strip_suffix(".git").unwrap_or(name);
+    if name.is_empty() {
+        return None;
+    }
 
     Some(GitParts {
-        host: host.as_str().to_string(),
-        author: author.as_str().to_string(),
-        name: name.as_str().to_string(),
+        host,
+        port,
+        author: author.to_string(),
+        name: name.to_string(),
+        url: repo_url.to_string(),
+        reference,
+        subdir,
     })
 }
+
+// a commit rather than a branch or a tag, what `git clone --branch` can't take
+pub fn is_commit_sha(reference: &str) -> bool {
+    (7..=40).contains(&reference.len()) && reference.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_extract_git_url_parts() {
+        let parts = extract_git_url_parts("https://github.com/acme/tools").unwrap();
+        assert_eq!(parts.host, "github.com");
+        assert_eq!(parts.author, "acme");
+        assert_eq!(parts.name, "tools");
+        assert_eq!(parts.url, "https://github.com/acme/tools");
+        assert_eq!(parts.reference, None);
+        assert_eq!(parts.subdir, None);
+
+        let parts = extract_git_url_parts("https://github.com/acme/tools#v1.2.0").unwrap();
+        assert_eq!(parts.name, "tools");
+        assert_eq!(parts.url, "https://github.com/acme/tools");
+        assert_eq!(parts.reference.as_deref(), Some("v1.2.0"));
+
+        let parts =
+            extract_git_url_parts("git@github.com:acme/mono.git//packages/docker#3f1c2ab").unwrap();
+        assert_eq!(parts.name, "mono");
+        assert_eq!(parts.url, "git@github.com:acme/mono.git");
+        assert_eq!(parts.subdir.as_deref(), Some("packages/docker"));
+        assert_eq!(parts.reference.as_deref(), Some("3f1c2ab"));
+
+        let parts =
+            extract_git_url_parts("ssh://git@gitlab.internal:2222/group/sub/repo.git").unwrap();
+        assert_eq!(parts.host, "gitlab.internal");
+        assert_eq!(parts.port, Some(2222));
+        assert_eq!(parts.author, "group/sub");
+        assert_eq!(parts.name, "repo");
+
+        let parts = extract_git_url_parts("https://gitlab.com/group/sub/repo//pkg").unwrap();
+        assert_eq!(parts.author, "group/sub");
+        assert_eq!(parts.subdir.as_deref(), Some("pkg"));
+
+        let parts = extract_git_url_parts("file:///srv/repos/x.git//pkg#main").unwrap();
+        assert_eq!(parts.host, "");
+        assert_eq!(parts.author, "srv/repos");
+        assert_eq!(parts.name, "x");
+        assert_eq!(parts.url, "file:///srv/repos/x.git");
+        assert_eq!(parts.subdir.as_deref(), Some("pkg"));
+        assert_eq!(parts.reference.as_deref(), Some("main"));
+
+        let parts = extract_git_url_parts("/srv/repos/x.git").unwrap();
+        assert_eq!(parts.url, "/srv/repos/x.git");
+        assert_eq!(parts.name, "x");
+
+        assert_eq!(extract_git_url_parts("docker"), None);
+        assert_eq!(extract_git_url_parts("/srv/packages/docker"), None);
+        assert_eq!(extract_git_url_parts("ftp://host/acme/tools"), None);
+
+        assert!(is_commit_sha("3f1c2ab"));
+        assert!(!is_commit_sha("v1.2.0"));
+        assert!(!is_commit_sha("beef"));
+    }
+
+    #[test]
+    fn test_bare_repository_path() {
+        let root = TempDir::new("cocmd").unwrap();
+        let repo = root.path().join("tools");
+        std::fs::create_dir_all(repo.join("objects")).unwrap();
+        std::fs::write(repo.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        let parts = extract_git_url_parts(repo.to_str().unwrap()).unwrap();
+        assert_eq!(parts.name, "tools");
+    }
+
+    #[test]
+    fn test_dir_name() {
+        let dir_name = |url: &str| extract_git_url_parts(url).unwrap().dir_name();
+        assert_eq!(
+            dir_name("https://github.com/acme/tools.git"),
+            dir_name("git@github.com:acme/tools")
+        );
+        assert_eq!(
+            dir_name("https://github.com/acme/tools"),
+            dir_name("ssh://git@GitHub.com/acme/tools.git")
+        );
+        assert_ne!(
+            dir_name("https://github.com/acme/tools"),
+            dir_name("https://gitlab.com/acme/tools")
+        );
+        assert_ne!(
+            dir_name("https://github.com/a.b/c"),
+            dir_name("https://github.com/a/b.c")
+        );
+        assert_ne!(
+            dir_name("https://github.com/acme/tools"),
+            dir_name("https://github.com/acme/tools#v1")
+        );
+        assert!(
+            dir_name("https://github.com/acme/mono//packages/docker#v1.0")
+                .starts_with("mono.packages.docker@v1.0-")
+        );
+    }
+}
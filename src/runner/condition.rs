@@ -0,0 +1,356 @@
+// a tiny expression language for `when:` fields on scripts and steps.
+//
+// examples:
+//   os == 'macos'
+//   env.CI != '' && !succeeded('install node')
+//   params.region =~ '^eu-' || use_docker
+//
+// identifiers:
+//   os            - the detected os, lowercase ("linux", "macos", "windows", "other")
+//   env.NAME      - environment variable NAME (empty string when not set)
+//   params.NAME   - parameter NAME (empty string when not set). a bare NAME works too
+// functions:
+//   succeeded('step title'), failed('step title'), skipped('step title')
+// operators:
+//   ==, !=, =~ (regex match), &&/and, ||/or, !/not, parentheses
+//
+// any value is truthy unless it's empty, "false", "no" or "0".
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use regex::Regex;
+
+use super::{StepReport, StepStatus};
+use crate::core::utils::sys::OS;
+
+pub struct ConditionContext<'a> {
+    pub os: OS,
+    pub params: &'a HashMap<String, String>,
+    pub steps: &'a [StepReport],
+}
+
+impl<'a> ConditionContext<'a> {
+    fn os_name(&self) -> String {
+        self.os.to_string().to_lowercase()
+    }
+
+    fn lookup(&self, ident: &str) -> String {
+        if ident == "os" || ident == "OS" {
+            return self.os_name();
+        }
+        if let Some(name) = ident.strip_prefix("env.") {
+            return std::env::var(name).unwrap_or_default();
+        }
+        let name = ident.strip_prefix("params.").unwrap_or(ident);
+        self.params.get(name).cloned().unwrap_or_default()
+    }
+
+    fn step_status(&self, title: &str) -> Option<StepStatus> {
+        // the latest step with this title wins
+        self.steps
+            .iter()
+            .rev()
+            .find(|report| report.title == title)
+            .map(|report| report.status)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Eq,
+    NotEq,
+    Match,
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let chars: Vec<char> = expr.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            ' ' | '\t' | '\n' | '\r' => i += 1,
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            '=' if next == Some('=') => {
+                tokens.push(Token::Eq);
+                i += 2;
+            }
+            '=' if next == Some('~') => {
+                tokens.push(Token::Match);
+                i += 2;
+            }
+            '!' if next == Some('=') => {
+                tokens.push(Token::NotEq);
+                i += 2;
+            }
+            '!' => {
+                tokens.push(Token::Not);
+                i += 1;
+            }
+            '&' if next == Some('&') => {
+                tokens.push(Token::And);
+                i += 2;
+            }
+            '|' if next == Some('|') => {
+                tokens.push(Token::Or);
+                i += 2;
+            }
+            '\'' | '"' => {
+                let quote = c;
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some('\\') if chars.get(i + 1) == Some(&quote) => {
+                            value.push(quote);
+                            i += 2;
+                        }
+                        Some(ch) if *ch == quote => {
+                            i += 1;
+                            break;
+                        }
+                        Some(ch) => {
+                            value.push(*ch);
+                            i += 1;
+                        }
+                        None => bail!("unterminated string in expression '{}'", expr),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '.' || c == '-' => {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '.' | '-'))
+                {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                tokens.push(match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Ident(word),
+                });
+            }
+            _ => bail!("unexpected character '{}' in expression '{}'", c, expr),
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Str(String),
+    Bool(bool),
+}
+
+impl Value {
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            Value::Str(s) => !matches!(s.trim().to_lowercase().as_str(), "" | "false" | "no" | "0"),
+        }
+    }
+
+    fn as_string(&self) -> String {
+        match self {
+            Value::Bool(b) => b.to_string(),
+            Value::Str(s) => s.clone(),
+        }
+    }
+}
+
+struct Parser<'a, 'b> {
+    tokens: Vec<Token>,
+    pos: usize,
+    ctx: &'b ConditionContext<'a>,
+}
+
+impl<'a, 'b> Parser<'a, 'b> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Value> {
+        let mut left = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.advance();
+            let right = self.parse_and()?;
+            left = Value::Bool(left.truthy() || right.truthy());
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Value> {
+        let mut left = self.parse_not()?;
+        while self.peek() == Some(&Token::And) {
+            self.advance();
+            let right = self.parse_not()?;
+            left = Value::Bool(left.truthy() && right.truthy());
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Value> {
+        if self.peek() == Some(&Token::Not) {
+            self.advance();
+            let value = self.parse_not()?;
+            return Ok(Value::Bool(!value.truthy()));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Value> {
+        let left = self.parse_primary()?;
+        let op = match self.peek() {
+            Some(Token::Eq) | Some(Token::NotEq) | Some(Token::Match) => self.advance().unwrap(),
+            _ => return Ok(left),
+        };
+        let right = self.parse_primary()?;
+        let (left, right) = (left.as_string(), right.as_string());
+
+        Ok(Value::Bool(match op {
+            Token::Eq => left == right,
+            Token::NotEq => left != right,
+            Token::Match => Regex::new(&right)
+                .map_err(|e| anyhow::anyhow!("invalid regex '{}': {}", right, e))?
+                .is_match(&left),
+            _ => unreachable!(),
+        }))
+    }
+
+    fn parse_primary(&mut self) -> Result<Value> {
+        match self.advance() {
+            Some(Token::Str(s)) => Ok(Value::Str(s)),
+            Some(Token::LParen) => {
+                let value = self.parse_or()?;
+                if self.advance() != Some(Token::RParen) {
+                    bail!("expected ')'");
+                }
+                Ok(value)
+            }
+            Some(Token::Ident(ident)) => {
+                if self.peek() == Some(&Token::LParen) {
+                    return self.parse_call(&ident);
+                }
+                Ok(match ident.as_str() {
+                    "true" => Value::Bool(true),
+                    "false" => Value::Bool(false),
+                    _ if ident.chars().next().unwrap().is_ascii_digit() => Value::Str(ident),
+                    _ => Value::Str(self.ctx.lookup(&ident)),
+                })
+            }
+            Some(token) => bail!("unexpected token {:?}", token),
+            None => bail!("unexpected end of expression"),
+        }
+    }
+
+    fn parse_call(&mut self, name: &str) -> Result<Value> {
+        self.advance(); // (
+        let arg = match self.advance() {
+            Some(Token::Str(s)) => s,
+            _ => bail!("{}() expects a quoted step title", name),
+        };
+        if self.advance() != Some(Token::RParen) {
+            bail!("expected ')' after {}('{}'", name, arg);
+        }
+
+        let status = self.ctx.step_status(&arg);
+        Ok(Value::Bool(match name {
+            "succeeded" => status == Some(StepStatus::Success),
+            "failed" => status == Some(StepStatus::Failed),
+            "skipped" => status == Some(StepStatus::Skipped),
+            _ => bail!("unknown function '{}'", name),
+        }))
+    }
+}
+
+pub fn evaluate(expr: &str, ctx: &ConditionContext) -> Result<bool> {
+    let tokens = tokenize(expr)?;
+    if tokens.is_empty() {
+        return Ok(true);
+    }
+
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        ctx,
+    };
+    let value = parser.parse_or()?;
+    if let Some(token) = parser.peek() {
+        bail!("unexpected token {:?} in expression '{}'", token, expr);
+    }
+    Ok(value.truthy())
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::hashmap;
+
+    use super::*;
+
+    fn eval(expr: &str) -> Result<bool> {
+        let params = hashmap! {
+            "region".to_string() => "eu-west-1".to_string(),
+            "use_docker".to_string() => "false".to_string(),
+        };
+        let steps = vec![
+            StepReport::new("clone").finish(StepStatus::Success),
+            StepReport::new("seed").finish(StepStatus::Failed),
+            StepReport::new("docs").finish(StepStatus::Skipped),
+        ];
+        let ctx = ConditionContext {
+            os: OS::Linux,
+            params: &params,
+            steps: &steps,
+        };
+        evaluate(expr, &ctx)
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert!(eval("os == 'linux'").unwrap());
+        assert!(!eval("OS != \"linux\"").unwrap());
+        assert!(eval("params.region =~ '^eu-'").unwrap());
+        assert!(eval("region == 'eu-west-1' and not use_docker").unwrap());
+        assert!(!eval("use_docker || missing").unwrap());
+        assert!(eval("succeeded('clone') && failed('seed') && skipped('docs')").unwrap());
+        assert!(!eval("succeeded('unknown step')").unwrap());
+        assert!(eval("!(os == 'windows' || os == 'macos')").unwrap());
+        assert!(eval("").unwrap());
+    }
+
+    #[test]
+    fn test_evaluate_errors() {
+        assert!(eval("os == 'linux").is_err());
+        assert!(eval("os == ").is_err());
+        assert!(eval("(os == 'linux'").is_err());
+        assert!(eval("nope('x')").is_err());
+        assert!(eval("os == 'linux' 'x'").is_err());
+    }
+}
@@ -1,49 +1,679 @@
 use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
 
-use anyhow::{Error, Result};
+use anyhow::{Context, Error, Result};
+use log::error;
+use tempdir::TempDir;
 
+use self::condition::ConditionContext;
+use self::shell::ShellOutput;
+use crate::core::models::script_model::{ScriptModel, StepModel};
+use crate::core::packages_manager::PackagesManager;
 use crate::core::utils::sys::OS;
-use crate::core::{models::script_model::ScriptModel, packages_manager::PackagesManager};
 use crate::output::print_md_debug;
+mod condition;
+mod parallel;
 pub mod shell;
 mod step_runner;
+pub mod template;
 mod utils;
 
-pub fn run_script(
-    automation_name: &String,
-    script: &ScriptModel,
+// steps can write `key=value` lines to the file in this env var to pass values to later steps
+pub const OUTPUT_FILE_ENV: &str = "COCMD_OUTPUT";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepStatus {
+    Success,
+    Failed,
+    Skipped,
+}
+
+impl StepStatus {
+    pub fn icon(&self) -> &'static str {
+        match self {
+            StepStatus::Success => "✅",
+            StepStatus::Failed => "❌",
+            StepStatus::Skipped => "⏭️",
+        }
+    }
+}
+
+// the result of a single run of a step. steps with retries may have several
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttemptOutcome {
+    Success,
+    Failed,
+    TimedOut,
+}
+
+impl AttemptOutcome {
+    pub fn icon(&self) -> &'static str {
+        match self {
+            AttemptOutcome::Success => "✅",
+            AttemptOutcome::Failed => "❌",
+            AttemptOutcome::TimedOut => "⏱️",
+        }
+    }
+}
+
+// what a single run of a step produced
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepExecution {
+    pub outcome: AttemptOutcome,
+    pub output: ShellOutput,
+}
+
+impl StepExecution {
+    pub fn new(outcome: AttemptOutcome, output: ShellOutput) -> Self {
+        Self { outcome, output }
+    }
+}
+
+impl Default for StepExecution {
+    fn default() -> Self {
+        Self::new(AttemptOutcome::Success, ShellOutput::default())
+    }
+}
+
+// state shared by the steps of a single run
+pub struct RunContext {
+    // values from `register:` and from $COCMD_OUTPUT files of previous steps
+    pub outputs: HashMap<String, String>,
+    // the package the script is from, its saved params come before the global ones
+    pub package: Option<String>,
+    // the directory of the package the script is from, {{ package_dir }} in templates
+    pub package_dir: Option<PathBuf>,
+    output_dir: TempDir,
+}
+
+impl RunContext {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            outputs: HashMap::new(),
+            package: None,
+            package_dir: None,
+            output_dir: TempDir::new("cocmd").context("unable to create a temp dir for outputs")?,
+        })
+    }
+
+    pub fn output_file(&self) -> PathBuf {
+        self.output_dir.path().join("output")
+    }
+
+    pub fn reset_output_file(&self) {
+        let _ = fs::write(self.output_file(), "");
+    }
+
+    // read the `key=value` lines the step wrote to $COCMD_OUTPUT
+    pub fn collect_output_file(&mut self) {
+        if let Ok(content) = fs::read_to_string(self.output_file()) {
+            self.outputs.extend(parse_output_lines(&content));
+        }
+    }
+
+    // `register: name` exposes {{ name }} (trimmed stdout), {{ name.stdout }} and {{ name.exit_code }}
+    pub fn register(&mut self, name: &str, execution: &StepExecution) {
+        let stdout = execution
+            .output
+            .stdout
+            .as_deref()
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        let exit_code = execution
+            .output
+            .exit_code
+            .map(|code| code.to_string())
+            .unwrap_or_default();
+
+        self.outputs.insert(name.to_string(), stdout.clone());
+        self.outputs.insert(format!("{}.stdout", name), stdout);
+        self.outputs
+            .insert(format!("{}.exit_code", name), exit_code);
+    }
+}
+
+fn parse_output_lines(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .filter(|(key, _)| !key.is_empty())
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepReport {
+    pub title: String,
+    pub status: StepStatus,
+    pub attempts: Vec<AttemptOutcome>,
+    // the step has `continue_on_error`, a failure doesn't fail the run
+    pub allow_failure: bool,
+}
+
+impl StepReport {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            status: StepStatus::Skipped,
+            attempts: vec![],
+            allow_failure: false,
+        }
+    }
+
+    pub fn is_failure(&self) -> bool {
+        self.status == StepStatus::Failed && !self.allow_failure
+    }
+
+    pub fn finish(mut self, status: StepStatus) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn summary(&self) -> String {
+        let icon = if self.status == StepStatus::Failed && self.allow_failure {
+            "⚠️"
+        } else {
+            self.status.icon()
+        };
+        let mut line = format!("{} {}", icon, self.title);
+        if self.attempts.len() > 1 {
+            let attempts: Vec<&str> = self.attempts.iter().map(|a| a.icon()).collect();
+            line += &format!(
+                " ({} attempts: {})",
+                self.attempts.len(),
+                attempts.join(" ")
+            );
+        }
+        line
+    }
+}
+
+// evaluate a `when:` expression. an invalid expression is reported and treated as an error
+fn check_condition(
+    when: &Option<String>,
+    env: OS,
+    params: &HashMap<String, String>,
+    steps: &[StepReport],
+) -> Result<bool> {
+    match when {
+        Some(expr) => {
+            let ctx = ConditionContext {
+                os: env,
+                params,
+                steps,
+            };
+            condition::evaluate(expr, &ctx).map_err(|e| {
+                error!("invalid `when` expression '{}': {}", expr, e);
+                e
+            })
+        }
+        None => Ok(true),
+    }
+}
+
+// conditions see saved params and outputs of previous steps too, cli params take priority
+fn condition_params(
+    packages_manager: &PackagesManager,
+    params: &HashMap<String, String>,
+    ctx: &RunContext,
+) -> HashMap<String, String> {
+    let mut condition_params = packages_manager
+        .settings
+        .saved_params(ctx.package.as_deref());
+    condition_params.extend(ctx.outputs.clone());
+    condition_params.extend(params.clone());
+    condition_params
+}
+
+// reports for a step that didn't run, one for every step of a `parallel` block
+fn reports_for(step: &StepModel, status: StepStatus) -> Vec<StepReport> {
+    let steps = match &step.parallel {
+        Some(_) => step.parallel_steps(),
+        None => vec![step.clone()],
+    };
+    steps
+        .iter()
+        .map(|step| {
+            let mut report = StepReport::new(&step.title).finish(status);
+            report.allow_failure = step.continue_on_error.unwrap_or(false);
+            report
+        })
+        .collect()
+}
+
+// run a single step or a `parallel` block, honoring its `when:` condition
+fn run_step(
+    step: &StepModel,
     env: OS,
+    script: &ScriptModel,
     packages_manager: &mut PackagesManager,
+    params: &HashMap<String, String>,
+    ctx: &mut RunContext,
+    step_reports: &[StepReport],
+) -> Vec<StepReport> {
+    let condition_params = condition_params(packages_manager, params, ctx);
+    match check_condition(&step.when, env, &condition_params, step_reports) {
+        Ok(true) if step.parallel.is_some() => parallel::run_parallel(
+            step,
+            env,
+            script,
+            packages_manager,
+            params,
+            ctx,
+            step_reports,
+        ),
+        Ok(true) => {
+            let mut reports =
+                step_runner::handle_step(step, env, script, packages_manager, params.clone(), ctx);
+            for report in &mut reports {
+                report.allow_failure = step.continue_on_error.unwrap_or(false);
+            }
+            reports
+        }
+        Ok(false) => {
+            print_md_debug(&format!(
+                "## {}\n⏭️ skipped (`when: {}`)",
+                &step.title,
+                step.when.as_ref().unwrap()
+            ));
+            reports_for(step, StepStatus::Skipped)
+        }
+        Err(_) => reports_for(step, StepStatus::Failed),
+    }
+}
+
+// check the cli params against the declarations of the script and its steps, before anything runs
+fn validate_params(
+    script: &ScriptModel,
     params: HashMap<String, String>,
+) -> Result<HashMap<String, String>> {
+    let declarations = script.param_declarations();
+    let mut validated = HashMap::new();
+    let mut errors = vec![];
+
+    for (name, value) in params {
+        let mut value = value;
+        for declaration in declarations.iter().filter(|p| p.name == name) {
+            match declaration.validate(&value) {
+                Ok(normalized) => value = normalized,
+                Err(err) => errors.push(err),
+            }
+        }
+        validated.insert(name, value);
+    }
+
+    if !errors.is_empty() {
+        errors.sort();
+        errors.dedup();
+        for err in &errors {
+            error!("{}", err);
+        }
+        return Err(Error::msg(format!(
+            "Invalid parameters: {}",
+            errors.join("; ")
+        )));
+    }
+    Ok(validated)
+}
+
+// the checks of run_script that don't need anything to run, for every automation of an execution
+// plan before the first one starts: the cli params have to fit the declarations, and when nobody
+// can be asked the params of the script need a value
+pub fn check_params(
+    script: &ScriptModel,
+    packages_manager: &PackagesManager,
+    params: &HashMap<String, String>,
+    package: Option<&str>,
 ) -> Result<()> {
-    let mut step_statuses = Vec::new();
-    let script_params = script.params.clone();
+    validate_params(script, params.clone())?;
+    if !packages_manager.settings.config.non_interactive {
+        return Ok(());
+    }
+
+    // secrets are in the vault, they're checked when the step asks for them
+    let saved = packages_manager.settings.saved_params(package);
+    let missing: Vec<&str> = script
+        .params
+        .iter()
+        .flatten()
+        .filter(|param| !param.secret && !params.contains_key(&param.name))
+        .filter(|param| {
+            saved
+                .get(&param.name)
+                .is_none_or(|value| param.validate(value).is_err())
+        })
+        .filter(|param| step_runner::default_value(param).is_err())
+        .map(|param| param.name.as_str())
+        .collect();
+    if !missing.is_empty() {
+        let message = format!(
+            "Missing parameters: {} (non_interactive is on, pass them with --param)",
+            missing.join(", ")
+        );
+        error!("{}", message);
+        return Err(Error::msg(message));
+    }
+    Ok(())
+}
+
+// the steps and then the teardown steps, with a report for every step and whether the run failed
+fn run_steps(
+    script: &ScriptModel,
+    env: OS,
+    packages_manager: &mut PackagesManager,
+    params: &HashMap<String, String>,
+    ctx: &mut RunContext,
+) -> (Vec<StepReport>, bool) {
+    let fail_fast = script.fail_fast.unwrap_or(true);
+    let mut step_reports: Vec<StepReport> = Vec::new();
+    let mut failed = false;
     for step in &script.steps {
-        let success = step_runner::handle_step(
+        if failed && fail_fast {
+            print_md_debug(&format!(
+                "## {}\n⏭️ skipped, a previous step failed",
+                &step.title
+            ));
+            step_reports.extend(reports_for(step, StepStatus::Skipped));
+            continue;
+        }
+
+        let reports = run_step(
             step,
             env,
-            script_params.clone(),
+            script,
             packages_manager,
-            params.clone(),
+            params,
+            ctx,
+            &step_reports,
         );
-        // check if step runner is executable shell/cmd/python add it
-        step_statuses.push((step.title.clone(), success));
+        failed |= reports.iter().any(|report| report.is_failure());
+        step_reports.extend(reports);
+    }
+
+    // teardown - on_failure steps only when the main steps failed, always steps in any case.
+    // all teardown steps run, even if one of them fails
+    let mut teardown: Vec<&StepModel> = vec![];
+    if failed {
+        teardown.extend(script.on_failure.iter().flatten());
+    }
+    teardown.extend(script.always.iter().flatten());
+
+    for step in teardown {
+        let reports = run_step(
+            step,
+            env,
+            script,
+            packages_manager,
+            params,
+            ctx,
+            &step_reports,
+        );
+        failed |= reports.iter().any(|report| report.is_failure());
+        step_reports.extend(reports);
+    }
+
+    (step_reports, failed)
+}
+
+pub fn run_script(
+    automation_name: &String,
+    script: &ScriptModel,
+    env: OS,
+    packages_manager: &mut PackagesManager,
+    params: HashMap<String, String>,
+    package: Option<String>,
+    package_dir: Option<PathBuf>,
+) -> Result<()> {
+    let params = validate_params(script, params)?;
+
+    // conditions see saved params too, cli params take priority
+    let mut condition_params = packages_manager.settings.saved_params(package.as_deref());
+    condition_params.extend(params.clone());
+
+    if !check_condition(&script.when, env, &condition_params, &[])? {
+        print_md_debug(&format!(
+            "## ⏭️ {} skipped (`when: {}`)",
+            automation_name,
+            script.when.as_ref().unwrap()
+        ));
+        return Ok(());
     }
 
+    let mut ctx = RunContext::new()?;
+    ctx.package = package;
+    ctx.package_dir = package_dir;
+    let (step_reports, failed) = run_steps(script, env, packages_manager, &params, &mut ctx);
+
     print_md_debug(&format!(
         "\n\n\n## 🚀🚀🚀 {} completed 🚀🚀🚀",
         automation_name
     ));
-    for (title, success) in &step_statuses {
-        let status_str = if *success { "✅" } else { "❌" };
-        print_md_debug(&format!("{} {}", status_str, title));
+    for report in &step_reports {
+        print_md_debug(&report.summary());
     }
 
-    // if any status is false return false
-    let any_failed = step_statuses.iter().any(|(_, success)| !success);
-    if any_failed {
+    if failed {
         return Err(Error::msg("Some steps failed"));
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_output_lines() {
+        let content = "version=1.2.3\n# comment\n\n token = a=b \nnot a pair\n=empty\n";
+        assert_eq!(
+            parse_output_lines(content),
+            vec![
+                ("version".to_string(), "1.2.3".to_string()),
+                ("token".to_string(), "a=b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_params() {
+        let script: ScriptModel = serde_yaml::from_str(
+            r#"
+            params:
+              - name: debug
+                type: bool
+            steps:
+              - title: deploy
+                runner: shell
+                content: echo {{ region }}
+                params:
+                  - name: region
+                    type: enum
+                    choices: [eu, us]
+            "#,
+        )
+        .unwrap();
+
+        let params = validate_params(
+            &script,
+            HashMap::from([
+                ("debug".to_string(), "yes".to_string()),
+                ("region".to_string(), "eu".to_string()),
+                ("other".to_string(), "x".to_string()),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(params["debug"], "true");
+        assert_eq!(params["region"], "eu");
+        assert_eq!(params["other"], "x");
+
+        let res = validate_params(
+            &script,
+            HashMap::from([("region".to_string(), "asia".to_string())]),
+        );
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_register() {
+        let mut ctx = RunContext::new().unwrap();
+        let execution = StepExecution::new(
+            AttemptOutcome::Failed,
+            ShellOutput {
+                exit_code: Some(2),
+                stdout: Some(" v18.2.0\n".to_string()),
+            },
+        );
+        ctx.register("node_version", &execution);
+        assert_eq!(ctx.outputs["node_version"], "v18.2.0");
+        assert_eq!(ctx.outputs["node_version.stdout"], "v18.2.0");
+        assert_eq!(ctx.outputs["node_version.exit_code"], "2");
+
+        fs::write(ctx.output_file(), "token=abc\n").unwrap();
+        ctx.collect_output_file();
+        assert_eq!(ctx.outputs["token"], "abc");
+    }
+
+    // the reports and the order the steps of `yaml` ran in (they append to LOG), and the result of
+    // running it as a script
+    #[cfg(target_os = "linux")]
+    fn run_logged(yaml: &str) -> (Vec<(String, StepStatus)>, Vec<String>, Result<()>) {
+        let root = TempDir::new("cocmd").unwrap();
+        let log = root.path().join("log");
+        let script: ScriptModel =
+            serde_yaml::from_str(&yaml.replace("LOG", log.to_str().unwrap())).unwrap();
+        let mut packages_manager = PackagesManager::new(crate::Settings::new(
+            root.path().join("home").to_str(),
+            None,
+        ));
+
+        let mut ctx = RunContext::new().unwrap();
+        let (reports, _) = run_steps(
+            &script,
+            OS::Linux,
+            &mut packages_manager,
+            &HashMap::new(),
+            &mut ctx,
+        );
+        let statuses = reports.into_iter().map(|r| (r.title, r.status)).collect();
+        let lines = fs::read_to_string(&log)
+            .unwrap_or_default()
+            .lines()
+            .map(String::from)
+            .collect();
+
+        let result = run_script(
+            &"demo".to_string(),
+            &script,
+            OS::Linux,
+            &mut packages_manager,
+            HashMap::new(),
+            None,
+            None,
+        );
+        (statuses, lines, result)
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_failing_script() {
+        let (statuses, log, result) = run_logged(
+            r#"
+            steps:
+              - title: build
+                content: echo build >> LOG
+              - title: lint
+                content: echo lint >> LOG; exit 1
+                continue_on_error: true
+              - title: test
+                content: echo test >> LOG; exit 1
+              - title: deploy
+                content: echo deploy >> LOG
+            on_failure:
+              - title: rollback
+                content: echo rollback >> LOG; exit 1
+              - title: notify
+                content: echo notify >> LOG
+            always:
+              - title: cleanup
+                content: echo cleanup >> LOG
+            "#,
+        );
+        assert_eq!(
+            statuses,
+            vec![
+                ("build".to_string(), StepStatus::Success),
+                ("lint".to_string(), StepStatus::Failed),
+                ("test".to_string(), StepStatus::Failed),
+                ("deploy".to_string(), StepStatus::Skipped),
+                ("rollback".to_string(), StepStatus::Failed),
+                ("notify".to_string(), StepStatus::Success),
+                ("cleanup".to_string(), StepStatus::Success),
+            ]
+        );
+        // a failing teardown step doesn't stop the others
+        assert_eq!(
+            log,
+            vec!["build", "lint", "test", "rollback", "notify", "cleanup"]
+        );
+        assert!(result.is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_allowed_failures() {
+        let (statuses, log, result) = run_logged(
+            r#"
+            steps:
+              - title: lint
+                content: echo lint >> LOG; exit 1
+                continue_on_error: true
+              - title: test
+                content: echo test >> LOG
+            on_failure:
+              - title: rollback
+                content: echo rollback >> LOG
+            always:
+              - title: cleanup
+                content: echo cleanup >> LOG
+            "#,
+        );
+        assert_eq!(
+            statuses,
+            vec![
+                ("lint".to_string(), StepStatus::Failed),
+                ("test".to_string(), StepStatus::Success),
+                ("cleanup".to_string(), StepStatus::Success),
+            ]
+        );
+        assert_eq!(log, vec!["lint", "test", "cleanup"]);
+        assert!(result.is_ok());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_without_fail_fast() {
+        let (statuses, _, result) = run_logged(
+            r#"
+            fail_fast: false
+            steps:
+              - title: test
+                content: exit 1
+              - title: docs
+                content: "true"
+            "#,
+        );
+        assert_eq!(
+            statuses,
+            vec![
+                ("test".to_string(), StepStatus::Failed),
+                ("docs".to_string(), StepStatus::Success),
+            ]
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread;
+
+use log::error;
+
+use super::step_runner::{execute_step, prepare_step};
+use super::{check_condition, condition_params, RunContext, StepReport, StepStatus};
+use crate::core::models::script_model::{ScriptModel, StepModel};
+use crate::core::packages_manager::PackagesManager;
+use crate::core::utils::sys::OS;
+use crate::output::print_md_debug;
+
+// run the steps of a `parallel` block, at most `max_parallel` at a time.
+// conditions, approvals and param prompts are handled one step after the other before
+// anything starts, so only the commands themselves run at the same time
+pub fn run_parallel(
+    block: &StepModel,
+    env: OS,
+    script: &ScriptModel,
+    packages_manager: &mut PackagesManager,
+    params: &HashMap<String, String>,
+    ctx: &mut RunContext,
+    step_reports: &[StepReport],
+) -> Vec<StepReport> {
+    let steps = block.parallel_steps();
+    let condition_params = condition_params(packages_manager, params, ctx);
+    print_md_debug(&format!(
+        "## {} ({} steps in parallel)",
+        &block.title,
+        steps.len()
+    ));
+
+    // a report for every step (or iteration) in the order of the steps, the ones that run
+    // are filled in when they are done
+    let mut reports: Vec<StepReport> = vec![];
+    let mut jobs = vec![];
+    for step in &steps {
+        let report = |title: &str, status: StepStatus| {
+            let mut report = StepReport::new(title).finish(status);
+            report.allow_failure = step.continue_on_error.unwrap_or(false);
+            report
+        };
+
+        if step.parallel.is_some() {
+            error!("nested parallel blocks are not supported ({})", &step.title);
+            reports.push(report(&step.title, StepStatus::Failed));
+            continue;
+        }
+
+        match check_condition(&step.when, env, &condition_params, step_reports) {
+            Ok(true) => {}
+            Ok(false) => {
+                print_md_debug(&format!(
+                    "## {}\n⏭️ skipped (`when: {}`)",
+                    &step.title,
+                    step.when.as_ref().unwrap()
+                ));
+                reports.push(report(&step.title, StepStatus::Skipped));
+                continue;
+            }
+            Err(_) => {
+                reports.push(report(&step.title, StepStatus::Failed));
+                continue;
+            }
+        }
+
+        let prepared = match prepare_step(step, script, packages_manager, params.clone(), ctx) {
+            Ok(prepared) => prepared,
+            Err(status) => {
+                reports.push(report(&step.title, status));
+                continue;
+            }
+        };
+        // every run gets its own output file, the outputs are merged when all are done
+        for mut prepared in prepared {
+            let step_ctx = match RunContext::new() {
+                Ok(step_ctx) => step_ctx,
+                Err(err) => {
+                    error!("{}", err);
+                    reports.push(report(&prepared.title, StepStatus::Failed));
+                    continue;
+                }
+            };
+            prepared.options.output_prefix = Some(format!("[{}] ", &prepared.title));
+            reports.push(report(&prepared.title, StepStatus::Skipped));
+            jobs.push((reports.len() - 1, prepared, step_ctx));
+        }
+    }
+
+    let limit = block.max_parallel.unwrap_or(jobs.len()).max(1);
+    let queue = Mutex::new(jobs.into_iter());
+    let results = Mutex::new(vec![]);
+    let packages_manager: &PackagesManager = packages_manager;
+    thread::scope(|scope| {
+        for _ in 0..limit {
+            scope.spawn(|| loop {
+                let job = queue.lock().unwrap().next();
+                let Some((index, prepared, mut step_ctx)) = job else {
+                    break;
+                };
+                let report = execute_step(&prepared, env, packages_manager, &mut step_ctx);
+                results.lock().unwrap().push((index, report, step_ctx));
+            });
+        }
+    });
+
+    // merge in the order of the steps, so a later step wins when two set the same output
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _, _)| *index);
+    for (index, report, step_ctx) in results {
+        ctx.outputs.extend(step_ctx.outputs);
+        reports[index].status = report.status;
+        reports[index].attempts = report.attempts;
+    }
+
+    reports
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use temp_testdir::TempDir;
+
+    use super::*;
+    use crate::Settings;
+
+    #[test]
+    fn test_run_parallel() {
+        let tmp_home_dir = TempDir::default();
+        let mut packages_manager = PackagesManager::new(Settings::new(tmp_home_dir.to_str(), None));
+        let script: ScriptModel = serde_yaml::from_str(
+            r#"
+            steps:
+              - title: toolchains
+                max_parallel: 2
+                parallel:
+                  - title: rust
+                    content: sleep 0.2; echo 1.75
+                    register: rust_version
+                  - title: node
+                    content: exit 1
+                    continue_on_error: true
+                  - title: docker
+                    content: echo docker
+                    when: os == 'windows'
+                  - title: python
+                    content: echo "python=3.12" >> $COCMD_OUTPUT
+                  - title: lint
+                    content: echo {{ item }}
+                    for_each: [api, web]
+            "#,
+        )
+        .unwrap();
+
+        let mut ctx = RunContext::new().unwrap();
+        let reports = run_parallel(
+            &script.steps[0],
+            OS::Linux,
+            &script,
+            &mut packages_manager,
+            &HashMap::new(),
+            &mut ctx,
+            &[],
+        );
+
+        let statuses: Vec<(&str, StepStatus, bool)> = reports
+            .iter()
+            .map(|r| (r.title.as_str(), r.status, r.allow_failure))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("rust", StepStatus::Success, false),
+                ("node", StepStatus::Failed, true),
+                ("docker", StepStatus::Skipped, false),
+                ("python", StepStatus::Success, false),
+                ("lint (api)", StepStatus::Success, false),
+                ("lint (web)", StepStatus::Success, false),
+            ]
+        );
+        assert_eq!(ctx.outputs["rust_version"], "1.75");
+        assert_eq!(ctx.outputs["python"], "3.12");
+    }
+}
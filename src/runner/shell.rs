@@ -1,31 +1,85 @@
 #![allow(unused_imports)]
+use std::collections::HashMap;
 use std::env;
-use std::process::Command;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 use anyhow::Result;
+use thiserror::Error;
 
+use super::utils::wait_with_timeout;
 use crate::core::packages_manager::PackagesManager;
 use crate::core::utils::packages::get_all_paths;
 use crate::core::utils::sys::OS;
 use crate::output::print_md_debug;
 
+#[derive(Debug, Default, Clone)]
+pub struct ShellOptions {
+    // kill the command if it runs longer than this
+    pub timeout: Option<Duration>,
+    // extra environment variables for the command
+    pub env: HashMap<String, String>,
+    // working directory for the command
+    pub cwd: Option<PathBuf>,
+    // keep a copy of stdout (it's still printed to the terminal)
+    pub capture_stdout: bool,
+    // print stdout and stderr line by line, each line starting with this prefix
+    pub output_prefix: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ShellOutput {
+    pub exit_code: Option<i32>,
+    // only set when ShellOptions::capture_stdout is on
+    pub stdout: Option<String>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ShellError {
+    #[error("Failed to start shell command: {0}")]
+    Start(String),
+    #[error("Shell command failed.")]
+    Failed(ShellOutput),
+    #[error("Shell command timed out after {}s.", .0.as_secs())]
+    TimedOut(Duration),
+}
+
 pub fn interactive_shell(
     packages_manager: &mut PackagesManager,
     command: String,
 ) -> Result<bool, String> {
+    interactive_shell_with_options(packages_manager, command, &ShellOptions::default())
+        .map(|_| true)
+        .map_err(|e| e.to_string())
+}
+
+pub fn interactive_shell_with_options(
+    packages_manager: &PackagesManager,
+    command: String,
+    options: &ShellOptions,
+) -> Result<ShellOutput, ShellError> {
     let paths_to_add = get_all_paths(packages_manager);
 
-    // find appropriate rc file to source based on env::var("SHELL")
+    // the shell of the user config, or else env::var("SHELL")
+    let binding = packages_manager
+        .settings
+        .config
+        .shell
+        .clone()
+        .or_else(|| env::var("SHELL").ok())
+        .unwrap_or("/bin/sh".to_string());
+
+    // find appropriate rc file to source based on the shell
     let rc_file = match &packages_manager.settings.os {
         OS::Windows => "".to_string(),
-        _ => {
-            let binding = env::var("SHELL").unwrap_or("/bin/sh".to_string());
-            match binding.as_str() {
-                "/bin/bash" => "~/.bashrc".to_string(),
-                "/bin/zsh" => "~/.zshrc".to_string(),
-                _ => "".to_string(),
-            }
-        }
+        _ => match binding.rsplit('/').next().unwrap_or_default() {
+            "bash" => "~/.bashrc".to_string(),
+            "zsh" => "~/.zshrc".to_string(),
+            _ => "".to_string(),
+        },
     };
 
     // create cmd with command, in the beginning source rc file with set +e and -e wrapping
@@ -41,27 +95,125 @@ pub fn interactive_shell(
     }
 
     // Detect the OS based on your package_manager.settings.os
-    let binding = env::var("SHELL").unwrap_or("/bin/sh".to_string());
     let (shell, cmd_arg) = match &packages_manager.settings.os {
         OS::Windows => ("cmd.exe", "/C"),
         _ => (&*binding, "-c"),
     };
 
-    let status = Command::new(shell)
+    let mut command = Command::new(shell);
+    command
         .arg(cmd_arg)
         .arg(cmd)
-        .env("PATH", new_path)
-        .status()
-        .map_err(|e| format!("Failed to start shell command: {}", e))?;
-
-    if status.success() {
-        Ok(true)
-    } else {
-        print_md_debug("## ❌ Failed (stderr):\n");
-        Err("Shell command failed.".to_string())
+        .envs(&options.env)
+        .env("PATH", new_path);
+    if let Some(cwd) = &options.cwd {
+        command.current_dir(cwd);
+    }
+    if options.capture_stdout || options.output_prefix.is_some() {
+        command.stdout(Stdio::piped());
+    }
+    if options.output_prefix.is_some() {
+        command.stderr(Stdio::piped());
+    }
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| ShellError::Start(e.to_string()))?;
+
+    let prefix = &options.output_prefix;
+    let stdout_reader = child
+        .stdout
+        .take()
+        .map(|pipe| forward_output(pipe, prefix.clone(), false));
+    let stderr_reader = child
+        .stderr
+        .take()
+        .map(|pipe| forward_output(pipe, prefix.clone(), true));
+
+    let status = wait_with_timeout(&mut child, options.timeout)
+        .map_err(|e| ShellError::Start(e.to_string()))?;
+
+    match status {
+        Some(status) => {
+            let stdout = stdout_reader.map(|reader| reader.join().unwrap_or_default());
+            if let Some(reader) = stderr_reader {
+                let _ = reader.join();
+            }
+            let output = ShellOutput {
+                exit_code: status.code(),
+                stdout: stdout.filter(|_| options.capture_stdout),
+            };
+            if status.success() {
+                Ok(output)
+            } else {
+                print_md_debug("## ❌ Failed (stderr):\n");
+                Err(ShellError::Failed(output))
+            }
+        }
+        None => {
+            let timeout = options.timeout.unwrap_or_default();
+            print_md_debug(&format!("## ⏱️ Timed out after {}s\n", timeout.as_secs()));
+            Err(ShellError::TimedOut(timeout))
+        }
     }
 }
 
+// print the output of a command as it comes and keep a copy.
+// with a prefix, the output is printed in whole lines so commands running at the same time
+// don't mix up each other's lines
+fn forward_output<R: Read + Send + 'static>(
+    pipe: R,
+    prefix: Option<String>,
+    to_stderr: bool,
+) -> JoinHandle<String> {
+    thread::spawn(move || {
+        let write = |bytes: &[u8]| {
+            if to_stderr {
+                let mut stderr = std::io::stderr().lock();
+                let _ = stderr.write_all(bytes);
+                let _ = stderr.flush();
+            } else {
+                let mut stdout = std::io::stdout().lock();
+                let _ = stdout.write_all(bytes);
+                let _ = stdout.flush();
+            }
+        };
+
+        let mut captured = vec![];
+        match prefix {
+            Some(prefix) => {
+                let mut reader = BufReader::new(pipe);
+                let mut line = vec![];
+                while let Ok(n) = reader.read_until(b'\n', &mut line) {
+                    if n == 0 {
+                        break;
+                    }
+                    captured.extend_from_slice(&line);
+                    if !line.ends_with(b"\n") {
+                        line.push(b'\n');
+                    }
+                    let mut prefixed = prefix.as_bytes().to_vec();
+                    prefixed.extend_from_slice(&line);
+                    write(&prefixed);
+                    line.clear();
+                }
+            }
+            None => {
+                let mut pipe = pipe;
+                let mut buf = [0u8; 4096];
+                while let Ok(n) = pipe.read(&mut buf) {
+                    if n == 0 {
+                        break;
+                    }
+                    write(&buf[..n]);
+                    captured.extend_from_slice(&buf[..n]);
+                }
+            }
+        }
+        String::from_utf8_lossy(&captured).to_string()
+    })
+}
+
 #[cfg(target_os = "windows")]
 mod windows_tests {
     use super::*;
@@ -69,7 +221,8 @@ mod windows_tests {
 
     #[test]
     fn test_interactive_shell_windows() {
-        let settings = Settings::new(None, None);
+        let home = tempdir::TempDir::new("cocmd").unwrap();
+        let settings = Settings::new(home.path().to_str(), None);
         let mut packages_manager = PackagesManager::new(settings);
 
         // Call the interactive_shell function with test data for Windows
@@ -88,7 +241,9 @@ mod linux_tests {
 
     #[test]
     fn test_interactive_shell_linux() {
-        let settings = Settings::new(None, None);
+        // a home of its own, settings write the saved params there when dropped
+        let home = tempdir::TempDir::new("cocmd").unwrap();
+        let settings = Settings::new(home.path().to_str(), None);
         let mut packages_manager = PackagesManager::new(settings);
 
         // Call the interactive_shell function with test data for Linux
@@ -98,6 +253,78 @@ mod linux_tests {
         // Assert that the result is as expected
         assert_eq!(result, Ok(true));
     }
+
+    #[test]
+    fn test_interactive_shell_linux_timeout() {
+        let home = tempdir::TempDir::new("cocmd").unwrap();
+        let settings = Settings::new(home.path().to_str(), None);
+        let packages_manager = PackagesManager::new(settings);
+
+        let options = ShellOptions {
+            timeout: Some(Duration::from_millis(300)),
+            ..Default::default()
+        };
+        let result =
+            interactive_shell_with_options(&packages_manager, "sleep 5".to_string(), &options);
+
+        assert_eq!(
+            result,
+            Err(ShellError::TimedOut(Duration::from_millis(300)))
+        );
+    }
+
+    #[test]
+    fn test_interactive_shell_linux_capture() {
+        let home = tempdir::TempDir::new("cocmd").unwrap();
+        let settings = Settings::new(home.path().to_str(), None);
+        let packages_manager = PackagesManager::new(settings);
+
+        let options = ShellOptions {
+            env: HashMap::from([("GREETING".to_string(), "hi".to_string())]),
+            capture_stdout: true,
+            ..Default::default()
+        };
+        let result = interactive_shell_with_options(
+            &packages_manager,
+            "echo $GREETING; exit 3".to_string(),
+            &options,
+        );
+
+        assert_eq!(
+            result,
+            Err(ShellError::Failed(ShellOutput {
+                exit_code: Some(3),
+                stdout: Some("hi\n".to_string()),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_interactive_shell_linux_prefix() {
+        let home = tempdir::TempDir::new("cocmd").unwrap();
+        let settings = Settings::new(home.path().to_str(), None);
+        let packages_manager = PackagesManager::new(settings);
+
+        let options = ShellOptions {
+            capture_stdout: true,
+            output_prefix: Some("[node] ".to_string()),
+            ..Default::default()
+        };
+        let result = interactive_shell_with_options(
+            &packages_manager,
+            "echo one; echo oops >&2; printf two".to_string(),
+            &options,
+        );
+
+        // the prefix is only for the terminal, the captured output is untouched
+        assert_eq!(
+            result,
+            Ok(ShellOutput {
+                exit_code: Some(0),
+                stdout: Some("one\ntwo".to_string()),
+            })
+        );
+    }
 }
 
 #[cfg(target_os = "macos")]
@@ -106,7 +333,8 @@ mod macos_tests {
     use crate::core::{models::settings::Settings, packages_manager::PackagesManager};
     #[test]
     fn test_interactive_shell_macos() {
-        let settings = Settings::new(None, None);
+        let home = tempdir::TempDir::new("cocmd").unwrap();
+        let settings = Settings::new(home.path().to_str(), None);
         let mut packages_manager = PackagesManager::new(settings);
 
         // Call the interactive_shell function with test data for macOS
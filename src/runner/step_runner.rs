@@ -1,116 +1,380 @@
 use std::collections::HashMap;
-use std::process;
-use std::process::Command;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use std::{process, thread};
 
 use dialoguer::theme::ColorfulTheme;
-use dialoguer::Confirm;
+use dialoguer::{Confirm, Input, Password, Select};
 use log::error;
-use regex::Regex;
 
-use super::shell::interactive_shell;
-use crate::core::models::script_model::StepParamModel;
+use super::shell::{
+    interactive_shell, interactive_shell_with_options, ShellError, ShellOptions, ShellOutput,
+};
+use super::template::{self, TemplateContext};
+use super::{AttemptOutcome, RunContext, StepExecution, StepReport, OUTPUT_FILE_ENV};
+use crate::core::models::script_model::{ForEach, ParamType, StepParamModel};
+use crate::core::models::settings::Settings;
 use crate::core::utils::packages::get_package_name_from_uri;
 use crate::core::utils::sys::OS;
 use crate::core::{
-    models::script_model::{StepModel, StepRunnerType},
+    models::script_model::{ScriptModel, StepModel, StepRunnerType},
     packages_manager::PackagesManager,
 };
 use crate::output::{print_md, print_md_debug};
-use crate::runner::utils::check_installed;
+use crate::runner::utils::{check_installed, output_with_timeout};
+use crate::runner::StepStatus;
+
+const DEFAULT_RETRY_DELAY_SECONDS: u64 = 1;
+
+// a step (or one iteration of a `for_each` step) with its params, env and cwd resolved,
+// ready to run
+pub struct PreparedStep<'a> {
+    pub step: &'a StepModel,
+    pub title: String,
+    pub content: String,
+    pub options: ShellOptions,
+}
 
+// run a step, once for every item when it has `for_each`. returns a report for every run
 pub fn handle_step(
     step: &StepModel,
     env: OS,
-    script_params: Option<Vec<StepParamModel>>,
+    script: &ScriptModel,
+    packages_manager: &mut PackagesManager,
+    params: HashMap<String, String>,
+    ctx: &mut RunContext,
+) -> Vec<StepReport> {
+    let prepared = match prepare_step(step, script, packages_manager, params, ctx) {
+        Ok(prepared) => prepared,
+        Err(status) => return vec![StepReport::new(&step.title).finish(status)],
+    };
+
+    // like steps, the remaining iterations are skipped after a failure unless the step
+    // has `continue_on_error` or the script turned off `fail_fast`
+    let stop_on_failure =
+        script.fail_fast.unwrap_or(true) && !step.continue_on_error.unwrap_or(false);
+    let mut reports: Vec<StepReport> = vec![];
+    for prepared in &prepared {
+        if stop_on_failure && reports.iter().any(|r| r.status == StepStatus::Failed) {
+            print_md_debug(&format!(
+                "## {}\n⏭️ skipped, a previous iteration failed",
+                &prepared.title
+            ));
+            reports.push(StepReport::new(&prepared.title).finish(StepStatus::Skipped));
+            continue;
+        }
+        if step.for_each.is_some() {
+            print_md_debug(&format!("### {}", &prepared.title));
+        }
+        reports.push(execute_step(prepared, env, packages_manager, ctx));
+    }
+    reports
+}
+
+// everything that may ask the user something happens here, before the step runs.
+// fails with the status the step should get when it can't run
+pub fn prepare_step<'a>(
+    step: &'a StepModel,
+    script: &ScriptModel,
     packages_manager: &mut PackagesManager,
     params: HashMap<String, String>,
-) -> bool {
-    let mut content = step.content.as_ref().unwrap().as_str();
-    let script_params = step.get_params(script_params);
+    ctx: &RunContext,
+) -> Result<Vec<PreparedStep<'a>>, StepStatus> {
+    let content = step.content.as_ref().unwrap().as_str();
+    let script_params = step.get_params(script.params.clone());
 
     print_md_debug(&format!("## {}", &step.title));
     if let Some(msg) = step.approval_message.clone() {
-        if !Confirm::new().with_prompt(msg).interact().unwrap() {
-            return true;
+        if packages_manager.settings.config.non_interactive {
+            print_md_debug(&format!("{} - approved (non-interactive)", msg));
+        } else if !Confirm::new().with_prompt(msg).interact().unwrap() {
+            return Err(StepStatus::Skipped);
         }
     }
 
-    let binding = apply_params_to_content(
-        content.to_string(),
-        script_params.clone(),
+    let params_map = resolve_params(
+        script_params,
         packages_manager,
-        params.clone(),
+        params,
+        &ctx.outputs,
+        ctx.package.as_deref(),
     );
-    content = &binding;
+    let os = packages_manager.settings.os;
+    let strict = step.strict.or(script.strict).unwrap_or(false);
+    let render = |text: &str, vars: &HashMap<String, String>| {
+        let template_ctx = TemplateContext {
+            os,
+            vars,
+            package_dir: ctx.package_dir.as_deref(),
+            strict,
+        };
+        template::render(text, &template_ctx).map_err(|e| {
+            error!("{}: {:#}", &step.title, e);
+            StepStatus::Failed
+        })
+    };
 
-    match &step.runner {
-        StepRunnerType::SHELL => {
-            if let Err(_err) = interactive_shell(packages_manager, content.to_string()) {
-                return false;
+    let iterations = match &step.for_each {
+        None => vec![(step.title.clone(), params_map)],
+        Some(for_each) => {
+            let items = for_each_items(for_each, &params_map, |text| render(text, &params_map))?;
+            if items.is_empty() {
+                print_md_debug("⏭️ skipped, `for_each` has no items");
+                return Err(StepStatus::Skipped);
             }
+            items
+                .into_iter()
+                .enumerate()
+                .map(|(index, item)| {
+                    let mut params_map = params_map.clone();
+                    params_map.insert("item".to_string(), item.clone());
+                    params_map.insert("index".to_string(), index.to_string());
+                    (format!("{} ({})", &step.title, item), params_map)
+                })
+                .collect()
         }
-        StepRunnerType::COCMD => {
-            let provider_name = get_package_name_from_uri(content);
-
-            let available_automations = packages_manager.automations();
-            if !available_automations.contains_key(content) {
-                if !Confirm::new()
-                    .with_prompt(format!(
-                        "Cocmd Package {} not found. Download?",
-                        &provider_name
-                    ))
-                    .interact()
-                    .unwrap()
-                {
-                    return false;
-                }
+    };
 
-                // ask the user if he wants to download the package. get yes/no approval
-                // if yes, download the package
-                if let Err(_err) = interactive_shell(
-                    packages_manager,
-                    format!("cocmd --no-verbose install {}", &provider_name),
-                ) {
-                    return false;
-                }
-                packages_manager.reload();
+    let mut prepared = vec![];
+    for (title, params_map) in iterations {
+        let content = render(content, &params_map)?;
+
+        let mut step_env: HashMap<String, String> = HashMap::new();
+        for (key, value) in script.step_env_vars(step) {
+            step_env.insert(key, render(&value, &params_map)?);
+        }
+
+        let cwd = match script.step_cwd(step) {
+            Some(cwd) => Some(expand_home(&render(&cwd, &params_map)?)),
+            None => None,
+        };
+        if let Some(cwd) = &cwd {
+            if !cwd.is_dir() {
+                error!("working directory {} does not exist", cwd.display());
+                return Err(StepStatus::Failed);
             }
-            if let Err(_err) = interactive_shell(
-                packages_manager,
-                format!("cocmd --no-verbose run {}", &content),
-            ) {
-                return false;
+        }
+
+        if step.runner == StepRunnerType::COCMD && !ensure_cocmd_package(&content, packages_manager)
+        {
+            return Err(StepStatus::Failed);
+        }
+
+        prepared.push(PreparedStep {
+            step,
+            title,
+            content,
+            options: ShellOptions {
+                timeout: step.timeout.map(Duration::from_secs),
+                env: step_env,
+                cwd,
+                capture_stdout: step.register.is_some(),
+                output_prefix: None,
+            },
+        });
+    }
+    Ok(prepared)
+}
+
+// the items of a `for_each`, rendered with `render`. a param or output is split on commas and
+// new lines
+fn for_each_items(
+    for_each: &ForEach,
+    params_map: &HashMap<String, String>,
+    render: impl Fn(&str) -> Result<String, StepStatus>,
+) -> Result<Vec<String>, StepStatus> {
+    let value = match for_each {
+        ForEach::Items(items) => return items.iter().map(|item| render(item)).collect(),
+        ForEach::Source(source) if source.contains("{{") || source.contains("{%") => {
+            render(source)?
+        }
+        ForEach::Source(name) => params_map.get(name.trim()).cloned().ok_or_else(|| {
+            error!("for_each: unknown param or output '{}'", name);
+            StepStatus::Failed
+        })?,
+    };
+
+    Ok(value
+        .split([',', '\n'])
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+// run a prepared step, with its retries
+pub fn execute_step(
+    prepared: &PreparedStep,
+    env: OS,
+    packages_manager: &PackagesManager,
+    ctx: &mut RunContext,
+) -> StepReport {
+    let step = prepared.step;
+    let mut report = StepReport::new(&prepared.title);
+    let mut options = prepared.options.clone();
+    options.env.insert(
+        OUTPUT_FILE_ENV.to_string(),
+        ctx.output_file().to_string_lossy().to_string(),
+    );
+    let max_attempts = step.retries.unwrap_or(0) + 1;
+    let retry_delay = step.retry_delay.unwrap_or(DEFAULT_RETRY_DELAY_SECONDS);
+
+    for attempt in 1..=max_attempts {
+        ctx.reset_output_file();
+        let execution = run_step_once(step, &prepared.content, env, packages_manager, &options);
+        report.attempts.push(execution.outcome);
+
+        if execution.outcome == AttemptOutcome::Success || attempt == max_attempts {
+            ctx.collect_output_file();
+            if let Some(name) = &step.register {
+                ctx.register(name, &execution);
             }
         }
+
+        if execution.outcome == AttemptOutcome::Success {
+            return report.finish(StepStatus::Success);
+        }
+
+        if attempt < max_attempts {
+            // exponential backoff: delay, 2*delay, 4*delay...
+            let delay = retry_delay.saturating_mul(2u64.saturating_pow(attempt - 1));
+            print_md_debug(&format!(
+                "## 🔁 {}retrying in {}s (attempt {}/{})",
+                prepared
+                    .options
+                    .output_prefix
+                    .as_deref()
+                    .unwrap_or_default(),
+                delay,
+                attempt + 1,
+                max_attempts
+            ));
+            thread::sleep(Duration::from_secs(delay));
+        }
+    }
+
+    report.finish(StepStatus::Failed)
+}
+
+// make sure the package of a `runner: cocmd` step is installed. asks the user before installing
+fn ensure_cocmd_package(content: &str, packages_manager: &mut PackagesManager) -> bool {
+    let provider_name = get_package_name_from_uri(content);
+
+    let available_automations = packages_manager.automations();
+    if available_automations.contains_key(content) {
+        return true;
+    }
+
+    if !packages_manager.settings.config.non_interactive
+        && !Confirm::new()
+            .with_prompt(format!(
+                "Cocmd Package {} not found. Download?",
+                &provider_name
+            ))
+            .interact()
+            .unwrap()
+    {
+        return false;
+    }
+
+    // ask the user if he wants to download the package. get yes/no approval
+    // if yes, download the package
+    if let Err(_err) = interactive_shell(
+        packages_manager,
+        format!("cocmd --no-verbose install {}", &provider_name),
+    ) {
+        return false;
+    }
+    packages_manager.reload();
+    true
+}
+
+fn shell_execution(result: Result<ShellOutput, ShellError>) -> StepExecution {
+    match result {
+        Ok(output) => StepExecution::new(AttemptOutcome::Success, output),
+        Err(ShellError::Failed(output)) => StepExecution::new(AttemptOutcome::Failed, output),
+        Err(ShellError::TimedOut(_)) => {
+            StepExecution::new(AttemptOutcome::TimedOut, ShellOutput::default())
+        }
+        Err(_) => StepExecution::new(AttemptOutcome::Failed, ShellOutput::default()),
+    }
+}
+
+// links are opened in the background on purpose, we don't wait for the browser
+#[allow(clippy::zombie_processes)]
+fn run_step_once(
+    step: &StepModel,
+    content: &str,
+    env: OS,
+    packages_manager: &PackagesManager,
+    options: &ShellOptions,
+) -> StepExecution {
+    match &step.runner {
+        StepRunnerType::SHELL => shell_execution(interactive_shell_with_options(
+            packages_manager,
+            content.to_string(),
+            options,
+        )),
+        StepRunnerType::COCMD => shell_execution(interactive_shell_with_options(
+            packages_manager,
+            format!("cocmd --no-verbose run {}", &content),
+            options,
+        )),
         StepRunnerType::MARKDOWN => {
             // Print Markdown content
             print_md(content);
+            StepExecution::default()
         }
         StepRunnerType::PYTHON => {
             // make sure that "python" is installed and reachable from the command line
             if !check_installed("python") {
                 print_md_debug("## ❌ Python not installed\n");
-                return false;
+                return StepExecution::new(AttemptOutcome::Failed, ShellOutput::default());
             }
 
             // Execute Python script
-            let output = Command::new("python")
-                .arg("-c")
-                .arg(content)
-                .output()
-                .expect("Failed to execute Python script.");
+            let output =
+                output_with_timeout(&mut python_command(content, options), options.timeout)
+                    .expect("Failed to execute Python script.");
 
-            println!("stdout: \n{}", String::from_utf8_lossy(&output.stdout));
-            println!("stderr: \n{}", String::from_utf8_lossy(&output.stderr));
+            let output = match output {
+                Some(output) => output,
+                None => {
+                    print_md_debug(&format!(
+                        "## ⏱️ Timed out after {}s\n",
+                        options.timeout.unwrap_or_default().as_secs()
+                    ));
+                    return StepExecution::new(AttemptOutcome::TimedOut, ShellOutput::default());
+                }
+            };
 
-            let success = output.status.success();
+            let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            match &options.output_prefix {
+                Some(prefix) => {
+                    stdout
+                        .lines()
+                        .for_each(|line| println!("{}{}", prefix, line));
+                    stderr
+                        .lines()
+                        .for_each(|line| eprintln!("{}{}", prefix, line));
+                }
+                None => {
+                    println!("stdout: \n{}", stdout);
+                    println!("stderr: \n{}", stderr);
+                }
+            }
 
-            if success {
-                // print_md_debug(&"## ✅ Success".to_string());
-                return true;
+            let shell_output = ShellOutput {
+                exit_code: output.status.code(),
+                stdout: Some(stdout),
+            };
+            if output.status.success() {
+                StepExecution::new(AttemptOutcome::Success, shell_output)
             } else {
                 print_md_debug("## ❌ Failed\n");
-                return false;
+                StepExecution::new(AttemptOutcome::Failed, shell_output)
             }
         }
         StepRunnerType::LINK => {
@@ -140,78 +404,426 @@ pub fn handle_step(
                     error!("unable to open link in the default browser.")
                 }
             }
+            StepExecution::default()
         }
     }
-    true
 }
 
-fn apply_params_to_content(
-    cmd: String,
+// find a value for every param of the step, and add the outputs of previous steps
+fn resolve_params(
     step_params: Vec<StepParamModel>,
     packages_manager: &mut PackagesManager,
     params: HashMap<String, String>,
-) -> String {
-    let mut cmd = cmd;
+    outputs: &HashMap<String, String>,
+    package: Option<&str>,
+) -> HashMap<String, String> {
     let mut params_map: HashMap<String, String> = HashMap::new();
 
     for param in step_params.clone() {
         // look for param.name in params
         // if found, use that value
-        // if not found, look for it in settings
+        // if not found, look for it in outputs of previous steps
+        // if not found, look for it in settings (the package scope, then the global one)
         // if not found, ask the user for it
         let param_name = param.name.clone();
-        let param_value = params.get(param_name.as_str());
+        let param_value = params
+            .get(param_name.as_str())
+            .or_else(|| outputs.get(param_name.as_str()));
 
         let param_value = match param_value {
             Some(value) => value.clone(),
             None => {
-                let param_value = packages_manager.settings.get_param(&param_name);
-                match param_value {
+                // a saved value that doesn't fit the declaration (anymore) is asked again
+                let saved_value = if param.secret {
+                    saved_secret(&param, &mut packages_manager.settings, package)
+                } else {
+                    packages_manager.settings.get_param(&param_name, package)
+                }
+                .and_then(|value| param.validate(&value).ok());
+                match saved_value {
                     Some(value) => value,
-                    None => {
-                        let prompt = format!("Enter value for parameter '{}'", param_name);
-                        let param_value = dialoguer::Input::with_theme(&ColorfulTheme::default())
-                            .with_prompt(&prompt)
-                            .interact_text()
-                            .unwrap_or_else(|_e| {
-                                error!("No value entered for parameter '{}'.", param_name);
-                                process::exit(1)
-                            });
-                        param_value
+                    None if packages_manager.settings.config.non_interactive => {
+                        default_param(&param)
                     }
+                    None => prompt_param(&param),
                 }
             }
         };
 
         params_map.insert(param.name.clone(), param_value.clone());
 
-        if param.save {
+        if param.save && param.secret {
+            if let Err(e) = packages_manager
+                .settings
+                .vault
+                .set(&param_name, &param_value, package)
+            {
+                error!("unable to save '{}': {}", param_name, e);
+            }
+        } else if param.save {
             packages_manager
                 .settings
-                .save_param(&param_name, &param_value);
+                .save_param(&param_name, &param_value, package);
         }
     }
 
-    // like in jinja2 parameters templating (but without using any jinja2 lib)
-    // replace in cmd ocorunces of {{\s*...\s*}} with the values from params_map. ignore spaces inside the brackets
-    // use regular expression to find all matches and ignore the spaces inside the brackets
-    // for each match, replace it with the value from params_map
+    // outputs of previous steps (`register` and $COCMD_OUTPUT) don't have to be declared
+    for (name, value) in outputs {
+        params_map
+            .entry(name.clone())
+            .or_insert_with(|| value.clone());
+    }
 
-    // Compile regex to match {{ param }}
-    let re = Regex::new(r"\{\s*\{\s*([\w.]+)\s*\}\s*\}").unwrap();
+    params_map
+}
 
-    // Find all parameter matches
-    for cap in re.captures_iter(cmd.clone().as_ref()) {
-        // Extract the parameter name
-        let param_name = &cap[1];
+// the value of a secret param from its `secret_command`, or else from the vault
+fn saved_secret(
+    param: &StepParamModel,
+    settings: &mut Settings,
+    package: Option<&str>,
+) -> Option<String> {
+    if let Some(command) = &param.secret_command {
+        match run_secret_command(command, &settings.terminal) {
+            Ok(value) => return Some(value),
+            Err(e) => error!("secret_command of '{}': {}", param.name, e),
+        }
+    }
+    match settings.vault.get(&param.name, package) {
+        Ok(Some(value)) => return Some(value),
+        Ok(None) => {}
+        Err(e) => error!("{}", e),
+    }
+    // saved in plain text before the param became secret. it's only moved to the vault when the
+    // param is saved anyway, an unsaved one doesn't ask for a vault passphrase mid run
+    let value = settings.get_param(&param.name, package)?;
+    if !param.save {
+        return Some(value);
+    }
+    match settings.vault.set(&param.name, &value, package) {
+        Ok(()) => {
+            settings
+                .unset_param(&param.name, package)
+                .or_else(|| settings.unset_param(&param.name, None));
+            print_md(&format!(
+                "🔐 '{}' is a secret param, its saved value was moved to the vault",
+                param.name
+            ));
+        }
+        Err(e) => error!("unable to save '{}': {}", param.name, e),
+    }
+    Some(value)
+}
+
+fn run_secret_command(command: &str, terminal: &str) -> anyhow::Result<String> {
+    let output = Command::new(terminal)
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!("`{}` failed with {}", command, output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
 
-        // Get the parameter value from params_map
-        if let Some(param_value) = params_map.get(param_name) {
-            // Replace match with param value
-            cmd = re.replace(&cmd, param_value).to_string();
-        } else {
-            // Param not found error
+fn python_command(content: &str, options: &ShellOptions) -> Command {
+    let mut command = Command::new("python");
+    command.arg("-c").arg(content).envs(&options.env);
+    if let Some(cwd) = &options.cwd {
+        command.current_dir(cwd);
+    }
+    command
+}
+
+// `~/x` -> `$HOME/x`, like the shell would do
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            home.join(rest.trim_start_matches('/'))
         }
+        _ => PathBuf::from(path),
+    }
+}
+
+fn exit_no_value<T>(param_name: &str) -> T {
+    error!("No value entered for parameter '{}'.", param_name);
+    process::exit(1)
+}
+
+// what a prompt would start with, when nobody can be asked
+pub(super) fn default_value(param: &StepParamModel) -> Result<String, String> {
+    let value = match (&param.default, &param.param_type) {
+        (Some(default), _) => default.clone(),
+        (None, ParamType::BOOL) => "false".to_string(),
+        (None, _) => String::new(),
+    };
+    param.validate(&value)
+}
+
+fn default_param(param: &StepParamModel) -> String {
+    default_value(param).unwrap_or_else(|e| {
+        error!("{} (non_interactive is on, pass it with --param)", e);
+        exit_no_value(&param.name)
+    })
+}
+
+// ask the user for a param value, with a widget that fits the param type
+fn prompt_param(param: &StepParamModel) -> String {
+    let theme = ColorfulTheme::default();
+    let prompt = match &param.description {
+        Some(description) => format!("{} ({})", description, param.name),
+        None => format!("Enter value for parameter '{}'", param.name),
+    };
+
+    match (&param.param_type, &param.choices) {
+        (ParamType::BOOL, _) => {
+            let default = param
+                .default
+                .as_ref()
+                .and_then(|d| param.validate(d).ok())
+                .is_some_and(|d| d == "true");
+            Confirm::with_theme(&theme)
+                .with_prompt(&prompt)
+                .default(default)
+                .interact()
+                .unwrap_or_else(|_e| exit_no_value(&param.name))
+                .to_string()
+        }
+        (_, Some(choices)) if !choices.is_empty() => {
+            let default = param
+                .default
+                .as_ref()
+                .and_then(|d| choices.iter().position(|c| c == d))
+                .unwrap_or(0);
+            let selected = Select::with_theme(&theme)
+                .with_prompt(&prompt)
+                .items(choices)
+                .default(default)
+                .interact()
+                .unwrap_or_else(|_e| exit_no_value(&param.name));
+            choices[selected].clone()
+        }
+        _ if param.secret => {
+            let value = Password::with_theme(&theme)
+                .with_prompt(&prompt)
+                .allow_empty_password(!param.is_required())
+                .validate_with(|value: &String| param.validate(value).map(|_| ()))
+                .interact()
+                .unwrap_or_else(|_e| exit_no_value(&param.name));
+            param.validate(&value).unwrap_or(value)
+        }
+        _ => {
+            let mut input = Input::<String>::with_theme(&theme)
+                .with_prompt(&prompt)
+                .allow_empty(!param.is_required())
+                .validate_with(|value: &String| param.validate(value).map(|_| ()));
+            if let Some(default) = &param.default {
+                input = input.default(default.clone());
+            }
+            let value = input
+                .interact_text()
+                .unwrap_or_else(|_e| exit_no_value(&param.name));
+            // validated above, only normalization is left
+            param.validate(&value).unwrap_or(value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use temp_testdir::TempDir;
+
+    use super::*;
+    use crate::core::consts;
+
+    #[test]
+    fn test_resolve_params() {
+        let tmp_home_dir = TempDir::default();
+        let mut packages_manager = PackagesManager::new(Settings::new(tmp_home_dir.to_str(), None));
+
+        let step_params = vec![StepParamModel {
+            name: "name".to_string(),
+            ..Default::default()
+        }];
+        let params = HashMap::from([("name".to_string(), "$world".to_string())]);
+        let outputs = HashMap::from([
+            ("version".to_string(), "1.2.3".to_string()),
+            ("name".to_string(), "ignored".to_string()),
+        ]);
+
+        let params_map = resolve_params(
+            step_params,
+            &mut packages_manager,
+            params,
+            &outputs,
+            Some("demo"),
+        );
+        assert_eq!(
+            params_map,
+            HashMap::from([
+                ("name".to_string(), "$world".to_string()),
+                ("version".to_string(), "1.2.3".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_resolve_scoped_params() {
+        let tmp_home_dir = tempdir::TempDir::new("cocmd").unwrap();
+        let mut packages_manager =
+            PackagesManager::new(Settings::new(tmp_home_dir.path().to_str(), None));
+        let settings = &mut packages_manager.settings;
+        settings.save_param("region", "eu", None);
+        settings.save_param("region", "us", Some("demo"));
+
+        let step_params = vec![StepParamModel {
+            name: "region".to_string(),
+            save: true,
+            ..Default::default()
+        }];
+        let resolve = |packages_manager: &mut PackagesManager, package| {
+            resolve_params(
+                step_params.clone(),
+                packages_manager,
+                HashMap::new(),
+                &HashMap::new(),
+                package,
+            )["region"]
+                .clone()
+        };
+        assert_eq!(resolve(&mut packages_manager, Some("demo")), "us");
+        assert_eq!(resolve(&mut packages_manager, Some("other")), "eu");
+
+        // saved in the package scope, the global value stays
+        let cli = HashMap::from([("region".to_string(), "ap".to_string())]);
+        resolve_params(
+            step_params.clone(),
+            &mut packages_manager,
+            cli,
+            &HashMap::new(),
+            Some("other"),
+        );
+        let settings = &packages_manager.settings;
+        assert_eq!(
+            settings.get_param("region", Some("other")),
+            Some("ap".into())
+        );
+        assert_eq!(
+            settings.get_param("region", Some("demo")),
+            Some("us".into())
+        );
+        assert_eq!(settings.get_param("region", None), Some("eu".into()));
+    }
+
+    #[test]
+    fn test_resolve_secret_params() {
+        let tmp_home_dir = tempdir::TempDir::new("cocmd").unwrap();
+        let home = tmp_home_dir.path();
+        std::fs::write(home.join(consts::VAULT_KEY_FILE), "key").unwrap();
+        let mut packages_manager = PackagesManager::new(Settings::new(home.to_str(), None));
+        packages_manager
+            .settings
+            .save_param("legacy", "from-plain-file", None);
+        packages_manager
+            .settings
+            .save_param("unsaved", "stays-plain", Some("demo"));
+
+        let secret = |name: &str| StepParamModel {
+            name: name.to_string(),
+            save: true,
+            secret: true,
+            ..Default::default()
+        };
+        let step_params = vec![
+            secret("token"),
+            secret("legacy"),
+            StepParamModel {
+                secret_command: Some("echo ' from-command '".to_string()),
+                ..secret("command")
+            },
+            StepParamModel {
+                save: false,
+                ..secret("unsaved")
+            },
+        ];
+        let params = HashMap::from([("token".to_string(), "s3cr3t".to_string())]);
+
+        let params_map = resolve_params(
+            step_params,
+            &mut packages_manager,
+            params,
+            &HashMap::new(),
+            Some("demo"),
+        );
+        assert_eq!(params_map["token"], "s3cr3t");
+        assert_eq!(params_map["legacy"], "from-plain-file");
+        assert_eq!(params_map["command"], "from-command");
+        assert_eq!(params_map["unsaved"], "stays-plain");
+
+        // only the saved legacy value moved to the vault
+        let settings = &mut packages_manager.settings;
+        assert!(settings.params.is_empty());
+        assert_eq!(
+            settings.saved_params(Some("demo")),
+            HashMap::from([("unsaved".to_string(), "stays-plain".to_string())])
+        );
+        let vault = &mut settings.vault;
+        assert_eq!(vault.get("token", None).unwrap(), None);
+        assert_eq!(
+            vault.get("token", Some("demo")).unwrap(),
+            Some("s3cr3t".into())
+        );
+        assert_eq!(
+            vault.get("legacy", Some("demo")).unwrap(),
+            Some("from-plain-file".into())
+        );
+        assert_eq!(vault.get("unsaved", Some("demo")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_for_each_items() {
+        let params_map = HashMap::from([
+            ("repos".to_string(), "api, web,,docs".to_string()),
+            ("services".to_string(), "auth\nbilling\n".to_string()),
+            ("env".to_string(), "prod".to_string()),
+        ]);
+        let render = |text: &str| {
+            let ctx = TemplateContext {
+                os: OS::Linux,
+                vars: &params_map,
+                package_dir: None,
+                strict: false,
+            };
+            template::render(text, &ctx).map_err(|_| StepStatus::Failed)
+        };
+        let items = |for_each: ForEach| for_each_items(&for_each, &params_map, render);
+
+        assert_eq!(
+            items(ForEach::Items(vec!["{{ env }}-a".into(), "b".into()])),
+            Ok(vec!["prod-a".to_string(), "b".to_string()])
+        );
+        assert_eq!(
+            items(ForEach::Source("repos".into())),
+            Ok(vec![
+                "api".to_string(),
+                "web".to_string(),
+                "docs".to_string()
+            ])
+        );
+        assert_eq!(
+            items(ForEach::Source("{{ services }}".into())),
+            Ok(vec!["auth".to_string(), "billing".to_string()])
+        );
+        assert_eq!(
+            items(ForEach::Source("{{ repos | upper }}".into())),
+            Ok(vec![
+                "API".to_string(),
+                "WEB".to_string(),
+                "DOCS".to_string()
+            ])
+        );
+        assert!(items(ForEach::Source("missing".into())).is_err());
     }
-    cmd.to_string()
 }
@@ -0,0 +1,550 @@
+// templates in the content, env, cwd and for_each of steps.
+//
+//   {{ name }}                    a param, an output of a previous step or a built-in
+//   {{ env.HOME }}                an environment variable
+//   {{ name | default('x') }}     filters: default('x') (when undefined or empty), upper, lower,
+//                                 trim, quote (single quotes for the shell)
+//   {% if os == 'macos' %} .. {% elif env.CI %} .. {% else %} .. {% endif %}
+//                                 the condition is a `when:` expression, see condition.rs
+//
+// built-ins: os, arch, package_dir, cwd, user, date (YYYY-MM-DD, utc).
+// an undefined variable is left as it is, unless the template is strict. `{{ }}` that isn't
+// an expression (like `docker inspect -f '{{.State}}'`) is always left as it is.
+// a tag alone on its line doesn't leave an empty line behind.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+
+use super::condition::{self, ConditionContext};
+use crate::core::utils::sys::OS;
+
+const BUILT_INS: &[&str] = &["os", "arch", "package_dir", "cwd", "user", "date"];
+
+pub struct TemplateContext<'a> {
+    pub os: OS,
+    // params, outputs of previous steps and the `for_each` item and index
+    pub vars: &'a HashMap<String, String>,
+    pub package_dir: Option<&'a Path>,
+    // an undefined variable is an error instead of being left as it is
+    pub strict: bool,
+}
+
+impl<'a> TemplateContext<'a> {
+    fn lookup(&self, name: &str) -> Option<String> {
+        if let Some(var) = name.strip_prefix("env.") {
+            return std::env::var(var).ok();
+        }
+        if let Some(value) = self.vars.get(name) {
+            return Some(value.clone());
+        }
+        match name {
+            "os" => Some(self.os.to_string().to_lowercase()),
+            "arch" => Some(std::env::consts::ARCH.to_string()),
+            "package_dir" => self.package_dir.map(|dir| dir.display().to_string()),
+            "cwd" => std::env::current_dir()
+                .ok()
+                .map(|dir| dir.display().to_string()),
+            "user" => std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .ok(),
+            "date" => Some(today()),
+            _ => None,
+        }
+    }
+
+    // what `{% if %}` conditions see, the built-ins included
+    fn condition_params(&self) -> HashMap<String, String> {
+        let mut params: HashMap<String, String> = BUILT_INS
+            .iter()
+            .filter_map(|name| self.lookup(name).map(|value| (name.to_string(), value)))
+            .collect();
+        params.extend(self.vars.clone());
+        params
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    // what's inside `{{ }}`, and the whole `{{ }}`
+    Expr(String, String),
+    // what's inside `{% %}`
+    Tag(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Expr(String, String),
+    // a condition and a body for the `if` and every `elif`, then the `else` body
+    If(Vec<(String, Vec<Node>)>, Vec<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Var(String),
+    Str(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Filter {
+    name: String,
+    args: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Expr {
+    operand: Operand,
+    filters: Vec<Filter>,
+}
+
+fn segments(template: &str) -> Result<Vec<Segment>> {
+    let mut segments = vec![];
+    let mut text = String::new();
+    // `text` starts at the beginning of a line
+    let mut line_start = true;
+    let mut rest = template;
+
+    loop {
+        let next = [("{{", "}}"), ("{%", "%}")]
+            .into_iter()
+            .filter_map(|(open, close)| rest.find(open).map(|start| (start, open, close)))
+            .min_by_key(|(start, _, _)| *start);
+        let Some((start, open, close)) = next else {
+            text.push_str(rest);
+            break;
+        };
+        text.push_str(&rest[..start]);
+
+        let after_open = &rest[start + 2..];
+        let Some(end) = after_open.find(close) else {
+            if open == "{%" {
+                bail!("`{{%` without a closing `%}}`");
+            }
+            // not a template expression
+            text.push_str(&rest[start..]);
+            break;
+        };
+        let inner = after_open[..end].trim().to_string();
+        let whole = rest[start..start + 2 + end + 2].to_string();
+        rest = &after_open[end + 2..];
+
+        if open == "{{" {
+            if !text.is_empty() {
+                segments.push(Segment::Text(std::mem::take(&mut text)));
+            }
+            segments.push(Segment::Expr(inner, whole));
+            line_start = false;
+            continue;
+        }
+
+        // drop the line of a tag that's alone on it
+        let line_begin = text.rfind('\n').map(|i| i + 1);
+        let alone_before = match line_begin {
+            Some(i) => text[i..].trim().is_empty(),
+            None => line_start && text.trim().is_empty(),
+        };
+        let line_end = rest.find('\n');
+        let alone_after = rest[..line_end.unwrap_or(rest.len())].trim().is_empty();
+        if alone_before && alone_after {
+            text.truncate(line_begin.unwrap_or(0));
+            rest = &rest[line_end.map_or(rest.len(), |i| i + 1)..];
+            line_start = true;
+        } else {
+            line_start = false;
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(std::mem::take(&mut text)));
+        }
+        segments.push(Segment::Tag(inner));
+    }
+
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    Ok(segments)
+}
+
+fn split_tag(tag: &str) -> (&str, &str) {
+    match tag.split_once(char::is_whitespace) {
+        Some((keyword, arg)) => (keyword, arg.trim()),
+        None => (tag, ""),
+    }
+}
+
+// the nodes up to the end of the template or to an `elif`/`else`/`endif` tag, which is returned
+fn parse_block(
+    segments: &mut impl Iterator<Item = Segment>,
+) -> Result<(Vec<Node>, Option<String>)> {
+    let mut nodes = vec![];
+    while let Some(segment) = segments.next() {
+        match segment {
+            Segment::Text(text) => nodes.push(Node::Text(text)),
+            Segment::Expr(inner, whole) => nodes.push(Node::Expr(inner, whole)),
+            Segment::Tag(tag) => match split_tag(&tag) {
+                ("if", condition) => nodes.push(parse_if(condition, segments)?),
+                ("elif" | "else" | "endif", _) => return Ok((nodes, Some(tag))),
+                _ => bail!("unknown tag `{{% {} %}}`", tag),
+            },
+        }
+    }
+    Ok((nodes, None))
+}
+
+fn parse_if(condition: &str, segments: &mut impl Iterator<Item = Segment>) -> Result<Node> {
+    if condition.is_empty() {
+        bail!("`{{% if %}}` without a condition");
+    }
+    let opening = condition;
+    let mut branches = vec![];
+    // None once the `else` is reached
+    let mut condition = Some(condition.to_string());
+    loop {
+        let (body, end) = parse_block(segments)?;
+        let Some(end) = end else {
+            bail!("`{{% if {} %}}` without an `{{% endif %}}`", opening);
+        };
+        let (keyword, arg) = split_tag(&end);
+        let Some(current) = condition.take() else {
+            if keyword != "endif" {
+                bail!("`{{% {} %}}` after `{{% else %}}`", end);
+            }
+            return Ok(Node::If(branches, body));
+        };
+        branches.push((current, body));
+        match keyword {
+            "elif" if arg.is_empty() => bail!("`{{% elif %}}` without a condition"),
+            "elif" => condition = Some(arg.to_string()),
+            "else" => {}
+            _ => return Ok(Node::If(branches, vec![])),
+        }
+    }
+}
+
+fn parse(template: &str) -> Result<Vec<Node>> {
+    let mut segments = segments(template)?.into_iter();
+    match parse_block(&mut segments)? {
+        (nodes, None) => Ok(nodes),
+        (_, Some(tag)) => bail!("`{{% {} %}}` without an `{{% if %}}`", tag),
+    }
+}
+
+// split on `separator`, but not inside quotes
+fn split_unquoted(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, c) if c == separator => {
+                parts.push(&text[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+fn unquote(text: &str) -> Option<&str> {
+    let text = text.trim();
+    ['\'', '"'].into_iter().find_map(|quote| {
+        text.strip_prefix(quote)
+            .and_then(|rest| rest.strip_suffix(quote))
+    })
+}
+
+fn parse_filter(text: &str) -> Result<Filter> {
+    let text = text.trim();
+    let (name, args) = match text.split_once('(') {
+        Some((name, args)) => match args.trim_end().strip_suffix(')') {
+            Some(args) if args.trim().is_empty() => (name.trim(), vec![]),
+            Some(args) => (
+                name.trim(),
+                split_unquoted(args, ',')
+                    .into_iter()
+                    .map(|arg| unquote(arg).unwrap_or(arg.trim()).to_string())
+                    .collect(),
+            ),
+            None => bail!("missing `)` in filter `{}`", text),
+        },
+        None => (text, vec![]),
+    };
+
+    let expected_args = match name {
+        "default" => 1,
+        "upper" | "lower" | "trim" | "quote" => 0,
+        _ => bail!("unknown filter `{}`", name),
+    };
+    if args.len() != expected_args {
+        bail!("filter `{}` takes {} argument(s)", name, expected_args);
+    }
+    Ok(Filter {
+        name: name.to_string(),
+        args,
+    })
+}
+
+// None when it isn't an expression at all, like the `{{.State}}` of a go template
+fn parse_expr(inner: &str) -> Result<Option<Expr>> {
+    let parts = split_unquoted(inner, '|');
+    let first = parts[0].trim();
+    let operand = match unquote(first) {
+        Some(value) => Operand::Str(value.to_string()),
+        None if !first.is_empty()
+            && !first.starts_with('.')
+            && first
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '.') =>
+        {
+            Operand::Var(first.to_string())
+        }
+        None => return Ok(None),
+    };
+
+    let filters = parts[1..]
+        .iter()
+        .map(|filter| parse_filter(filter))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Some(Expr { operand, filters }))
+}
+
+// single quotes, for sh-like shells
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+impl Expr {
+    // None when it's undefined
+    fn evaluate(&self, ctx: &TemplateContext) -> Option<String> {
+        let mut value = match &self.operand {
+            Operand::Var(name) => ctx.lookup(name),
+            Operand::Str(value) => Some(value.clone()),
+        };
+        for filter in &self.filters {
+            value = match filter.name.as_str() {
+                "default" => Some(
+                    value
+                        .filter(|value| !value.is_empty())
+                        .unwrap_or_else(|| filter.args[0].clone()),
+                ),
+                "upper" => value.map(|value| value.to_uppercase()),
+                "lower" => value.map(|value| value.to_lowercase()),
+                "trim" => value.map(|value| value.trim().to_string()),
+                "quote" => value.map(|value| shell_quote(&value)),
+                _ => unreachable!(),
+            };
+        }
+        value
+    }
+}
+
+fn render_nodes(nodes: &[Node], ctx: &TemplateContext, output: &mut String) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Expr(inner, whole) => {
+                let Some(expr) = parse_expr(inner)? else {
+                    output.push_str(whole);
+                    continue;
+                };
+                match expr.evaluate(ctx) {
+                    Some(value) => output.push_str(&value),
+                    None if ctx.strict => bail!("`{}` is not defined", whole),
+                    None => output.push_str(whole),
+                }
+            }
+            Node::If(branches, otherwise) => {
+                let params = ctx.condition_params();
+                let condition_ctx = ConditionContext {
+                    os: ctx.os,
+                    params: &params,
+                    steps: &[],
+                };
+                let mut body = otherwise;
+                for (condition, branch) in branches {
+                    if condition::evaluate(condition, &condition_ctx)
+                        .with_context(|| format!("invalid condition `{}`", condition))?
+                    {
+                        body = branch;
+                        break;
+                    }
+                }
+                render_nodes(body, ctx, output)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn render(template: &str, ctx: &TemplateContext) -> Result<String> {
+    if !template.contains("{{") && !template.contains("{%") {
+        return Ok(template.to_string());
+    }
+    let mut output = String::new();
+    render_nodes(&parse(template)?, ctx, &mut output)?;
+    Ok(output)
+}
+
+fn collect_references(nodes: &[Node], references: &mut Vec<(String, String)>) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Text(_) => {}
+            Node::Expr(inner, whole) => {
+                let Some(expr) = parse_expr(inner)? else {
+                    continue;
+                };
+                if let Operand::Var(name) = &expr.operand {
+                    let needed = !name.starts_with("env.")
+                        && !BUILT_INS.contains(&name.as_str())
+                        && !expr.filters.iter().any(|filter| filter.name == "default");
+                    if needed {
+                        references.push((name.clone(), whole.clone()));
+                    }
+                }
+            }
+            Node::If(branches, otherwise) => {
+                for (_, body) in branches {
+                    collect_references(body, references)?;
+                }
+                collect_references(otherwise, references)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// the params and outputs a template needs, as (name, the whole `{{ }}`). env lookups, built-ins
+// and variables with a default aren't needed
+pub fn references(template: &str) -> Result<Vec<(String, String)>> {
+    let mut references = vec![];
+    collect_references(&parse(template)?, &mut references)?;
+    Ok(references)
+}
+
+// YYYY-MM-DD of a number of days since 1970-01-01 (howard hinnant's `civil_from_days`)
+fn civil_date(days: i64) -> String {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn today() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    civil_date((seconds / 86400) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn render_with(template: &str, strict: bool) -> Result<String> {
+        let vars = HashMap::from([
+            ("name".to_string(), "it's me".to_string()),
+            ("empty".to_string(), "".to_string()),
+            ("build.exit_code".to_string(), "0".to_string()),
+        ]);
+        let package_dir = PathBuf::from("/pkgs/demo");
+        let ctx = TemplateContext {
+            os: OS::Linux,
+            vars: &vars,
+            package_dir: Some(&package_dir),
+            strict,
+        };
+        render(template, &ctx)
+    }
+
+    #[test]
+    fn test_render() {
+        let render = |template: &str| render_with(template, false).unwrap();
+
+        assert_eq!(
+            render("echo {{name}} {{ build.exit_code }}"),
+            "echo it's me 0"
+        );
+        assert_eq!(render("echo {{ name | quote }}"), r"echo 'it'\''s me'");
+        assert_eq!(render("{{ name | upper }} {{ 'A' | lower }}"), "IT'S ME a");
+        assert_eq!(
+            render("{{ missing | default('x, y') }} {{ empty | default(\"z\") | upper }}"),
+            "x, y Z"
+        );
+        assert_eq!(
+            render("{{ os }} {{ package_dir }}/bin"),
+            "linux /pkgs/demo/bin"
+        );
+        assert_eq!(
+            render("{{ env.COCMD_TEMPLATE_TEST }}"),
+            "{{ env.COCMD_TEMPLATE_TEST }}"
+        );
+        assert_eq!(
+            render("{{ missing }} {{.State}} {{ "),
+            "{{ missing }} {{.State}} {{ "
+        );
+        assert_eq!(render("no templates"), "no templates");
+    }
+
+    #[test]
+    fn test_render_if() {
+        let render = |template: &str| render_with(template, false).unwrap();
+
+        let template = "start\n  {% if os == 'macos' %}\nbrew\n  {% elif name =~ 'me' %}\napt {{ name }}\n{% else %}\nnone\n{% endif %}\nend";
+        assert_eq!(render(template), "start\napt it's me\nend");
+        assert_eq!(render("a {% if empty %}x{% else %}y{% endif %} b"), "a y b");
+        assert_eq!(
+            render("{% if arch %}{% if not empty %}nested{% endif %}{% endif %}"),
+            "nested"
+        );
+    }
+
+    #[test]
+    fn test_render_errors() {
+        assert!(render_with("{{ missing }}", true).is_err());
+        assert_eq!(
+            render_with("{{ missing | default('x') }}", true).unwrap(),
+            "x"
+        );
+        assert!(render_with("{{ name | shout }}", false).is_err());
+        assert!(render_with("{{ name | default }}", false).is_err());
+        assert!(render_with("{% if os %}x", false).is_err());
+        assert!(render_with("{% else %}", false).is_err());
+        assert!(render_with("{% if os %}a{% else %}b{% elif os %}c{% endif %}", false).is_err());
+        assert!(render_with("{% for x in y %}{% endfor %}", false).is_err());
+        assert!(render_with("{% if os == %}x{% endif %}", false).is_err());
+    }
+
+    #[test]
+    fn test_references() {
+        assert_eq!(
+            references("{{ a }} {{ env.X }} {{ os }} {{ b | default('') }} {% if x %}{{ c.stdout | quote }}{% endif %}").unwrap(),
+            vec![
+                ("a".to_string(), "{{ a }}".to_string()),
+                ("c.stdout".to_string(), "{{ c.stdout | quote }}".to_string()),
+            ]
+        );
+        assert!(references("{% if x %}").is_err());
+    }
+
+    #[test]
+    fn test_civil_date() {
+        assert_eq!(civil_date(0), "1970-01-01");
+        assert_eq!(civil_date(11016), "2000-02-29");
+        assert_eq!(civil_date(19723), "2024-01-01");
+    }
+}
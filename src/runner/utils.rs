@@ -1,18 +1,81 @@
-use std::process::Command;
+use std::io::Read;
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
 pub fn check_installed(cmd: &str) -> bool {
-    let output = Command::new(cmd).output();
+    match Command::new(cmd).output() {
+        Ok(output) => output.status.success(),
+        Err(_) => false,
+    }
+}
+
+// wait for the child to exit. if it runs longer than timeout it's killed and None is returned
+pub fn wait_with_timeout(
+    child: &mut Child,
+    timeout: Option<Duration>,
+) -> std::io::Result<Option<ExitStatus>> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return child.wait().map(Some),
+    };
 
-    if output.is_err() {
-        return false;
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if started.elapsed() >= timeout {
+            kill(child)?;
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(50));
     }
+}
+
+// kill the child and (best effort) the processes it started, e.g. the command a shell is running
+fn kill(child: &mut Child) -> std::io::Result<()> {
+    #[cfg(unix)]
+    let _ = Command::new("pkill")
+        .arg("-KILL")
+        .arg("-P")
+        .arg(child.id().to_string())
+        .status();
+    child.kill()?;
+    child.wait()?;
+    Ok(())
+}
 
-    let success = output.unwrap().status.success();
+// like Command::output, but gives up after timeout. returns None when the command timed out
+pub fn output_with_timeout(
+    command: &mut Command,
+    timeout: Option<Duration>,
+) -> std::io::Result<Option<Output>> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
 
-    if success {
-        return true;
-    } else {
-        return false;
+    // drain the pipes in the background so a chatty child doesn't block on a full pipe
+    let mut stdout_pipe = child.stdout.take().unwrap();
+    let mut stderr_pipe = child.stderr.take().unwrap();
+    let stdout_reader = thread::spawn(move || {
+        let mut buf = vec![];
+        stdout_pipe.read_to_end(&mut buf).map(|_| buf)
+    });
+    let stderr_reader = thread::spawn(move || {
+        let mut buf = vec![];
+        stderr_pipe.read_to_end(&mut buf).map(|_| buf)
+    });
+
+    match wait_with_timeout(&mut child, timeout)? {
+        Some(status) => Ok(Some(Output {
+            status,
+            stdout: stdout_reader.join().unwrap()?,
+            stderr: stderr_reader.join().unwrap()?,
+        })),
+        // don't wait for the readers, a leftover grandchild may still hold the pipes open
+        None => Ok(None),
     }
 }
 
@@ -26,14 +89,36 @@ mod tests {
     #[cfg(any(target_os = "linux", target_os = "macos"))]
     #[test]
     fn test_check_installed() {
-        assert_eq!(check_installed("ls"), true);
-        assert_eq!(check_installed("dummy_command"), false);
+        assert!(check_installed("ls"));
+        assert!(!check_installed("dummy_command"));
     }
 
-    #[cfg(any(target_os = "windows"))]
+    #[cfg(target_os = "windows")]
     #[test]
     fn test_check_installed() {
-        assert_eq!(check_installed("dir"), true);
-        assert_eq!(check_installed("dummy_command"), false);
+        assert!(check_installed("dir"));
+        assert!(!check_installed("dummy_command"));
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn test_output_with_timeout() {
+        let output = output_with_timeout(
+            Command::new("sh").arg("-c").arg("echo hi"),
+            Some(Duration::from_secs(5)),
+        )
+        .unwrap()
+        .unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "hi\n");
+
+        let started = Instant::now();
+        let output = output_with_timeout(
+            Command::new("sh").arg("-c").arg("sleep 5"),
+            Some(Duration::from_millis(200)),
+        )
+        .unwrap();
+        assert!(output.is_none());
+        assert!(started.elapsed() < Duration::from_secs(5));
     }
 }
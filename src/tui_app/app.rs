@@ -30,7 +30,13 @@ pub struct App {
 impl App {
     /// Constructs a new instance of [`App`].
     pub fn new(packages_manager: PackagesManager) -> Self {
-        let items = packages_manager.packages.values().cloned().collect();
+        // the project we stand in comes first
+        let items = packages_manager
+            .project
+            .iter()
+            .chain(packages_manager.packages.values())
+            .cloned()
+            .collect();
         Self {
             focus: AppFocus::Packages,
             running: true,
@@ -3,7 +3,7 @@ use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 
-use crossterm::event::{self, Event as CrosstermEvent, KeyEvent, MouseEvent};
+use crossterm::event::{self, Event as CrosstermEvent, KeyEvent};
 
 use super::app::AppResult;
 
@@ -15,19 +15,16 @@ pub enum Event {
     /// Key press.
     Key(KeyEvent),
     /// Mouse click/scroll.
-    Mouse(MouseEvent),
+    Mouse,
     /// Terminal resize.
-    Resize(u16, u16),
+    Resize,
     /// Termination event to stop the main loop.
     Terminate,
 }
 
 /// Terminal event handler.
-#[allow(dead_code)]
-
 pub struct EventHandler {
     sender: mpsc::Sender<Event>,
-    handler: thread::JoinHandle<()>,
     receiver: Arc<Mutex<mpsc::Receiver<Event>>>,
 }
 
@@ -38,7 +35,8 @@ impl EventHandler {
         let (sender, receiver) = mpsc::channel();
         let receiver = Arc::new(Mutex::new(receiver));
 
-        let handler = {
+        // the thread stops on its own once it reads the termination event
+        {
             let sender = sender.clone();
             let receiver = Arc::clone(&receiver);
             thread::spawn(move || {
@@ -51,8 +49,8 @@ impl EventHandler {
                     if event::poll(timeout).expect("no events available") {
                         match event::read().expect("unable to read event") {
                             CrosstermEvent::Key(e) => sender.send(Event::Key(e)),
-                            CrosstermEvent::Mouse(e) => sender.send(Event::Mouse(e)),
-                            CrosstermEvent::Resize(w, h) => sender.send(Event::Resize(w, h)),
+                            CrosstermEvent::Mouse(_) => sender.send(Event::Mouse),
+                            CrosstermEvent::Resize(_, _) => sender.send(Event::Resize),
                             _ => unimplemented!(),
                         }
                         .expect("failed to send terminal event")
@@ -68,13 +66,9 @@ impl EventHandler {
                         break; // Break the loop to stop event handling.
                     }
                 }
-            })
-        };
-        Self {
-            sender,
-            handler,
-            receiver,
+            });
         }
+        Self { sender, receiver }
     }
 
     /// Receive the next event from the handler thread.
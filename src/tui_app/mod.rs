@@ -64,8 +64,8 @@ pub fn tui_runner(packages_manager: PackagesManager) -> AppResult<Option<String>
         match tui.events.next()? {
             Event::Tick => app.tick(),
             Event::Key(key_event) => handle_key_events(key_event, &mut app)?,
-            Event::Mouse(_) => {}
-            Event::Resize(_, _) => {}
+            Event::Mouse => {}
+            Event::Resize => {}
             Event::Terminate => {
                 tui.exit()?;
                 return Ok(None);